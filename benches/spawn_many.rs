@@ -0,0 +1,51 @@
+//! Spawns many short-lived children at once and waits for all of them.
+//!
+//! When children are reaped via SIGCHLD, every signal wakes up every child
+//! still waiting on one, making this a worst case for the number of
+//! `waitpid` calls issued. Run with `cargo bench` (optionally under
+//! `strace -f -c -e trace=wait4`) to compare.
+
+extern crate futures;
+extern crate tokio;
+extern crate tokio_process;
+
+#[cfg(unix)]
+fn spawn_many(children: usize) {
+    use futures::future;
+    use std::process::{Command, Stdio};
+    use tokio_process::CommandExt;
+
+    let children = (0..children).map(|_| {
+        let child = Command::new("true")
+            .stdout(Stdio::piped())
+            .spawn_async()
+            .expect("failed to spawn");
+
+        // Reading stdout wakes up the task without any SIGCHLD arriving,
+        // which should not result in any additional `waitpid` calls.
+        child.wait_with_output()
+    }).collect::<Vec<_>>();
+
+    let outputs = tokio::runtime::current_thread::Runtime::new()
+        .expect("failed to create runtime")
+        .block_on(future::join_all(children))
+        .expect("failed to wait");
+
+    assert!(outputs.iter().all(|output| output.status.success()));
+}
+
+#[cfg(unix)]
+fn main() {
+    use std::time::Instant;
+
+    const CHILDREN: usize = 500;
+
+    for round in 0..5 {
+        let start = Instant::now();
+        spawn_many(CHILDREN);
+        println!("round {}: {} children in {:?}", round, CHILDREN, start.elapsed());
+    }
+}
+
+#[cfg(not(unix))]
+fn main() {}
@@ -0,0 +1,132 @@
+use futures::{Async, Poll};
+use std::cmp;
+use std::io;
+use std::mem;
+use tokio_io::AsyncRead;
+
+/// Incrementally reads a stream to EOF, retaining everything read so far so
+/// that it can be recovered even if reading is abandoned part way through.
+#[derive(Debug)]
+pub(crate) struct Capture<R> {
+    reader: Option<R>,
+    buf: Vec<u8>,
+    limit: Option<usize>,
+    // If set, only this many bytes at the end of the stream are retained
+    tail: Option<usize>,
+    truncated: bool,
+}
+
+impl<R: AsyncRead> Capture<R> {
+    pub(crate) fn new(reader: Option<R>) -> Self {
+        Self {
+            reader,
+            buf: Vec::new(),
+            limit: None,
+            tail: None,
+            truncated: false,
+        }
+    }
+
+    /// Creates a capture which stops reading once more than `limit` bytes
+    /// have been produced, retaining only the first `limit` bytes.
+    pub(crate) fn with_limit(reader: Option<R>, limit: usize) -> Self {
+        Self {
+            limit: Some(limit),
+            ..Self::new(reader)
+        }
+    }
+
+    /// Creates a capture which reads the whole stream, but only retains the
+    /// last `tail` bytes.
+    pub(crate) fn with_tail(reader: Option<R>, tail: usize) -> Self {
+        Self {
+            tail: Some(tail),
+            ..Self::new(reader)
+        }
+    }
+
+    /// Whether the stream produced more data than the capture's limit.
+    pub(crate) fn is_truncated(&self) -> bool {
+        self.truncated
+    }
+
+    /// Reads as much data as is available, resolving once EOF is reached or
+    /// the limit of the capture has been exceeded.
+    ///
+    /// The reader is dropped (and thus closed) as soon as either condition is
+    /// observed. Closing the reader early ensures the writing end can never
+    /// block on a full pipe which nobody will ever drain.
+    pub(crate) fn poll_capture(&mut self) -> Poll<(), io::Error> {
+        self.poll_capture_within(None)
+    }
+
+    /// Like `poll_capture`, but additionally treats running out of `budget`
+    /// like exceeding the limit of the capture, reducing the budget by the
+    /// amount of data read.
+    ///
+    /// This allows several captures to share a single limit.
+    pub(crate) fn poll_capture_within(&mut self, mut budget: Option<&mut usize>) -> Poll<(), io::Error> {
+        let mut chunk = [0; 8 * 1024];
+
+        loop {
+            let n = match self.reader {
+                Some(ref mut reader) => try_ready!(reader.poll_read(&mut chunk)),
+                None => return Ok(Async::Ready(())),
+            };
+
+            if n == 0 {
+                self.reader = None;
+                return Ok(Async::Ready(()));
+            }
+
+            let remaining = match (self.limit, budget.as_ref()) {
+                (Some(limit), Some(budget)) => Some(cmp::min(limit - self.buf.len(), **budget)),
+                (Some(limit), None) => Some(limit - self.buf.len()),
+                (None, Some(budget)) => Some(**budget),
+                (None, None) => None,
+            };
+            let (n, truncated) = match remaining {
+                Some(remaining) if n > remaining => (remaining, true),
+                _ => (n, false),
+            };
+
+            self.buf.extend_from_slice(&chunk[..n]);
+            if let Some(tail) = self.tail {
+                // Only discard once enough has piled up to amortize moving
+                // the tail to the front
+                if self.buf.len() > tail.saturating_mul(2) {
+                    self.trim_to_tail(tail);
+                }
+            }
+            if let Some(ref mut budget) = budget {
+                **budget -= n;
+            }
+
+            if truncated {
+                self.truncated = true;
+                self.reader = None;
+                return Ok(Async::Ready(()));
+            }
+        }
+    }
+
+    /// Stops reading, keeping everything captured so far.
+    pub(crate) fn close(&mut self) {
+        self.reader = None;
+    }
+
+    fn trim_to_tail(&mut self, tail: usize) {
+        if self.buf.len() > tail {
+            let excess = self.buf.len() - tail;
+            self.buf.drain(..excess);
+        }
+    }
+
+    /// Takes all data captured so far.
+    pub(crate) fn take(&mut self) -> Vec<u8> {
+        if let Some(tail) = self.tail {
+            self.trim_to_tail(tail);
+        }
+        mem::take(&mut self.buf)
+    }
+}
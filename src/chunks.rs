@@ -0,0 +1,47 @@
+use bytes::{Bytes, BytesMut};
+use futures::{Async, Poll, Stream};
+use std::io;
+use tokio_io::AsyncRead;
+
+/// A stream over the raw data written by a child process, in chunks of up to
+/// a fixed size.
+///
+/// This type is created by the `Child::stdout_chunks` method. Each item holds
+/// whatever data was available to be read at the time, but never more than
+/// the configured chunk size, and the stream ends once the child closes its
+/// end of the pipe.
+#[derive(Debug)]
+pub struct Chunks<R> {
+    reader: R,
+    buf: BytesMut,
+    chunk_size: usize,
+}
+
+impl<R: AsyncRead> Chunks<R> {
+    pub(crate) fn new(reader: R, chunk_size: usize) -> Self {
+        assert!(chunk_size > 0, "chunk size must be greater than zero");
+
+        Self {
+            reader,
+            buf: BytesMut::new(),
+            chunk_size,
+        }
+    }
+}
+
+impl<R: AsyncRead> Stream for Chunks<R> {
+    type Item = Bytes;
+    type Error = io::Error;
+
+    fn poll(&mut self) -> Poll<Option<Bytes>, io::Error> {
+        // Any part of the buffer left over from the previous chunk is reused
+        self.buf.resize(self.chunk_size, 0);
+
+        let n = try_nb!(self.reader.read(&mut self.buf));
+        if n == 0 {
+            return Ok(Async::Ready(None));
+        }
+
+        Ok(Async::Ready(Some(self.buf.split_to(n).freeze())))
+    }
+}
@@ -0,0 +1,75 @@
+use capture::Capture;
+use futures::{Async, Future, Poll};
+use std::io;
+use std::time::{Duration, Instant};
+use tokio_timer::Delay;
+use super::{Child, ChildStderr};
+
+/// Future returned from the `Child::kill_and_collect_stderr` method.
+///
+/// This future will resolve to everything the child wrote to its stderr once
+/// it has been killed and either its stderr has been closed or the timeout
+/// has elapsed.
+#[must_use = "futures do nothing unless polled"]
+#[derive(Debug)]
+pub struct KillAndCollectStderr<'a> {
+    child: &'a mut Child,
+    stderr: Capture<ChildStderr>,
+    timeout: Duration,
+    // Only started once we're first polled, i.e. once the child is killed
+    delay: Option<Delay>,
+    killed: bool,
+    exited: bool,
+}
+
+impl<'a> KillAndCollectStderr<'a> {
+    pub(crate) fn new(child: &'a mut Child, timeout: Duration) -> Self {
+        let stderr = Capture::new(child.stderr().take());
+
+        Self {
+            child,
+            stderr,
+            timeout,
+            delay: None,
+            killed: false,
+            exited: false,
+        }
+    }
+}
+
+impl<'a> Future for KillAndCollectStderr<'a> {
+    type Item = Vec<u8>;
+    type Error = io::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        if !self.exited {
+            self.exited = self.child.poll()?.is_ready();
+        }
+
+        // Never signal a child which has already been reaped
+        if !self.killed && !self.exited {
+            if let Err(e) = self.child.kill() {
+                // The child may have exited on its own right after we last
+                // checked on it, which isn't an error.
+                if self.child.poll()?.is_not_ready() {
+                    return Err(e);
+                }
+            }
+
+            self.killed = true;
+            self.exited = self.child.poll()?.is_ready();
+        }
+
+        let eof = self.stderr.poll_capture()?.is_ready();
+        if eof && self.exited {
+            return Ok(Async::Ready(self.stderr.take()));
+        }
+
+        // The stderr of the child may have been inherited by a process which
+        // is still running, in which case whatever was read so far has to do.
+        let timeout = self.timeout;
+        let delay = self.delay.get_or_insert_with(|| Delay::new(Instant::now() + timeout));
+        try_ready!(delay.poll().map_err(io::Error::other));
+        Ok(Async::Ready(self.stderr.take()))
+    }
+}
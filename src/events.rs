@@ -0,0 +1,63 @@
+use futures::{Async, Poll, Stream};
+use std::io;
+use std::process::ExitStatus;
+use super::Child;
+
+/// A change in the state of a child process.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChildEvent {
+    /// The child was stopped by the contained signal (e.g. `SIGSTOP` or
+    /// `SIGTSTP`).
+    ///
+    /// This is only ever reported for children spawned through a
+    /// `SpawnHandle` with `track_job_control` enabled.
+    Stopped(i32),
+    /// The child was resumed by a `SIGCONT` after having been stopped.
+    ///
+    /// This is only ever reported for children spawned through a
+    /// `SpawnHandle` with `track_job_control` enabled.
+    Continued,
+    /// The child exited, and has been reaped.
+    Exited(ExitStatus),
+}
+
+/// A stream of the state changes of a child process.
+///
+/// This type is created by the `Child::status_stream` method. The last item
+/// of the stream is always `ChildEvent::Exited`.
+#[must_use = "streams do nothing unless polled"]
+#[derive(Debug)]
+pub struct StatusStream<'a> {
+    child: &'a mut Child,
+    done: bool,
+}
+
+impl<'a> StatusStream<'a> {
+    pub(crate) fn new(child: &'a mut Child) -> Self {
+        Self {
+            child,
+            done: false,
+        }
+    }
+}
+
+impl<'a> Stream for StatusStream<'a> {
+    type Item = ChildEvent;
+    type Error = io::Error;
+
+    fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        if self.done {
+            return Ok(Async::Ready(None));
+        }
+
+        let event = try_ready!(self.child.child.inner.poll_event());
+        if let ChildEvent::Exited(status) = event {
+            // Avoid the overhead of trying to kill a reaped process
+            self.child.child.kill_on_drop = false;
+            self.child.exit_watch.publish(status);
+            self.done = true;
+        }
+
+        Ok(Async::Ready(Some(event)))
+    }
+}
@@ -0,0 +1,169 @@
+use futures::{Async, Future, Poll};
+use std::fmt;
+use std::io;
+use std::process::ExitStatus;
+use super::Child;
+
+/// A platform independent description of how a process exited.
+///
+/// This can be obtained from the `ExitStatus` a `Child` resolves to via
+/// `ProcessExit::from` (e.g. with `child.map(ProcessExit::from)`), without
+/// needing to reach for any platform specific extension traits.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ProcessExit {
+    /// The process exited on its own with the given exit code.
+    Exited(i32),
+    /// The process was terminated by a signal. This only happens on Unix.
+    Signaled {
+        /// The signal which terminated the process.
+        signal: i32,
+        /// Whether the process produced a core dump when it terminated.
+        core_dumped: bool,
+    },
+    /// The process neither exited nor was terminated, with the given raw wait
+    /// status. This only happens on Unix, for a status describing a stopped
+    /// or continued process (e.g. one built with `ExitStatusExt::from_raw`),
+    /// which a `Child` never resolves to itself.
+    Other(i32),
+}
+
+impl ProcessExit {
+    /// Returns whether the process exited successfully.
+    pub fn success(&self) -> bool {
+        *self == ProcessExit::Exited(0)
+    }
+
+    /// Returns whether the process produced a core dump when it terminated.
+    ///
+    /// This is always `false` on Windows, or for processes which exited on
+    /// their own.
+    pub fn core_dumped(&self) -> bool {
+        match *self {
+            ProcessExit::Exited(_) | ProcessExit::Other(_) => false,
+            ProcessExit::Signaled { core_dumped, .. } => core_dumped,
+        }
+    }
+}
+
+impl fmt::Display for ProcessExit {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ProcessExit::Exited(code) => write!(fmt, "exited with code {}", code),
+            ProcessExit::Signaled { signal, core_dumped: false } => {
+                write!(fmt, "was terminated by signal {}", signal)
+            },
+            ProcessExit::Signaled { signal, core_dumped: true } => {
+                write!(fmt, "was terminated by signal {} (core dumped)", signal)
+            },
+            ProcessExit::Other(status) => write!(fmt, "has wait status {:#x}", status),
+        }
+    }
+}
+
+/// A classification of why a process exited, as returned by
+/// `Child::exit_reason`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ExitReason {
+    /// The process exited on its own with the given exit code.
+    Exited(i32),
+    /// The process was killed through its `Child`, e.g. via `Child::kill`.
+    Killed,
+    /// The process was most likely killed for running out of memory.
+    ///
+    /// This is only reported on Linux, for a process killed by a `SIGKILL`
+    /// which was not sent through its `Child`, and only if the OOM killer
+    /// killed something in the cgroup of the current process while the child
+    /// was running (according to the `oom_kill` count of its cgroup v2
+    /// `memory.events`). A `SIGKILL` without such evidence is reported as
+    /// `Signaled`.
+    OutOfMemory,
+    /// The process was terminated by the given signal. This only happens on
+    /// Unix.
+    Signaled(i32),
+    /// The process neither exited nor was terminated, with the given raw wait
+    /// status. See `ProcessExit::Other`.
+    Other(i32),
+}
+
+/// The signal used to kill processes, which is the same on all Unix platforms.
+#[cfg(unix)]
+const SIGKILL: i32 = 9;
+
+/// The exit code Windows processes are given when killed.
+#[cfg(windows)]
+const KILLED_EXIT_CODE: i32 = 1;
+
+impl ExitReason {
+    #[cfg_attr(not(target_os = "linux"), allow(unused_variables))]
+    pub(crate) fn new(status: ExitStatus, killed: bool, oom_killed: bool) -> Self {
+        match ProcessExit::from(status) {
+            #[cfg(windows)]
+            ProcessExit::Exited(KILLED_EXIT_CODE) if killed => ExitReason::Killed,
+            ProcessExit::Exited(code) => ExitReason::Exited(code),
+
+            #[cfg(unix)]
+            ProcessExit::Signaled { signal: SIGKILL, .. } if killed => ExitReason::Killed,
+            #[cfg(target_os = "linux")]
+            ProcessExit::Signaled { signal: SIGKILL, .. } if oom_killed => ExitReason::OutOfMemory,
+            ProcessExit::Signaled { signal, .. } => ExitReason::Signaled(signal),
+            ProcessExit::Other(status) => ExitReason::Other(status),
+        }
+    }
+}
+
+impl From<ExitStatus> for ProcessExit {
+    #[cfg(unix)]
+    fn from(status: ExitStatus) -> Self {
+        use std::os::unix::process::ExitStatusExt;
+
+        if let Some(code) = status.code() {
+            return ProcessExit::Exited(code);
+        }
+
+        // Statuses of stopped or continued children can be turned into an
+        // `ExitStatus` too (e.g. through `ExitStatusExt::from_raw`), so they
+        // must not be mistaken for a signal.
+        match status.signal() {
+            Some(signal) => ProcessExit::Signaled {
+                signal,
+                core_dumped: status.core_dumped(),
+            },
+            None => ProcessExit::Other(status.into_raw()),
+        }
+    }
+
+    #[cfg(windows)]
+    fn from(status: ExitStatus) -> Self {
+        ProcessExit::Exited(status.code().expect("windows exit statuses always have a code"))
+    }
+}
+
+/// Future returned from the `Child::success` method.
+///
+/// This future resolves to `()` once the child has exited successfully, and
+/// fails with an error describing how the child exited otherwise.
+#[must_use = "futures do nothing unless polled"]
+#[derive(Debug)]
+pub struct Success {
+    child: Child,
+}
+
+impl Success {
+    pub(crate) fn new(child: Child) -> Self {
+        Self { child }
+    }
+}
+
+impl Future for Success {
+    type Item = ();
+    type Error = io::Error;
+
+    fn poll(&mut self) -> Poll<(), io::Error> {
+        let exit = ProcessExit::from(try_ready!(self.child.poll()));
+        if exit.success() {
+            Ok(Async::Ready(()))
+        } else {
+            Err(io::Error::other(format!("process {}", exit)))
+        }
+    }
+}
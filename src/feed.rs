@@ -0,0 +1,72 @@
+use futures::{Async, Future, Poll, Stream};
+use std::io;
+use tokio_io::AsyncWrite;
+use super::{no_stdin, ChildStdin};
+
+/// Future returned from the `Child::feed_stdin` method.
+///
+/// This future writes every chunk produced by its source to the child's
+/// stdin, and resolves once the source is exhausted and stdin has been
+/// closed.
+#[must_use = "futures do nothing unless polled"]
+#[derive(Debug)]
+pub struct FeedStdin<S: Stream> {
+    stdin: Option<ChildStdin>,
+    source: S,
+    chunk: Option<S::Item>,
+    written: usize,
+}
+
+impl<S: Stream> FeedStdin<S> {
+    pub(crate) fn new(stdin: Option<ChildStdin>, source: S) -> Self {
+        Self {
+            stdin,
+            source,
+            chunk: None,
+            written: 0,
+        }
+    }
+}
+
+impl<S> Future for FeedStdin<S>
+    where S: Stream<Error = io::Error>,
+          S::Item: AsRef<[u8]>,
+{
+    type Item = ();
+    type Error = io::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        loop {
+            if let Some(ref chunk) = self.chunk {
+                let chunk = chunk.as_ref();
+
+                while self.written < chunk.len() {
+                    let stdin = self.stdin.as_mut().ok_or_else(no_stdin)?;
+                    let n = try_ready!(stdin.poll_write(&chunk[self.written..]));
+                    if n == 0 {
+                        return Err(io::Error::new(
+                            io::ErrorKind::WriteZero,
+                            "failed to write chunk to child stdin",
+                        ));
+                    }
+
+                    self.written += n;
+                }
+            }
+
+            self.chunk = None;
+            self.written = 0;
+
+            match try_ready!(self.source.poll()) {
+                Some(chunk) => self.chunk = Some(chunk),
+                None => break,
+            }
+        }
+
+        try_ready!(self.stdin.as_mut().ok_or_else(no_stdin)?.poll_flush());
+
+        // Close stdin so that the child sees EOF
+        self.stdin = None;
+        Ok(Async::Ready(()))
+    }
+}
@@ -144,6 +144,23 @@
 //! }
 //! ```
 //!
+//! # Interoperability
+//!
+//! The stdio handles of a child (`ChildStdin`, `ChildStdout` and
+//! `ChildStderr`) implement the `AsyncRead` and `AsyncWrite` traits of
+//! `tokio-io` 0.1, which are the same traits as those re-exported by
+//! `tokio::io` in tokio 0.1, as well as the standard library's `Read` and
+//! `Write` traits. They can thus be used with any of the utilities in
+//! `tokio::io` directly.
+//!
+//! The handles do not implement the `AsyncRead` and `AsyncWrite` traits of
+//! `futures-io` 0.3 or tokio 0.2, since those are built on an incompatible
+//! version of futures. Code on either of those can bridge the gap with the
+//! compatibility layer of the `futures` 0.3 crate (enabled by its `compat`
+//! feature): `futures::compat::AsyncRead01CompatExt::compat` adapts a
+//! handle to the `futures-io` traits, and `tokio-util`'s `compat` module
+//! adapts those further to tokio's own traits if need be.
+//!
 //! # Caveats
 //!
 //! While similar to the standard library, this crate's `Child` type differs
@@ -158,24 +175,37 @@
 #![deny(missing_docs)]
 #![doc(html_root_url = "https://docs.rs/tokio-process/0.2")]
 
+extern crate bytes;
+#[macro_use]
 extern crate futures;
+#[macro_use]
 extern crate tokio_io;
 extern crate tokio_reactor;
+extern crate tokio_timer;
 
 #[cfg(unix)]
 #[macro_use]
 extern crate lazy_static;
-#[cfg(unix)]
 #[macro_use]
 extern crate log;
 
-use std::io::{self, Read, Write};
-use std::process::{Command, ExitStatus, Output, Stdio};
-
-use futures::{Async, Future, Poll, IntoFuture};
+use std::env;
+use std::io::{self, BufReader, Read, Write};
+use std::mem::ManuallyDrop;
+use std::process::{self, Command, ExitStatus, Output, Stdio};
+use std::ptr;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+
+use bytes::Bytes;
+use futures::{Async, Future, Poll, IntoFuture, Stream};
 use futures::future::{Either, ok};
 use kill::Kill;
 use std::fmt;
+#[cfg(unix)]
+use std::os::raw::c_int;
+use std::time::{Duration, Instant};
 use tokio_io::io::{read_to_end};
 use tokio_io::{AsyncWrite, AsyncRead, IoFuture};
 use tokio_reactor::Handle;
@@ -188,7 +218,67 @@ mod imp;
 #[cfg(windows)]
 mod imp;
 
+/// The building blocks used to reap child processes on Unix.
+///
+/// These allow building custom child-like futures, e.g. over a process which
+/// was not spawned by this crate, or over a mock process in tests.
+///
+/// This module is only available with the `unstable` feature enabled, and is
+/// exempt from the usual semver guarantees.
+#[cfg(all(unix, feature = "unstable"))]
+pub mod unix {
+    pub use imp::orphan::{AtomicOrphanQueue, OrphanQueue, Wait};
+    pub use imp::reap::{Generation, Reaper};
+    pub use imp::{sigchld_stream, Fd, SignalStream};
+}
+
+mod capture;
+mod chunks;
+mod drain;
+mod events;
+mod exit;
+mod feed;
 mod kill;
+mod limit;
+mod lines;
+mod merge;
+mod run;
+#[cfg(unix)]
+mod rusage;
+mod spec;
+mod splice;
+mod streaming;
+mod supervise;
+mod tail;
+mod terminate;
+mod timeout;
+mod watch;
+
+pub use chunks::Chunks;
+pub use drain::KillAndCollectStderr;
+pub use events::{ChildEvent, StatusStream};
+pub use exit::{ExitReason, ProcessExit, Success};
+pub use feed::FeedStdin;
+pub use limit::{LimitedOutput, WaitWithOutputLimited};
+pub use lines::Lines;
+pub use merge::{MergedOutput, OutputEvent, OutputEvents};
+pub use run::{RunExit, RunOutput};
+#[cfg(unix)]
+pub use rusage::{ResourceUsage, WaitWithOutputRusage};
+pub use spec::{ProcessSpec, StdioSpec};
+pub use splice::SpliceOutput;
+pub use streaming::WaitWithOutputStreaming;
+pub use supervise::Supervised;
+pub use tail::WaitWithOutputTail;
+pub use terminate::{GracefulExit, KillTimeout, TerminateGraceful};
+pub use timeout::{OutputAsyncTimeout, PartialOutput, TimeoutError, WaitWithOutputTimeout, WithTimeout};
+pub use watch::ExitHandle;
+use watch::ExitWatch;
+
+/// The highest priority accepted by `CommandConfigExt::nice`.
+const MIN_NICE: i32 = -20;
+/// The lowest priority accepted by `CommandConfigExt::nice`.
+const MAX_NICE: i32 = 19;
 
 /// Extensions provided by this crate to the `Command` type in the standard
 /// library.
@@ -230,9 +320,39 @@ pub trait CommandExt {
     ///
     /// The `handle` specified to this method must be a handle to a valid event
     /// loop, and all I/O this child does will be associated with the specified
-    /// event loop.
+    /// event loop. This includes the notifications used to detect the child's
+    /// exit, so children may be spawned from threads which are not running
+    /// any event loop themselves.
     fn spawn_async_with_handle(&mut self, handle: &Handle) -> io::Result<Child>;
 
+    /// Like `spawn_async`, but retries spawning the child should it fail
+    /// with a transient error.
+    ///
+    /// Spawning a child may fail temporarily when the system is under load,
+    /// e.g. with `EAGAIN` once the process limit has been reached or with
+    /// `ENOMEM` if `fork` cannot allocate memory for the child. Such failures
+    /// (i.e. errors of kind `WouldBlock` or `OutOfMemory`) are retried up to
+    /// `attempts` times in total, sleeping for `backoff` before the first
+    /// retry and twice as long before each subsequent one. Any other error,
+    /// or the error of the last attempt, is returned as is.
+    ///
+    /// Note that the backoff blocks the current thread, as spawning a child
+    /// always happens synchronously.
+    fn spawn_async_retry(&mut self, attempts: usize, backoff: Duration) -> io::Result<Child> {
+        self.spawn_async_retry_with_handle(attempts, backoff, &Handle::default())
+    }
+
+    /// Like `spawn_async_with_handle`, but retries spawning the child should
+    /// it fail with a transient error, see `spawn_async_retry`.
+    ///
+    /// The default implementation retries `spawn_async_with_handle` as a
+    /// whole.
+    fn spawn_async_retry_with_handle(&mut self, attempts: usize, backoff: Duration, handle: &Handle)
+        -> io::Result<Child>
+    {
+        retry_transient(attempts, backoff, || self.spawn_async_with_handle(handle))
+    }
+
     /// Executes a command as a child process, waiting for it to finish and
     /// collecting its exit status.
     ///
@@ -281,14 +401,65 @@ pub trait CommandExt {
     /// are returned through the `StatusAsync` future.
     fn status_async_with_handle(&mut self, handle: &Handle) -> io::Result<StatusAsync>;
 
+    /// Executes a command as a child process, returning a future of its exit
+    /// status along with any of its stdio handles which were piped.
+    ///
+    /// Unlike `status_async`, the piped stdio handles are *not* closed, and
+    /// are instead handed back so that they can be read from and written to
+    /// concurrently with waiting on the exit status. Note that it is then the
+    /// caller's responsibility to drain (or close) the handles: a child which
+    /// is blocked writing to a full pipe which nobody reads from, or reading
+    /// from a pipe which is never closed, will never exit.
+    ///
+    /// All I/O this child does will be associated with the current default
+    /// event loop.
+    ///
+    /// If the `StatusAsync` future is dropped before the future resolves, then
+    /// the child will be killed, if it was spawned.
+    fn status_async_keep_stdio(&mut self) -> io::Result<(StatusAsync, ChildStdio)> {
+        self.status_async_keep_stdio_with_handle(&Handle::default())
+    }
+
+    /// Executes a command as a child process, returning a future of its exit
+    /// status along with any of its stdio handles which were piped.
+    ///
+    /// Unlike `status_async_with_handle`, the piped stdio handles are *not*
+    /// closed, and are instead handed back so that they can be read from and
+    /// written to concurrently with waiting on the exit status. Note that it is
+    /// then the caller's responsibility to drain (or close) the handles: a
+    /// child which is blocked writing to a full pipe which nobody reads from,
+    /// or reading from a pipe which is never closed, will never exit.
+    ///
+    /// The `handle` specified must be a handle to a valid event loop, and all
+    /// I/O this child does will be associated with the specified event loop.
+    ///
+    /// If the `StatusAsync` future is dropped before the future resolves, then
+    /// the child will be killed, if it was spawned.
+    fn status_async_keep_stdio_with_handle(&mut self, handle: &Handle)
+        -> io::Result<(StatusAsync, ChildStdio)>
+    {
+        self.spawn_async_with_handle(handle).map(|mut child| {
+            let stdio = ChildStdio {
+                stdin: child.stdin.take(),
+                stdout: child.stdout.take(),
+                stderr: child.stderr.take(),
+            };
+
+            (StatusAsync { inner: child }, stdio)
+        })
+    }
+
     /// Executes the command as a child process, waiting for it to finish and
     /// collecting all of its output.
     ///
     /// > **Note**: this method, unlike the standard library, will
     /// > unconditionally configure the stdout/stderr handles to be pipes, even
-    /// > if they have been previously configured. If this is not desired then
-    /// > the `spawn_async` method should be used in combination with the
-    /// > `wait_with_output` method on child.
+    /// > if they have been previously configured. `Command` offers no way to
+    /// > inspect its current stdio configuration, so we cannot tell whether
+    /// > the handles were left unset. If this is not desired then the
+    /// > `spawn_async` method should be used in combination with the
+    /// > `wait_with_output` method on child, which captures whichever of
+    /// > stdout/stderr were configured as pipes.
     ///
     /// This method will return a future representing the collection of the
     /// child process's stdout/stderr. The `OutputAsync` future will resolve to
@@ -310,9 +481,12 @@ pub trait CommandExt {
     ///
     /// > **Note**: this method, unlike the standard library, will
     /// > unconditionally configure the stdout/stderr handles to be pipes, even
-    /// > if they have been previously configured. If this is not desired then
-    /// > the `spawn_async` method should be used in combination with the
-    /// > `wait_with_output` method on child.
+    /// > if they have been previously configured. `Command` offers no way to
+    /// > inspect its current stdio configuration, so we cannot tell whether
+    /// > the handles were left unset. If this is not desired then the
+    /// > `spawn_async` method should be used in combination with the
+    /// > `wait_with_output` method on child, which captures whichever of
+    /// > stdout/stderr were configured as pipes.
     ///
     /// This method will return a future representing the collection of the
     /// child process's stdout/stderr. The `OutputAsync` future will resolve to
@@ -326,6 +500,236 @@ pub trait CommandExt {
     /// If the `OutputAsync` future is dropped before the future resolves, then
     /// the child will be killed, if it was spawned.
     fn output_async_with_handle(&mut self, handle: &Handle) -> OutputAsync;
+
+    /// Executes the command as a child process, collecting all of its output
+    /// but killing it should it not finish within `timeout`.
+    ///
+    /// Like `output_async`, this unconditionally configures the stdout/stderr
+    /// handles to be pipes. The `OutputAsyncTimeout` future returned resolves
+    /// to the `Output` of the child if it exits and closes its output streams
+    /// in time. Otherwise the child is killed and the future fails with
+    /// `TimeoutError::Elapsed`, which holds the output captured up to that
+    /// point. Use `OutputAsyncTimeout::grace_period` to give the child a
+    /// chance to clean up via a `SIGTERM` before it is killed.
+    ///
+    /// All I/O this child does will be associated with the current default
+    /// event loop, and the returned future must be polled within a runtime
+    /// which provides a timer.
+    ///
+    /// If the `OutputAsyncTimeout` future is dropped before the future
+    /// resolves, then the child will be killed, if it was spawned.
+    fn output_async_timeout(&mut self, timeout: Duration) -> OutputAsyncTimeout {
+        self.output_async_timeout_with_handle(timeout, &Handle::default())
+    }
+
+    /// Executes the command as a child process, collecting all of its output
+    /// but killing it should it not finish within `timeout`.
+    ///
+    /// This is the same as `output_async_timeout`, except that the `handle`
+    /// specified must be a handle to a valid event loop, and all I/O this
+    /// child does will be associated with the specified event loop.
+    ///
+    /// The default implementation spawns the child through
+    /// `spawn_async_with_handle`, and thus only captures the output streams
+    /// which that configures to be pipes.
+    fn output_async_timeout_with_handle(&mut self, timeout: Duration, handle: &Handle)
+        -> OutputAsyncTimeout
+    {
+        OutputAsyncTimeout::new(self.spawn_async_with_handle(handle), timeout)
+    }
+}
+
+/// Further extensions provided by this crate to the `Command` type in the
+/// standard library, configuring how the child is spawned.
+///
+/// These are kept apart from `CommandExt`, so that they can be added without
+/// breaking any implementations of `CommandExt` outside of this crate.
+///
+/// # Spawn latency
+///
+/// Children are always spawned through `Command::spawn` of the standard
+/// library. On Unix it uses `posix_spawn` (which on Linux avoids copying the
+/// page tables of the parent, unlike `fork`) whenever the command allows it,
+/// and otherwise falls back to `fork` followed by `exec`. There is no way to
+/// force the former, as anything which must run in the child before it
+/// executes the command requires the latter. In particular, configuring any
+/// of the following forces a command onto the slower `fork` path, which
+/// matters most for parents with a large address space which spawn children
+/// frequently:
+///
+/// * `before_exec_async`, `rlimit`, `nice` and `cpu_affinity`, which all
+///   run in the child before it executes the command.
+/// * `spawn_pty_async` and `daemonize`, which make the child the leader of a
+///   new session.
+/// * The `pre_exec`, `uid` and `gid` methods of
+///   `std::os::unix::process::CommandExt`.
+///
+/// Note that whether `posix_spawn` is used also depends on the version of the
+/// standard library and the C library. `new_process_group` and
+/// `SpawnHandle::kill_process_tree` don't force the `fork` path.
+pub trait CommandConfigExt {
+    /// Configures the command to spawn the child as the leader of a new
+    /// process group.
+    ///
+    /// Any processes the child starts will join its group by default, so that
+    /// they can all be killed together with `Child::kill_group`, for example
+    /// when the child is a shell running a pipeline.
+    #[cfg(unix)]
+    fn new_process_group(&mut self) -> &mut Command;
+
+    /// Configures the command to spawn the child detached from the current
+    /// session, as is commonly done when starting a daemon.
+    ///
+    /// The child is made the leader of a new session (via `setsid(2)`) right
+    /// before it executes the command, so that it has no controlling terminal
+    /// and won't receive the signals sent to the terminal's foreground
+    /// process group, e.g. a `SIGINT` on Ctrl-C or a `SIGHUP` once the
+    /// terminal is closed. Its stdin, stdout and stderr are redirected to
+    /// `/dev/null`, which may be overridden by configuring its stdio
+    /// afterwards. As the leader of a new session the child also leads a new
+    /// process group, so `Child::kill_group` works as usual. However, this
+    /// cannot be combined with `new_process_group` or
+    /// `SpawnHandle::kill_process_tree`, as a process group leader cannot
+    /// create a session, and spawning fails with `EPERM` in that case.
+    ///
+    /// Note that the child still is a child of the current process, which
+    /// has to reap it once it exits. The `Child` returned when spawning it
+    /// kills it when dropped as usual, so to leave it running either keep
+    /// waiting on the `Child`, or call `kill_on_drop(false)` (or `forget`)
+    /// and drop it, after which it is reaped through the queue of orphans
+    /// once it exits (see `orphan_reaper` for reaping it right away). Fully
+    /// disowning the child, e.g. by double forking, isn't supported.
+    #[cfg(unix)]
+    fn daemonize(&mut self) -> &mut Command;
+
+    /// Schedules a closure to be run in the child process just before it
+    /// executes the command, like `std::os::unix::process::CommandExt::pre_exec`.
+    ///
+    /// Hooks run in the order they were registered, after the child's stdio
+    /// has been set up, and may freely inspect or adjust the child's stdio
+    /// descriptors. The asynchronous handling of any captured stdio happens
+    /// entirely within the parent once the child has been spawned, and only
+    /// ever affects the parent's ends of the pipes, so it never interferes
+    /// with these hooks. In particular, hooks should *not* make the child's
+    /// stdio nonblocking, as most programs do not expect that.
+    ///
+    /// If a hook returns an error, spawning the child fails with that error.
+    ///
+    /// # Safety
+    ///
+    /// The closure runs in the child after `fork`, so the same restrictions
+    /// as for `pre_exec` apply: it may only perform async-signal-safe
+    /// operations, and must not allocate or acquire any locks.
+    #[cfg(unix)]
+    unsafe fn before_exec_async<F>(&mut self, f: F) -> &mut Command
+        where F: FnMut() -> io::Result<()> + Send + Sync + 'static;
+
+    /// Sets a resource limit of the child (via `setrlimit(2)`) before it
+    /// executes the command, e.g. to bound its CPU time, memory or number of
+    /// open files.
+    ///
+    /// `resource` is one of the `RLIMIT_*` constants of the platform (which
+    /// may need to be cast to a `c_int`), and `soft` and `hard` are the soft
+    /// and hard limits to set, where `u64::MAX` stands for no limit at all.
+    /// Limits too large for the platform to represent (e.g. above
+    /// `RLIM_INFINITY`, or above `u32::MAX` where limits are 32 bits wide)
+    /// are treated as no limit as well. Note that an unprivileged process may
+    /// not raise its hard limits.
+    ///
+    /// Limits are applied in the child right before it executes the command,
+    /// after its stdio has been set up, in the order they were set (along
+    /// with any hooks registered through `before_exec_async`). Setting the
+    /// same resource more than once leaves it at the last limits set. If a
+    /// limit cannot be set, spawning the child fails with the error.
+    #[cfg(unix)]
+    fn rlimit(&mut self, resource: c_int, soft: u64, hard: u64) -> &mut Command;
+
+    /// Sets the scheduling priority of the child, e.g. to keep background
+    /// batch jobs from competing with latency sensitive work.
+    ///
+    /// `priority` is a niceness as understood by Unix, ranging from -20 (the
+    /// highest priority) to 19 (the lowest), with 0 being the default. On
+    /// Unix it is set in the child (via `setpriority(2)`) right before it
+    /// executes the command, and spawning fails if it cannot be set (e.g.
+    /// since only privileged processes may raise their priority).
+    ///
+    /// On Windows the niceness is mapped onto the closest priority class,
+    /// which is passed to `CommandExt::creation_flags`. The standard library
+    /// can't read back the creation flags of a command, so the class can't be
+    /// combined with flags set through it: any set before are discarded, and
+    /// setting flags afterwards discards the class in turn.
+    ///
+    /// A priority outside of the supported range is ignored with a warning,
+    /// leaving the priority of the child unchanged.
+    fn nice(&mut self, priority: i32) -> &mut Command;
+
+    /// Pins the child to the CPUs numbered in `cpus` (via
+    /// `sched_setaffinity(2)`) before it executes the command, e.g. to
+    /// dedicate isolated cores to it.
+    ///
+    /// The affinity is applied in the child right before it executes the
+    /// command, in the order it was set along with any resource limits and
+    /// hooks registered through `before_exec_async`. Spawning the child fails
+    /// with an error of kind `InvalidInput` if `cpus` is empty or refers to a
+    /// CPU which doesn't exist, or with whatever error setting the affinity
+    /// fails with (e.g. if none of the CPUs are available to the child).
+    #[cfg(target_os = "linux")]
+    fn cpu_affinity(&mut self, cpus: &[usize]) -> &mut Command;
+
+    /// Sets the file mode creation mask of the child (via `umask(2)`) before
+    /// it executes the command, controlling the permissions of any files the
+    /// child creates.
+    ///
+    /// Only the permission bits of `mode` (i.e. `0o777`) are used. The mask
+    /// is applied in the child right before it executes the command, in the
+    /// order it was set along with any resource limits and hooks registered
+    /// through `before_exec_async`, so setting it more than once leaves it at
+    /// the last mask set. Setting the mask never fails.
+    #[cfg(unix)]
+    fn umask(&mut self, mode: u32) -> &mut Command;
+
+    /// Clears the environment of the child, except for the variables named in
+    /// `allowlist` which keep the values they have in the current process.
+    ///
+    /// The values are read when this method is called, and variables which
+    /// aren't set in the current process are left unset. This is equivalent
+    /// to calling `env_clear` followed by `env` for each of the variables, so
+    /// any environment configured on the command before is discarded, while
+    /// variables set afterwards (e.g. through `env`) are added as usual.
+    fn sanitized_env(&mut self, allowlist: &[&str]) -> &mut Command;
+
+    /// Executes the command as a child process attached to a newly allocated
+    /// pseudo-terminal of `size` (given as `(rows, columns)`), returning a
+    /// handle to the child along with the parent's end of the pty.
+    ///
+    /// The child's stdin, stdout and stderr are all connected to the pty,
+    /// which also becomes its controlling terminal (the child is made the
+    /// leader of a new session to allow this). Everything the child writes
+    /// can be read from the returned `Pty`, and everything written to the
+    /// `Pty` is seen as terminal input by the child.
+    ///
+    /// This overrides any stdio configured on the command, and leaves it
+    /// inheriting the parent's stdio afterwards. The hook making the child a
+    /// session leader and acquiring the pty stays installed on the command,
+    /// so it should not be reused for spawning a child without a pty: such a
+    /// child would still try to acquire its stdin as a controlling terminal,
+    /// failing to spawn if that isn't a terminal.
+    ///
+    /// Spawning fails if the child can't be made the leader of a new session,
+    /// e.g. since `new_process_group` made it the leader of a process group.
+    ///
+    /// All I/O this child does will be associated with the current default
+    /// event loop.
+    #[cfg(unix)]
+    fn spawn_pty_async(&mut self, size: (u16, u16)) -> io::Result<(Child, Pty)> {
+        self.spawn_pty_async_with_handle(size, &Handle::default())
+    }
+
+    /// Like `spawn_pty_async`, but associates all I/O this child does with
+    /// the event loop of `handle`.
+    #[cfg(unix)]
+    fn spawn_pty_async_with_handle(&mut self, size: (u16, u16), handle: &Handle)
+        -> io::Result<(Child, Pty)>;
 }
 
 struct SpawnedChild {
@@ -335,25 +739,176 @@ struct SpawnedChild {
     stderr: Option<imp::ChildStderr>,
 }
 
+impl SpawnedChild {
+    fn into_child(self) -> Child {
+        let stdout_eof = EofFlag::default();
+        let stderr_eof = EofFlag::default();
+
+        // Taken as early as possible, as it is used to measure the wall time
+        // of the child
+        let spawned = Instant::now();
+        let start_time = self.child.start_time();
+
+        Child {
+            child: ChildDropGuard::new(self.child),
+            stdin: self.stdin.map(|inner| ChildStdin { inner }),
+            stdout: self.stdout.map(|inner| ChildStdout { inner, eof: stdout_eof.clone() }),
+            stderr: self.stderr.map(|inner| ChildStderr { inner, eof: stderr_eof.clone() }),
+            stdout_eof,
+            stderr_eof,
+            killed_by_us: false,
+            exit_watch: ExitWatch::default(),
+            spawned,
+            start_time,
+            #[cfg(target_os = "linux")]
+            oom_kills: imp::oom_kills(),
+        }
+    }
+}
+
+/// Options for spawning children which cannot be configured on a `Command`.
+#[derive(Debug, Clone, Default)]
+struct SpawnOptions {
+    kill_tree: bool,
+    #[cfg(target_os = "linux")]
+    pipe_capacity: Option<usize>,
+    /// A SIGCHLD stream shared by all children spawned onto the same event
+    /// loop, used instead of registering a stream per child.
+    #[cfg(unix)]
+    sigchld: Option<imp::sigchld::SharedSigchld>,
+    /// The signal to listen for instead of `SIGCHLD`, if any.
+    #[cfg(unix)]
+    reap_signal: Option<c_int>,
+    /// Whether children report being stopped and continued.
+    #[cfg(unix)]
+    job_control: bool,
+    /// How long to wait for a notification before checking on a child anyway.
+    #[cfg(unix)]
+    reap_poll_interval: Option<Duration>,
+    /// How many times to attempt spawning a child, and how long to wait
+    /// before the first retry.
+    spawn_retry: Option<(usize, Duration)>,
+    hooks: Hooks,
+}
+
+type SpawnHook = Arc<dyn Fn(u32, &Command) + Send + Sync>;
+type ReapHook = Arc<dyn Fn(u32, ExitStatus) + Send + Sync>;
+
+/// Callbacks invoked as children are spawned and reaped, see
+/// `SpawnHandle::on_spawn` and `SpawnHandle::on_reap`.
+#[derive(Clone, Default)]
+struct Hooks {
+    on_spawn: Option<SpawnHook>,
+    on_reap: Option<ReapHook>,
+}
+
+impl fmt::Debug for Hooks {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.debug_struct("Hooks")
+            .field("on_spawn", &self.on_spawn.is_some())
+            .field("on_reap", &self.on_reap.is_some())
+            .finish()
+    }
+}
+
+impl SpawnOptions {
+    /// Spawns `cmd`, retrying on transient failures if configured to.
+    fn spawn_std(&self, cmd: &mut Command) -> io::Result<process::Child> {
+        match self.spawn_retry {
+            Some((attempts, backoff)) => retry_transient(attempts, backoff, || cmd.spawn()),
+            None => cmd.spawn(),
+        }
+    }
+}
+
+/// Calls `spawn` up to `attempts` times in total for as long as it fails with
+/// a transient error, sleeping for `backoff` before the first retry and twice
+/// as long before each subsequent one.
+fn retry_transient<T, F>(attempts: usize, mut backoff: Duration, mut spawn: F) -> io::Result<T>
+    where F: FnMut() -> io::Result<T>,
+{
+    let mut attempt = 1;
+    loop {
+        match spawn() {
+            Err(ref e) if attempt < attempts && is_transient_spawn_error(e) => {
+                debug!("retrying failed spawn in {:?}: {}", backoff, e);
+                thread::sleep(backoff);
+                backoff *= 2;
+                attempt += 1;
+            },
+            ret => return ret,
+        }
+    }
+}
+
+/// Returns whether spawning a child failed due to a (presumably temporary)
+/// shortage of resources.
+fn is_transient_spawn_error(e: &io::Error) -> bool {
+    matches!(e.kind(), io::ErrorKind::WouldBlock | io::ErrorKind::OutOfMemory)
+}
+
+fn spawn(cmd: &mut Command, handle: &Handle, orphans: imp::Orphans, options: &SpawnOptions)
+    -> io::Result<Child>
+{
+    imp::check_reactor(handle)?;
+    spawn_unchecked(cmd, handle, orphans, options)
+}
+
+/// Spawns each of `commands`, returning the result of spawning each of them
+/// in order, or an error if the event loop of `handle` has gone away.
+fn spawn_many(commands: Vec<Command>, handle: &Handle, orphans: &imp::Orphans, options: &SpawnOptions)
+    -> io::Result<Vec<io::Result<Child>>>
+{
+    // All children share an event loop, so it only needs to be checked once
+    imp::check_reactor(handle)?;
+
+    Ok(commands.into_iter()
+        .map(|mut cmd| spawn_unchecked(&mut cmd, handle, orphans.clone(), options))
+        .collect())
+}
+
+/// Like `spawn`, but without checking whether the event loop of `handle` is
+/// still running.
+fn spawn_unchecked(cmd: &mut Command, handle: &Handle, orphans: imp::Orphans, options: &SpawnOptions)
+    -> io::Result<Child>
+{
+    let mut child = imp::spawn_child(cmd, handle, orphans, options)?.into_child();
+    let id = child.child.inner.id();
+
+    if let Some(ref on_spawn) = options.hooks.on_spawn {
+        on_spawn(id, cmd);
+    }
+
+    if let Some(ref on_reap) = options.hooks.on_reap {
+        let on_reap = on_reap.clone();
+        child.exit_watch.on_publish(move |status| on_reap(id, status));
+    }
+
+    Ok(child)
+}
+
 impl CommandExt for Command {
     fn spawn_async_with_handle(&mut self, handle: &Handle) -> io::Result<Child> {
-        imp::spawn_child(self, handle)
-            .map(|spawned_child| Child {
-                child: ChildDropGuard::new(spawned_child.child),
-                stdin: spawned_child.stdin.map(|inner| ChildStdin { inner }),
-                stdout: spawned_child.stdout.map(|inner| ChildStdout { inner }),
-                stderr: spawned_child.stderr.map(|inner| ChildStderr { inner }),
-            })
+        spawn(self, handle, imp::Orphans::global(), &SpawnOptions::default())
+    }
+
+    fn spawn_async_retry_with_handle(&mut self, attempts: usize, backoff: Duration, handle: &Handle)
+        -> io::Result<Child>
+    {
+        let options = SpawnOptions {
+            spawn_retry: Some((attempts, backoff)),
+            ..SpawnOptions::default()
+        };
+        spawn(self, handle, imp::Orphans::global(), &options)
     }
 
     fn status_async_with_handle(&mut self, handle: &Handle) -> io::Result<StatusAsync> {
         self.spawn_async_with_handle(handle).map(|mut child| {
             // Ensure we close any stdio handles so we can't deadlock
             // waiting on the child which may be waiting to read/write
-            // to a pipe we're holding.
-            child.stdin.take();
-            child.stdout.take();
-            child.stderr.take();
+            // to a pipe we're holding. The handles are closed regardless of
+            // whether deregistering them fails.
+            drop(child.close_all_stdio());
 
             StatusAsync {
                 inner: child,
@@ -373,6 +928,501 @@ impl CommandExt for Command {
             inner: Box::new(inner),
         }
     }
+
+    fn output_async_timeout_with_handle(&mut self, timeout: Duration, handle: &Handle)
+        -> OutputAsyncTimeout
+    {
+        self.stdout(Stdio::piped());
+        self.stderr(Stdio::piped());
+
+        OutputAsyncTimeout::new(self.spawn_async_with_handle(handle), timeout)
+    }
+}
+
+impl CommandConfigExt for Command {
+    #[cfg(unix)]
+    fn new_process_group(&mut self) -> &mut Command {
+        ::std::os::unix::process::CommandExt::process_group(self, 0)
+    }
+
+    #[cfg(unix)]
+    fn daemonize(&mut self) -> &mut Command {
+        self.stdin(Stdio::null());
+        self.stdout(Stdio::null());
+        self.stderr(Stdio::null());
+
+        imp::start_session(self);
+        self
+    }
+
+    #[cfg(unix)]
+    unsafe fn before_exec_async<F>(&mut self, f: F) -> &mut Command
+        where F: FnMut() -> io::Result<()> + Send + Sync + 'static,
+    {
+        ::std::os::unix::process::CommandExt::pre_exec(self, f)
+    }
+
+    #[cfg(unix)]
+    fn rlimit(&mut self, resource: c_int, soft: u64, hard: u64) -> &mut Command {
+        let limit = imp::Rlimit::new(resource, soft, hard);
+
+        // Safe since setting the limit is async-signal-safe
+        unsafe {
+            ::std::os::unix::process::CommandExt::pre_exec(self, move || limit.apply())
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    fn cpu_affinity(&mut self, cpus: &[usize]) -> &mut Command {
+        let affinity = imp::CpuAffinity::new(cpus);
+
+        // Safe since setting the affinity is async-signal-safe
+        unsafe {
+            ::std::os::unix::process::CommandExt::pre_exec(self, move || affinity.apply())
+        }
+    }
+
+    #[cfg(unix)]
+    fn umask(&mut self, mode: u32) -> &mut Command {
+        imp::set_umask(self, mode);
+        self
+    }
+
+    fn nice(&mut self, priority: i32) -> &mut Command {
+        if !(MIN_NICE..=MAX_NICE).contains(&priority) {
+            warn!("ignoring unsupported scheduling priority {}", priority);
+            return self;
+        }
+
+        imp::set_priority(self, priority);
+        self
+    }
+
+    fn sanitized_env(&mut self, allowlist: &[&str]) -> &mut Command {
+        self.env_clear();
+
+        for key in allowlist {
+            if let Some(value) = env::var_os(key) {
+                self.env(key, value);
+            }
+        }
+
+        self
+    }
+
+    #[cfg(unix)]
+    fn spawn_pty_async_with_handle(&mut self, size: (u16, u16), handle: &Handle)
+        -> io::Result<(Child, Pty)>
+    {
+        let (master, slave) = imp::pty::open(size)?;
+        imp::pty::attach(self, &slave)?;
+
+        let child = spawn(self, handle, imp::Orphans::global(), &SpawnOptions::default());
+        imp::pty::detach(self);
+        let child = child?;
+
+        let pty = Pty {
+            inner: imp::pty::register(master, handle)?,
+        };
+
+        Ok((child, pty))
+    }
+}
+
+/// A handle for spawning children onto a specific event loop, each sharing a
+/// dedicated queue of orphaned processes.
+///
+/// On Unix, a child which is dropped before it has been reaped (for example
+/// via `Child::forget`) is placed on a queue of orphans which are reaped the
+/// next time any other child is polled. By default all children share a
+/// single process-wide queue, which in applications running several event
+/// loops means every event loop ends up reaping (and contending on) every
+/// other loop's orphans. Children spawned through a `SpawnHandle` instead
+/// only share a queue with other children spawned through the same handle
+/// (or its clones).
+///
+/// On Windows there are no orphans to reap, and this is simply equivalent to
+/// using `CommandExt::spawn_async_with_handle`.
+///
+/// On Unix, children which are notified of their exit through `SIGCHLD`
+/// (rather than a per-child descriptor, where the platform supports it) also
+/// share a single signal stream with the other children spawned through the
+/// same handle, instead of each registering a stream of their own.
+///
+/// A `SpawnHandle` can additionally be configured to spawn children whose
+/// entire process tree is killed along with them, see `kill_process_tree`.
+#[derive(Debug, Clone)]
+pub struct SpawnHandle {
+    handle: Handle,
+    orphans: imp::Orphans,
+    options: SpawnOptions,
+}
+
+impl SpawnHandle {
+    /// Creates a new `SpawnHandle` which associates all I/O of the children it
+    /// spawns with the event loop of `handle`.
+    pub fn new(handle: Handle) -> Self {
+        #[cfg_attr(not(unix), allow(unused_mut))]
+        let mut options = SpawnOptions::default();
+        #[cfg(unix)]
+        {
+            options.sigchld = Some(imp::sigchld::SharedSigchld::new(&handle));
+        }
+
+        Self {
+            handle,
+            orphans: imp::Orphans::new(),
+            options,
+        }
+    }
+
+    /// Controls whether killing a child spawned by this handle (including via
+    /// `kill_on_drop`) also kills all of the processes it has started.
+    ///
+    /// By default only the child itself is killed, and any processes it has
+    /// started are left running.
+    ///
+    /// On Unix, each child is placed in a new process group (leaving its
+    /// `Command` configured to do so) and killing it signals the entire
+    /// group. Note that descendants which move themselves to a different
+    /// process group or session will not be killed.
+    ///
+    /// The group is signalled even if the child itself has already exited and
+    /// been reaped, so that descendants outliving it are killed as well.
+    /// Should every member of the group have exited by then, however, the OS
+    /// may have recycled the group id for an unrelated process, which would be
+    /// killed in their place along with its own process group. Only enable
+    /// this for children whose process tree is killed before they are reaped,
+    /// or if this risk is acceptable.
+    ///
+    /// On Windows, each child is assigned to a job object and killing it
+    /// terminates the job. Any processes the child manages to start before it
+    /// has been assigned to the job will not be killed.
+    pub fn kill_process_tree(&mut self, kill_tree: bool) -> &mut Self {
+        self.options.kill_tree = kill_tree;
+        self
+    }
+
+    /// Sets the capacity, in bytes, of the pipes created for the captured
+    /// stdio of children spawned by this handle.
+    ///
+    /// Larger pipes allow data to be transferred with fewer system calls
+    /// (and fewer wakeups of the event loop). The kernel rounds the capacity
+    /// up to a multiple of the page size, and unprivileged processes may not
+    /// exceed the limit in `/proc/sys/fs/pipe-max-size`. Should the capacity
+    /// not be accepted, pipes silently retain their default capacity.
+    #[cfg(target_os = "linux")]
+    pub fn pipe_capacity(&mut self, capacity: usize) -> &mut Self {
+        self.options.pipe_capacity = Some(capacity);
+        self
+    }
+
+    /// Sets the signal which notifies us that a child may have exited,
+    /// instead of `SIGCHLD`.
+    ///
+    /// This is only meant for sandboxed or containerized environments whose
+    /// runtime intercepts `SIGCHLD` and forwards it as a different signal.
+    /// There are a number of caveats:
+    ///
+    /// * The signal must be delivered to this process whenever any of its
+    ///   children exit, otherwise children spawned by this handle may never
+    ///   be reaped.
+    /// * A handler for the signal is installed for the rest of the lifetime
+    ///   of the process, replacing its default disposition (so e.g. using
+    ///   `SIGUSR1` stops it from terminating the process).
+    /// * On platforms where children are notified of their exit without any
+    ///   signals (Linux 5.3+, macOS and FreeBSD), the signal is only used for
+    ///   children for which that is unavailable.
+    ///
+    /// The signal must be one which can be handled (i.e. not `SIGKILL` or
+    /// `SIGSTOP`), otherwise waiting on any child which relies on it fails.
+    #[cfg(unix)]
+    pub fn reap_signal(&mut self, signal: c_int) -> &mut Self {
+        self.options.reap_signal = Some(signal);
+        self.options.sigchld = Some(imp::sigchld::SharedSigchld::with_signal(&self.handle, signal));
+        self
+    }
+
+    /// Checks on children spawned by this handle whenever no notification that
+    /// they may have exited has been received for `interval`.
+    ///
+    /// This is a safety net for environments in which `SIGCHLD` (or the
+    /// signal configured by `reap_signal`) is not delivered reliably, for
+    /// example because it is masked, where children could otherwise never be
+    /// observed exiting. Each waiting child then also checks on itself at
+    /// least once per `interval`, so this is disabled by default to avoid
+    /// needless timers and system calls. Waiting on such children must
+    /// happen within a runtime which provides a timer.
+    #[cfg(unix)]
+    pub fn reap_poll_interval(&mut self, interval: Duration) -> &mut Self {
+        self.options.reap_poll_interval = Some(interval);
+        self
+    }
+
+    /// Controls whether children spawned by this handle report being stopped
+    /// and continued through `Child::status_stream`, in addition to exiting.
+    ///
+    /// This is disabled by default, since it requires children to be notified
+    /// of their state changes through `SIGCHLD` (or the signal configured by
+    /// `reap_signal`), even on platforms where they are otherwise notified of
+    /// their exit without any signals. A stream of such a child consumes the
+    /// stop and continue notifications of the child, so they should not also
+    /// be waited for elsewhere (e.g. with `waitpid`).
+    #[cfg(unix)]
+    pub fn track_job_control(&mut self, job_control: bool) -> &mut Self {
+        self.options.job_control = job_control;
+        self
+    }
+
+    /// Registers a callback which is invoked right after each child is
+    /// successfully spawned through this handle, with its process id and the
+    /// command it was spawned from.
+    ///
+    /// This is meant for observability, e.g. to trace or count every child
+    /// spawned, without wrapping every place children are spawned from. The
+    /// callback runs synchronously within `spawn`, so it should be cheap.
+    /// Registering another callback replaces the previous one.
+    pub fn on_spawn<F>(&mut self, hook: F) -> &mut Self
+        where F: Fn(u32, &Command) + Send + Sync + 'static,
+    {
+        self.options.hooks.on_spawn = Some(Arc::new(hook));
+        self
+    }
+
+    /// Registers a callback which is invoked once a child spawned through
+    /// this handle is observed exiting, with its process id and exit status.
+    ///
+    /// The callback is invoked at most once per child, from within whichever
+    /// call observes the exit first, i.e. polling the `Child` (or its
+    /// `status_stream`) to completion or `Child::try_status`. It should thus
+    /// be cheap and must not block. Children which are dropped before their
+    /// exit is observed are reaped in the background without invoking it.
+    /// Registering another callback replaces the previous one, for children
+    /// spawned afterwards.
+    pub fn on_reap<F>(&mut self, hook: F) -> &mut Self
+        where F: Fn(u32, ExitStatus) + Send + Sync + 'static,
+    {
+        self.options.hooks.on_reap = Some(Arc::new(hook));
+        self
+    }
+
+    /// Returns the event loop handle children are spawned with.
+    pub fn handle(&self) -> &Handle {
+        &self.handle
+    }
+
+    /// Executes the command as a child process, returning a handle to it.
+    ///
+    /// This behaves the same as `CommandExt::spawn_async_with_handle`,
+    /// except that the child uses the orphan queue of this `SpawnHandle`.
+    pub fn spawn(&self, cmd: &mut Command) -> io::Result<Child> {
+        spawn(cmd, &self.handle, self.orphans.clone(), &self.options)
+    }
+
+    /// Executes each of `commands` as a child process, like `spawn`.
+    ///
+    /// This is cheaper than spawning the children one by one, see
+    /// `spawn_many_async` for details.
+    pub fn spawn_many(&self, commands: Vec<Command>) -> io::Result<Vec<io::Result<Child>>> {
+        spawn_many(commands, &self.handle, &self.orphans, &self.options)
+    }
+
+    /// Like the crate-level `reap_orphans_now`, but reaps the orphans of
+    /// children spawned through this handle (or its clones) instead.
+    pub fn reap_orphans_now(&self, timeout: Duration) -> Vec<u32> {
+        self.orphans.reap_orphans_now(timeout)
+    }
+
+    /// Like the crate-level `reap_orphan`, but looks for the orphan among the
+    /// orphans of children spawned through this handle (or its clones).
+    pub fn reap_orphan(&self, pid: u32) -> io::Result<Option<ExitStatus>> {
+        self.orphans.reap_orphan(pid)
+    }
+
+    /// Like the crate-level `orphan_queue_len`, but counts the orphans of
+    /// children spawned through this handle (or its clones) instead.
+    pub fn orphan_queue_len(&self) -> usize {
+        self.orphans.len()
+    }
+
+    /// Like the crate-level `orphan_reaper`, but reaps the orphans of
+    /// children spawned through this handle (or its clones) instead.
+    ///
+    /// The returned future listens for the same signal as the children of
+    /// this handle, see `reap_signal`.
+    pub fn orphan_reaper(&self) -> io::Result<OrphanReaper> {
+        imp::OrphanReaper::new(self.orphans.clone(), &self.handle, &self.options)
+            .map(|inner| OrphanReaper { inner })
+    }
+}
+
+/// Executes each of `commands` as a child process, associating the I/O of all
+/// of them with the event loop of `handle`.
+///
+/// This is meant for launching many children at once, e.g. in parallel job
+/// runners, and avoids some of the overhead of spawning them one by one
+/// through `CommandExt::spawn_async_with_handle`: the event loop is only
+/// checked once, and on Unix the children which are notified of their exit
+/// through `SIGCHLD` share a single signal stream (as if spawned through the
+/// same `SpawnHandle`), rather than each registering a stream of their own.
+///
+/// Failing to spawn one of the commands doesn't affect the others, so the
+/// result of spawning each command is returned in the same order as
+/// `commands`. An error is only returned as a whole if the event loop of
+/// `handle` has gone away, in which case none of the commands are spawned.
+pub fn spawn_many_async(commands: Vec<Command>, handle: &Handle) -> io::Result<Vec<io::Result<Child>>> {
+    #[cfg_attr(not(unix), allow(unused_mut))]
+    let mut options = SpawnOptions::default();
+    #[cfg(unix)]
+    {
+        options.sigchld = Some(imp::sigchld::SharedSigchld::new(handle));
+    }
+
+    spawn_many(commands, handle, &imp::Orphans::global(), &options)
+}
+
+/// Synchronously reaps any orphaned children, blocking the current thread
+/// until they have all been reaped or `timeout` has elapsed.
+///
+/// Children which are dropped while still running are queued up to be reaped
+/// the next time any other child is polled. If that never happens, for
+/// example because the event loop is shutting down, they are left behind as
+/// zombie processes. This function is meant to be called in such a shutdown
+/// path to avoid that. Note that it does not kill any orphans, but only waits
+/// for them to exit.
+///
+/// Returns the ids of any orphans which could not be reaped in time. These
+/// are still reaped in the background as usual should any other child be
+/// polled later on.
+///
+/// On Windows there are no orphans to reap, so this does nothing.
+pub fn reap_orphans_now(timeout: Duration) -> Vec<u32> {
+    imp::Orphans::global().reap_orphans_now(timeout)
+}
+
+/// Returns the OS-assigned process identifier of the current process.
+///
+/// This is the id every child spawned by this process reports as its parent,
+/// see `Child::ppid`, and is the same as `std::process::id`.
+pub fn current_pid() -> u32 {
+    process::id()
+}
+
+/// Registers the current process as a child subreaper, via
+/// `prctl(PR_SET_CHILD_SUBREAPER)`.
+///
+/// Descendants of a subreaper which are orphaned (e.g. since the child which
+/// spawned them exited) are then reparented to it rather than to `init`,
+/// which is what init-like supervisors and container entrypoints rely on to
+/// keep track of every process they started, directly or not.
+///
+/// Note that reparented descendants are not reaped by this crate by default:
+/// the orphan queue only tracks children which were spawned (and then
+/// dropped) through it, and neither lazy reaping nor `orphan_reaper` waits on
+/// any other process. Reparented descendants arrive with a `SIGCHLD` like any
+/// other child, so a supervisor has to either wait on them itself or use
+/// `reap_reparented_children` instead to avoid accumulating zombie processes.
+#[cfg(target_os = "linux")]
+pub fn become_subreaper() -> io::Result<()> {
+    imp::become_subreaper()
+}
+
+/// Registers the current process as a child subreaper like
+/// `become_subreaper`, and additionally reaps any descendants reparented to
+/// it once they exit.
+///
+/// Reparented descendants are reaped whenever orphans are, i.e. whenever a
+/// child is polled after a `SIGCHLD`, or eagerly by an `orphan_reaper`
+/// future, which init-like supervisors will want to spawn. Their exit status
+/// is discarded.
+///
+/// Children spawned through this crate (or adopted via `Child::from_std`)
+/// are never reaped this way for as long as their `Child` exists, or they
+/// are queued up as orphans, so their exit status is never stolen. Any other
+/// child of the current process is considered to be reparented, however, and
+/// may be reaped as soon as it exits: this includes children spawned through
+/// `std::process::Command` directly (until they are adopted) and children
+/// handed back through `Child::into_std`, which can thus no longer be waited
+/// on reliably. This mode is meant for processes acting as an init, which
+/// spawn all of their children through this crate, and cannot be turned off
+/// again.
+#[cfg(target_os = "linux")]
+pub fn reap_reparented_children() -> io::Result<()> {
+    imp::reap_reparented_children()
+}
+
+/// Attempts to reap only the orphaned child with the id `pid`, without
+/// blocking or checking on any other orphans.
+///
+/// This is meant for supervisors which keep track of the ids of the children
+/// they orphan themselves, and want to clean up after a particular one (e.g.
+/// once told by some other means that it has exited). Returns the exit status
+/// of the orphan if it has exited, or `None` if it is still running, in which
+/// case it stays queued to be reaped later on. Fails with an error of kind
+/// `NotFound` if no orphan with the id is queued, e.g. since it has already
+/// been reaped in the background. Children spawned through a `SpawnHandle`
+/// must be reaped through `SpawnHandle::reap_orphan` instead.
+///
+/// On Windows there are no orphans to reap, so this always fails.
+pub fn reap_orphan(pid: u32) -> io::Result<Option<ExitStatus>> {
+    imp::Orphans::global().reap_orphan(pid)
+}
+
+/// Returns the number of orphaned children which are still waiting to be
+/// reaped.
+///
+/// This is cheap enough to be sampled regularly, e.g. as a metric, and a
+/// number which keeps growing indicates that orphans are not being reaped.
+/// The count is only a snapshot taken without locking the queue, and
+/// excludes any orphans which are in the middle of being reaped. Children
+/// spawned through a `SpawnHandle` are counted by
+/// `SpawnHandle::orphan_queue_len` instead.
+///
+/// On Windows there are no orphans to reap, so this always returns zero.
+pub fn orphan_queue_len() -> usize {
+    imp::Orphans::global().len()
+}
+
+/// Creates a future which reaps orphaned children as soon as they exit,
+/// registered with the event loop of `handle`.
+///
+/// By default orphans are only reaped lazily, whenever some other child is
+/// polled. Applications which mostly spawn children and then drop them (e.g.
+/// with `kill_on_drop(false)`) may never poll another child, and would thus
+/// accumulate zombie processes. Spawning the returned future onto an
+/// executor instead reaps orphans eagerly, every time a `SIGCHLD` is
+/// received.
+///
+/// The future never completes on its own, so it should be dropped (e.g. by
+/// shutting down the runtime it was spawned onto) once it is no longer
+/// needed. Children spawned through a `SpawnHandle` are reaped by
+/// `SpawnHandle::orphan_reaper` instead.
+///
+/// On Windows there are no orphans to reap, so the future completes
+/// immediately.
+pub fn orphan_reaper(handle: &Handle) -> io::Result<OrphanReaper> {
+    imp::OrphanReaper::new(imp::Orphans::global(), handle, &SpawnOptions::default())
+        .map(|inner| OrphanReaper { inner })
+}
+
+/// Future returned from the `orphan_reaper` and `SpawnHandle::orphan_reaper`
+/// functions.
+///
+/// This future reaps orphaned children whenever they may have exited, and
+/// never completes unless it fails to listen for their exit.
+#[must_use = "futures do nothing unless polled"]
+#[derive(Debug)]
+pub struct OrphanReaper {
+    inner: imp::OrphanReaper,
+}
+
+impl Future for OrphanReaper {
+    type Item = ();
+    type Error = io::Error;
+
+    fn poll(&mut self) -> Poll<(), io::Error> {
+        self.inner.poll()
+    }
 }
 
 /// A drop guard which ensures the child process is killed on drop to maintain
@@ -391,8 +1441,23 @@ impl<T: Kill> ChildDropGuard<T> {
         }
     }
 
+    /// Consumes the guard without killing the child.
+    fn into_inner(self) -> T {
+        // Keeps our `Drop` implementation from running, which would otherwise
+        // kill the child
+        let this = ManuallyDrop::new(self);
+        // Safe since `this` is never used (nor dropped) again, so `inner` is
+        // only ever moved out of it once, and `kill_on_drop` does not need to
+        // be dropped.
+        unsafe { ptr::read(&this.inner) }
+    }
+
     fn forget(&mut self) {
-        self.kill_on_drop = false;
+        self.set_kill_on_drop(false);
+    }
+
+    fn set_kill_on_drop(&mut self, kill_on_drop: bool) {
+        self.kill_on_drop = kill_on_drop;
     }
 }
 
@@ -408,84 +1473,671 @@ impl<T: Kill> Kill for ChildDropGuard<T> {
     }
 }
 
-impl<T: Kill> Drop for ChildDropGuard<T> {
-    fn drop(&mut self) {
-        if self.kill_on_drop {
-            drop(self.kill());
+impl<T: Kill> Drop for ChildDropGuard<T> {
+    fn drop(&mut self) {
+        if self.kill_on_drop {
+            drop(self.kill());
+        }
+    }
+}
+
+
+impl<T: Future + Kill> Future for ChildDropGuard<T> {
+    type Item = T::Item;
+    type Error = T::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        let ret = self.inner.poll();
+
+        if let Ok(Async::Ready(_)) = ret {
+            // Avoid the overhead of trying to kill a reaped process
+            self.kill_on_drop = false;
+        }
+
+        ret
+    }
+}
+
+/// Representation of a child process spawned onto an event loop.
+///
+/// This type is also a future which will yield the `ExitStatus` of the
+/// underlying child process. A `Child` here also provides access to information
+/// like the OS-assigned identifier and the stdio streams.
+///
+/// Unlike most futures, a `Child` may be polled again after it has resolved,
+/// in which case it resolves to the same `ExitStatus` straight away, so it is
+/// safe to use with combinators which may poll it more than once.
+///
+/// > **Note**: The behavior of `drop` on a child in this crate is *different
+/// > than the behavior of the standard library*. If a `tokio_process::Child` is
+/// > dropped before the process finishes then the process will be terminated.
+/// > In the standard library, however, the process continues executing. This is
+/// > done because futures in general take `drop` as a sign of cancellation, and
+/// > this `Child` is itself a future. If you'd like to run a process in the
+/// > background, though, you may use the `forget` method.
+///
+/// `Child` is both `Send` and `Sync`, as are its stdio handles, so it can be
+/// moved to other threads or shared between tasks (e.g. behind an
+/// `Arc<Mutex<Child>>`). Note that polling it, like reading from or writing
+/// to its stdio, still requires exclusive access.
+#[must_use = "futures do nothing unless polled"]
+#[derive(Debug)]
+pub struct Child {
+    child: ChildDropGuard<imp::Child>,
+    stdin: Option<ChildStdin>,
+    stdout: Option<ChildStdout>,
+    stderr: Option<ChildStderr>,
+    stdout_eof: EofFlag,
+    stderr_eof: EofFlag,
+    killed_by_us: bool,
+    exit_watch: ExitWatch,
+    #[cfg_attr(not(unix), allow(dead_code))]
+    spawned: Instant,
+    start_time: Option<u64>,
+    /// The OOM kill count of our cgroup when the child was spawned, used to
+    /// tell whether a `SIGKILL` came from the OOM killer.
+    #[cfg(target_os = "linux")]
+    oom_kills: Option<u64>,
+}
+
+// Fails to compile should any of the types ever stop being `Send` or `Sync`.
+#[allow(dead_code)]
+fn assert_send_sync() {
+    fn is_send_sync<T: Send + Sync>() {}
+
+    is_send_sync::<Child>();
+    is_send_sync::<ChildStdin>();
+    is_send_sync::<ChildStdout>();
+    is_send_sync::<ChildStderr>();
+}
+
+impl Child {
+    /// Adopts a child process which was spawned elsewhere, so that it can be
+    /// waited on asynchronously.
+    ///
+    /// Any stdio handles present on `child` are switched to nonblocking mode
+    /// and associated with the event loop of `handle`, becoming available
+    /// through the `stdin`, `stdout`, and `stderr` methods. From then on the
+    /// returned `Child` behaves exactly as if it had been spawned via
+    /// `CommandExt::spawn_async_with_handle`, including being killed if it is
+    /// dropped before exiting.
+    ///
+    /// The process must not have been waited on already (e.g. via
+    /// `std::process::Child::wait`), as it is not possible to receive any
+    /// further notifications about a process which has already been reaped.
+    pub fn from_std(child: process::Child, handle: &Handle) -> io::Result<Child> {
+        imp::from_std(child, handle, imp::Orphans::global()).map(SpawnedChild::into_child)
+    }
+
+    /// Returns the OS-assigned process identifier associated with this child.
+    ///
+    /// Note that this keeps returning the same identifier even after the
+    /// child has been reaped, at which point the OS may reuse it for an
+    /// unrelated process.
+    #[deprecated(note = "may refer to an unrelated process once the child has been reaped, \
+                         use `current_id` instead")]
+    pub fn id(&self) -> u32 {
+        self.child.inner.id()
+    }
+
+    /// Returns the OS-assigned process identifier associated with this child,
+    /// or `None` once the child has exited and been reaped.
+    ///
+    /// On Unix, a child is reaped once its exit status has been observed,
+    /// e.g. by polling the `Child` to completion or via `try_wait`, after
+    /// which its identifier may be reused for an unrelated process at any
+    /// moment. On Windows, the identifier remains reserved for as long as
+    /// the `Child` exists, so it is always returned.
+    pub fn current_id(&self) -> Option<u32> {
+        self.child.inner.current_id()
+    }
+
+    /// Returns the time the child was started at, which together with its id
+    /// uniquely identifies the process even across reuse of the id.
+    ///
+    /// This is read once when the child is spawned (or adopted via
+    /// `from_std`), so it remains available after the child has exited. The
+    /// value is only meaningful when compared with other start times of the
+    /// same platform: on Linux it is the `starttime` field of
+    /// `/proc/<pid>/stat` (in clock ticks since boot), and on Windows the
+    /// creation time from `GetProcessTimes` (in 100-nanosecond intervals since
+    /// 1601). It is `None` on other platforms, or if it couldn't be read.
+    ///
+    /// On Linux it is also `None` for a child adopted via `from_std` after it
+    /// had already been reaped, provided its id hasn't been reused by another
+    /// child of this process since. Such a child should never be adopted in
+    /// the first place, though.
+    pub fn start_time(&self) -> Option<u64> {
+        self.start_time
+    }
+
+    /// Returns the process identifier of the child's parent, or `None` once
+    /// the child has exited and been reaped.
+    ///
+    /// A child is never reparented while its parent is alive (only its own
+    /// descendants are, e.g. to a `PR_SET_CHILD_SUBREAPER` subreaper), so this
+    /// is always our own id as returned by `current_pid`. It is mostly useful
+    /// as a sanity check when debugging reaping in environments with
+    /// subreapers: any process reaped through this `Child` was a child of
+    /// ours, rather than a reparented descendant.
+    #[cfg(unix)]
+    pub fn ppid(&self) -> Option<u32> {
+        self.current_id().map(|_| current_pid())
+    }
+
+    /// Attempts to classify why the child exited, if it has already exited.
+    ///
+    /// Unlike the plain exit status, this distinguishes children which were
+    /// killed through this `Child` (e.g. via `kill`, or when escalating in
+    /// `terminate_graceful`) from children which were killed by someone
+    /// else. See `ExitReason` for details.
+    pub fn exit_reason(&mut self) -> io::Result<Option<ExitReason>> {
+        let killed = self.killed_by_us;
+        let status = match self.try_status()? {
+            Some(status) => status,
+            None => return Ok(None),
+        };
+
+        #[cfg(target_os = "linux")]
+        let oom_killed = match (self.oom_kills, imp::oom_kills()) {
+            (Some(before), Some(after)) => after > before,
+            _ => false,
+        };
+        #[cfg(not(target_os = "linux"))]
+        let oom_killed = false;
+
+        Ok(Some(ExitReason::new(status, killed, oom_killed)))
+    }
+
+    /// Returns whether this `Child` has ever delivered a signal which may
+    /// have caused the child to exit.
+    ///
+    /// This is the case once the child has been killed via `kill` or
+    /// `kill_group`, sent a signal via `signal` whose default action is to
+    /// terminate the process (so e.g. not `SIGCONT` or `SIGSTOP`), or asked to
+    /// terminate via `terminate_graceful` (whether or not that escalated to
+    /// killing it).
+    pub fn was_killed_by_us(&self) -> bool {
+        self.killed_by_us
+    }
+
+    /// Attempts to collect the exit status of the child if it has already
+    /// exited.
+    ///
+    /// This function will not block the calling thread and will only check
+    /// to see if the child process has exited or not. If the child has exited
+    /// then on Unix the process id is reaped. Unlike polling the `Child` as a
+    /// future, this does not arrange for the current task to be notified when
+    /// the child exits, so it may be called outside of a task entirely, for
+    /// example to cheaply check on a child's liveness in a supervisory loop.
+    ///
+    /// The `Child` may still be polled as a future afterwards, in which case
+    /// it will resolve to the same exit status.
+    pub fn try_status(&mut self) -> io::Result<Option<ExitStatus>> {
+        let ret = self.child.inner.try_wait()?;
+
+        if let Some(status) = ret {
+            // Avoid the overhead of trying to kill a reaped process
+            self.child.kill_on_drop = false;
+            self.exit_watch.publish(status);
+        }
+
+        Ok(ret)
+    }
+
+    /// Publishes the exit status of the child if it was reaped without being
+    /// waited on through this `Child`.
+    #[cfg(unix)]
+    fn publish_reaped(&mut self) {
+        if let Some(status) = self.child.inner.status() {
+            // Avoid the overhead of trying to kill a reaped process
+            self.child.kill_on_drop = false;
+            self.exit_watch.publish(status);
+        }
+    }
+
+    /// Polls for the child's exit status, arranging for the current task to be
+    /// notified once the child exits.
+    ///
+    /// This is the same as polling the `Child` as a future, but is usable
+    /// without the `Future` trait in scope, and without having to juggle the
+    /// `Child` as a future when embedding it in a custom `Future::poll`
+    /// implementation (or a `future::poll_fn` closure) which also drives its
+    /// stdio. Like the future, once the child has exited this keeps returning
+    /// the same exit status.
+    pub fn poll_exit(&mut self) -> Poll<ExitStatus, io::Error> {
+        let status = try_ready!(self.child.poll());
+        self.exit_watch.publish(status);
+        Ok(Async::Ready(status))
+    }
+
+    /// Returns the raw status the child was reaped with, as reported by
+    /// `waitpid(2)`, or `None` if it hasn't been reaped yet.
+    ///
+    /// The status is recorded once the child's exit has been observed, e.g.
+    /// by polling the `Child` to completion or via `try_status`. It can be
+    /// decoded with the `WIFEXITED` family of macros, e.g. to inspect bits
+    /// which `ExitStatus` doesn't expose, or to mirror exactly how a shell
+    /// would compute `$?`.
+    #[cfg(unix)]
+    pub fn raw_wait_status(&self) -> Option<c_int> {
+        self.child.inner.raw_wait_status()
+    }
+
+    /// Returns the exit status of the child if its exit has already been
+    /// observed, e.g. by polling the `Child` to completion or via
+    /// `try_status`, without checking on the child itself.
+    ///
+    /// The status is cached in a cell shared with any handles returned from
+    /// `exit_handle`, which can query it through `ExitHandle::status`. This
+    /// way components which don't own the `Child` can learn the status even
+    /// once the `Child` (as a future) has been consumed by whoever awaited it.
+    pub fn exit_status_cached(&self) -> Option<ExitStatus> {
+        self.exit_watch.status()
+    }
+
+    /// Returns a handle which can be used to wait on the child's exit without
+    /// owning the `Child`.
+    ///
+    /// The handle is a future which can be cloned any number of times (and
+    /// moved to other tasks), with each clone resolving to the child's exit
+    /// status once the child exits. Note that the handle does not drive the
+    /// child itself: the `Child` still needs to be polled (or have its status
+    /// collected via `try_status`) for the exit to be observed. If the `Child`
+    /// is dropped before then (including via `forget`), the handle resolves
+    /// to an error.
+    pub fn exit_handle(&self) -> ExitHandle {
+        self.exit_watch.handle()
+    }
+
+    /// Forces the child to exit.
+    ///
+    /// This is equivalent to sending a SIGKILL on unix platforms.
+    ///
+    /// Killing a child which has already exited succeeds, so that it is safe
+    /// to kill a child which may have exited on its own in the meantime. In
+    /// that case the child isn't considered to have been killed by us (see
+    /// `was_killed_by_us`), although any other processes which are to be
+    /// killed along with it (see `SpawnHandle::kill_process_tree`) still are.
+    pub fn kill(&mut self) -> io::Result<()> {
+        let exited = self.try_status()?.is_some();
+
+        match self.child.kill() {
+            Ok(()) => {},
+            // The child exited right after we checked on it
+            Err(ref e) if imp::is_already_exited(e) => return Ok(()),
+            Err(e) => return Err(e),
+        }
+
+        if !exited {
+            self.killed_by_us = true;
         }
+        Ok(())
     }
-}
 
+    /// Sends the specified signal to the child.
+    ///
+    /// This can be used to deliver signals such as `SIGHUP` or `SIGUSR1` to
+    /// a long-running child, for example to ask it to reload its
+    /// configuration.
+    ///
+    /// An error is returned if the child has already exited and been reaped,
+    /// as its pid may have since been recycled by the OS.
+    #[cfg(unix)]
+    pub fn signal(&mut self, signal: c_int) -> io::Result<()> {
+        let ret = self.child.inner.signal(signal);
+        // The child is checked on before being signalled, which may have
+        // reaped it
+        self.publish_reaped();
+        ret?;
+        if imp::terminates_by_default(signal) {
+            self.killed_by_us = true;
+        }
+        Ok(())
+    }
 
-impl<T: Future + Kill> Future for ChildDropGuard<T> {
-    type Item = T::Item;
-    type Error = T::Error;
+    /// Forces the child and every other process in its process group to exit.
+    ///
+    /// This is intended for children spawned with
+    /// `CommandConfigExt::new_process_group`, and sends a SIGKILL to the group led
+    /// by the child. The child itself is still reaped as usual once it exits.
+    ///
+    /// An error is returned if no such process group exists, for example if
+    /// the child was not spawned in a new process group. An error is also
+    /// returned if the child has already exited and been reaped, as the group
+    /// id may have since been recycled by the OS once the rest of the group
+    /// exited too. To kill processes which outlive the child within its group,
+    /// see `SpawnHandle::kill_process_tree`.
+    #[cfg(unix)]
+    pub fn kill_group(&mut self) -> io::Result<()> {
+        self.child.inner.kill_group()?;
+        self.killed_by_us = true;
+        Ok(())
+    }
 
-    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
-        let ret = self.inner.poll();
+    /// Returns a future which gracefully terminates the child, forcefully
+    /// killing it if it does not exit within `timeout`.
+    ///
+    /// On Unix platforms this first delivers a `SIGTERM` to give the child a
+    /// chance to clean up, and only escalates to a `SIGKILL` if the child is
+    /// still running once `timeout` has elapsed. If the child exits on its own
+    /// in the meantime it is reaped normally and no further signals are sent,
+    /// which avoids signalling a pid which may have since been recycled. On
+    /// Windows this is equivalent to calling `kill` and waiting for the child.
+    ///
+    /// The returned future resolves to the child's exit status along with
+    /// whether escalation was necessary. It must be polled within a runtime
+    /// which provides a timer.
+    pub fn terminate_graceful(&mut self, timeout: Duration) -> TerminateGraceful<'_> {
+        TerminateGraceful::new(self, timeout)
+    }
 
-        if let Ok(Async::Ready(_)) = ret {
-            // Avoid the overhead of trying to kill a reaped process
-            self.kill_on_drop = false;
-        }
+    /// Consumes the child, returning a future which gracefully terminates it
+    /// like `terminate_graceful`, and resolves to its exit status.
+    ///
+    /// Unlike `terminate_graceful`, the child is asked to terminate (i.e. sent
+    /// a `SIGTERM` on Unix) right away rather than once the future is first
+    /// polled, and `timeout` starts counting down then. Since the future owns
+    /// the child, dropping it before it resolves drops the child, which (as
+    /// usual) kills the child unless `kill_on_drop` was disabled. This makes
+    /// it suitable for racing against other events, e.g. via `select`, as
+    /// the child is never left running without having been signalled.
+    ///
+    /// It must be polled within a runtime which provides a timer.
+    pub fn kill_timeout(self, timeout: Duration) -> KillTimeout {
+        KillTimeout::new(self, timeout)
+    }
 
-        ret
+    /// Kills the child, returning a future of everything it wrote to its
+    /// stderr, e.g. to preserve any diagnostics it printed before dying.
+    ///
+    /// The stderr handle is taken out of this `Child` and read until it is
+    /// closed, which normally happens as soon as the child has died. Should
+    /// it have been inherited by another process which keeps it open, reading
+    /// stops once `timeout` has elapsed since the child was killed (when the
+    /// future is first polled) and only the data read so far is returned. The
+    /// child is reaped as well, and is not killed if it has already exited.
+    /// If stderr wasn't captured with `Stdio::piped()` the returned data is
+    /// always empty.
+    ///
+    /// The returned future must be polled within a runtime which provides a
+    /// timer.
+    pub fn kill_and_collect_stderr(&mut self, timeout: Duration) -> KillAndCollectStderr<'_> {
+        KillAndCollectStderr::new(self, timeout)
     }
-}
 
-/// Representation of a child process spawned onto an event loop.
-///
-/// This type is also a future which will yield the `ExitStatus` of the
-/// underlying child process. A `Child` here also provides access to information
-/// like the OS-assigned identifier and the stdio streams.
-///
-/// > **Note**: The behavior of `drop` on a child in this crate is *different
-/// > than the behavior of the standard library*. If a `tokio_process::Child` is
-/// > dropped before the process finishes then the process will be terminated.
-/// > In the standard library, however, the process continues executing. This is
-/// > done because futures in general take `drop` as a sign of cancellation, and
-/// > this `Child` is itself a future. If you'd like to run a process in the
-/// > background, though, you may use the `forget` method.
-#[must_use = "futures do nothing unless polled"]
-#[derive(Debug)]
-pub struct Child {
-    child: ChildDropGuard<imp::Child>,
-    stdin: Option<ChildStdin>,
-    stdout: Option<ChildStdout>,
-    stderr: Option<ChildStderr>,
-}
+    /// Returns a future which waits for the child to exit, giving up once
+    /// `timeout` has elapsed.
+    ///
+    /// The returned future resolves to `Some(status)` if the child exits in
+    /// time, or to `None` otherwise. The child is left running in the latter
+    /// case, and it is up to the caller to decide whether to keep waiting on
+    /// it or to kill it. Unlike `wait_with_output_timeout`, the child's stdio
+    /// handles are left untouched.
+    ///
+    /// The returned future must be polled within a runtime which provides a
+    /// timer.
+    pub fn with_timeout(&mut self, timeout: Duration) -> WithTimeout<'_> {
+        WithTimeout::new(self, timeout)
+    }
 
-impl Child {
-    /// Returns the OS-assigned process identifier associated with this child.
-    pub fn id(&self) -> u32 {
-        self.child.inner.id()
+    /// Returns a stream of the state changes of the child, ending once the
+    /// child has exited.
+    ///
+    /// By default the only item of the stream is `ChildEvent::Exited`, which
+    /// the `Child` also resolves to as a future. Children spawned through a
+    /// `SpawnHandle` with `track_job_control` enabled additionally report
+    /// whenever they are stopped or continued, which is useful for
+    /// supervisors implementing job control. Job control is not available on
+    /// Windows.
+    pub fn status_stream(&mut self) -> StatusStream<'_> {
+        StatusStream::new(self)
     }
 
-    /// Forces the child to exit.
+    /// Controls whether the child is killed when this `Child` is dropped.
     ///
-    /// This is equivalent to sending a SIGKILL on unix platforms.
-    pub fn kill(&mut self) -> io::Result<()> {
-        self.child.kill()
+    /// By default a `Child` which is dropped before the process has exited
+    /// will kill the process, mirroring the cancellation semantics of other
+    /// futures. Passing `false` here allows the process to continue running
+    /// once the `Child` is dropped; it will still be reaped in the background
+    /// once it exits so it does not linger as a zombie.
+    ///
+    /// Note that a process which has already exited and been reaped is never
+    /// killed, regardless of this setting.
+    pub fn kill_on_drop(&mut self, kill_on_drop: bool) -> &mut Self {
+        self.child.set_kill_on_drop(kill_on_drop);
+        self
     }
 
     /// Returns a handle for writing to the child's stdin, if it has been
     /// captured
+    ///
+    /// Only streams configured with `Stdio::piped()` are captured and made
+    /// available as asynchronous handles. Any other stream (e.g. one which is
+    /// inherited, set to `Stdio::null()`, or redirected to a file) is handed
+    /// directly to the child, and its handle here is always `None`. This
+    /// doesn't affect waiting on the child in any way, so streams may be
+    /// captured selectively, e.g. to capture stderr while stdout goes
+    /// straight to the parent's own stdout.
+    ///
+    /// The same goes for descriptors passed to the child via `Stdio::from`,
+    /// such as one end of a socket pair or a pipe created by the caller:
+    /// these are never registered with the event loop nor touched in any
+    /// other way, so whoever holds the other end remains responsible for
+    /// driving it (and may do so asynchronously on their own). Note that the
+    /// `Command` keeps its copy of such a descriptor open until the `Command`
+    /// itself is dropped.
     pub fn stdin(&mut self) -> &mut Option<ChildStdin> {
         &mut self.stdin
     }
 
-    /// Returns a handle for writing to the child's stdout, if it has been
-    /// captured
+    /// Splits the child into its captured stdio handles and the `Child`
+    /// itself, which remains usable for controlling and waiting on the
+    /// process.
+    ///
+    /// This allows the handles to be moved into different tasks independently
+    /// of each other and of the `Child`. The returned `Child` no longer has
+    /// any stdio handles of its own.
+    pub fn split(mut self) -> (Child, Option<ChildStdin>, Option<ChildStdout>, Option<ChildStderr>) {
+        let stdin = self.stdin.take();
+        let stdout = self.stdout.take();
+        let stderr = self.stderr.take();
+        (self, stdin, stdout, stderr)
+    }
+
+    /// Closes the child's stdin handle, if it has been captured, so that the
+    /// child sees EOF.
+    ///
+    /// The child's other handles and its exit status remain available as
+    /// usual. Closing stdin more than once (or when it was never captured)
+    /// does nothing.
+    ///
+    /// An error is returned if the handle could not be deregistered from its
+    /// event loop (e.g. because the event loop has gone away), though the
+    /// handle is closed regardless.
+    pub fn close_stdin(&mut self) -> io::Result<()> {
+        match self.stdin.take() {
+            Some(stdin) => stdin.inner.into_inner().map(drop),
+            None => Ok(()),
+        }
+    }
+
+    /// Closes all of the child's captured stdio handles, deregistering them
+    /// from their event loop.
+    ///
+    /// This is what `status_async` does before waiting on the child, and
+    /// should be done before waiting on a child whose streams aren't needed:
+    /// a child blocked reading from a pipe which is never closed, or writing
+    /// to a full pipe which nobody reads from, never exits. Any data the
+    /// child writes afterwards is discarded.
+    ///
+    /// All handles are closed even if deregistering one of them fails, in
+    /// which case the first error is returned.
+    pub fn close_all_stdio(&mut self) -> io::Result<()> {
+        let stdin = self.close_stdin();
+        let stdout = match self.stdout.take() {
+            Some(stdout) => stdout.inner.into_inner().map(drop),
+            None => Ok(()),
+        };
+        let stderr = match self.stderr.take() {
+            Some(stderr) => stderr.inner.into_inner().map(drop),
+            None => Ok(()),
+        };
+
+        stdin.and(stdout).and(stderr)
+    }
+
+    /// Returns a handle for reading from the child's stdout, if it has been
+    /// captured (see `stdin` for which streams are captured)
     pub fn stdout(&mut self) -> &mut Option<ChildStdout> {
         &mut self.stdout
     }
 
-    /// Returns a handle for writing to the child's stderr, if it has been
-    /// captured
+    /// Returns a handle for reading from the child's stderr, if it has been
+    /// captured (see `stdin` for which streams are captured)
     pub fn stderr(&mut self) -> &mut Option<ChildStderr> {
         &mut self.stderr
     }
 
+    /// Returns whether reading from the child's stdout has reached EOF, i.e.
+    /// whether the child (and any process sharing its stdout) has closed it.
+    ///
+    /// This can be the case well before the child exits, and tells apart a
+    /// child which is merely not writing anything at the moment from one
+    /// which won't write anything anymore. EOF is tracked even once the
+    /// handle has been taken out of the `Child`, but only as observed by
+    /// reading from it: this is `false` if stdout was never captured, or
+    /// dropped before EOF was read.
+    pub fn stdout_at_eof(&self) -> bool {
+        self.stdout_eof.get()
+    }
+
+    /// Returns whether reading from the child's stderr has reached EOF, see
+    /// `stdout_at_eof` for details.
+    pub fn stderr_at_eof(&self) -> bool {
+        self.stderr_eof.get()
+    }
+
+    /// Takes the child's stdin handle, returning a future which writes all
+    /// data produced by `source` to it.
+    ///
+    /// Each item of `source` is a chunk of data to write, such as a `Vec<u8>`
+    /// or `Bytes`. The future resolves once `source` is exhausted and all of
+    /// its data has been written, at which point stdin is closed so that the
+    /// child sees EOF. The future resolves to an error if stdin was not
+    /// captured (or has already been taken out of this `Child`).
+    ///
+    /// Note that a child may stop reading its stdin once the pipe to its
+    /// stdout is full, so any captured output should be read concurrently
+    /// with (e.g. joined with) this future to avoid a deadlock.
+    pub fn feed_stdin<S>(&mut self, source: S) -> FeedStdin<S>
+        where S: Stream<Error = io::Error>,
+              S::Item: AsRef<[u8]>,
+    {
+        FeedStdin::new(self.stdin.take(), source)
+    }
+
+    /// Takes the child's stdout handle, returning a stream over the lines of
+    /// text it writes.
+    ///
+    /// Each line is yielded without its trailing newline, and the stream ends
+    /// once the child closes its stdout. Returns `None` if stdout was not
+    /// captured (or has already been taken out of this `Child`).
+    pub fn stdout_lines(&mut self) -> Option<Lines<ChildStdout>> {
+        self.stdout.take().map(Lines::new)
+    }
+
+    /// Takes the child's stdout handle, returning it wrapped in a buffered
+    /// reader with a buffer of `capacity` bytes.
+    ///
+    /// The reader implements both `BufRead` and `AsyncRead`, so it can be
+    /// used directly with e.g. `tokio_io::io::read_until` or
+    /// `tokio_io::io::lines`. Returns `None` if stdout was not captured (or
+    /// has already been taken out of this `Child`).
+    ///
+    /// # Panics
+    ///
+    /// Panics if `capacity` is zero.
+    pub fn stdout_buffered(&mut self, capacity: usize) -> Option<BufReader<ChildStdout>> {
+        assert!(capacity > 0, "buffer capacity must be non-zero");
+        self.stdout.take().map(|stdout| BufReader::with_capacity(capacity, stdout))
+    }
+
+    /// Takes the child's stdout handle, returning a stream over the raw data
+    /// it writes in chunks of at most `chunk_size` bytes.
+    ///
+    /// This is useful for processing large (or binary) output as it arrives
+    /// without buffering all of it. The stream ends once the child closes its
+    /// stdout, and any error reading from it is yielded by the stream. Returns
+    /// `None` if stdout was not captured (or has already been taken out of
+    /// this `Child`).
+    ///
+    /// # Panics
+    ///
+    /// Panics if `chunk_size` is zero.
+    pub fn stdout_chunks(&mut self, chunk_size: usize) -> Option<Chunks<ChildStdout>> {
+        self.stdout.take().map(|stdout| Chunks::new(stdout, chunk_size))
+    }
+
+    /// Takes the stdout handle of this child and the stdin handle of `other`,
+    /// returning a future which moves everything this child writes into
+    /// `other`, like a shell pipeline.
+    ///
+    /// On Linux, the data is moved between the pipes with `splice(2)`, which
+    /// avoids copying it through userspace. Elsewhere (or should splicing
+    /// not be supported for the handles) the data is copied instead.
+    ///
+    /// The future resolves to the number of bytes moved once this child
+    /// closes its stdout, after which the stdin of `other` is closed so that
+    /// it sees EOF. It fails if either handle was not captured (or has
+    /// already been taken out of its `Child`).
+    pub fn splice_output_to(&mut self, other: &mut Child) -> SpliceOutput {
+        SpliceOutput::new(self.stdout.take(), other.stdin.take())
+    }
+
+    /// Takes the child's stdout and stderr handles, returning a single reader
+    /// which interleaves the data written to both of them.
+    ///
+    /// Data is yielded in roughly the order the child wrote it, though since
+    /// the two streams are distinct pipes their relative ordering cannot be
+    /// perfectly preserved. The returned reader reaches EOF once both streams
+    /// have been closed.
+    ///
+    /// Returns `None` if neither stdout nor stderr were captured (or if they
+    /// have already been taken out of this `Child`).
+    pub fn merged_output(&mut self) -> Option<MergedOutput> {
+        let stdout = self.stdout.take();
+        let stderr = self.stderr.take();
+
+        if stdout.is_none() && stderr.is_none() {
+            return None;
+        }
+
+        Some(MergedOutput::new(stdout, stderr))
+    }
+
+    /// Takes the child's stdout and stderr handles, returning a stream over
+    /// the data written to both of them, with each chunk tagged with the
+    /// stream it came from.
+    ///
+    /// This is like `merged_output`, but keeps the two streams apart, e.g. to
+    /// color them differently or route them to separate destinations while
+    /// still handling them in roughly the order they were written. The
+    /// stream ends once both stdout and stderr have been closed.
+    ///
+    /// Returns `None` if neither stdout nor stderr were captured (or if they
+    /// have already been taken out of this `Child`).
+    pub fn output_events(&mut self) -> Option<OutputEvents> {
+        let stdout = self.stdout.take();
+        let stderr = self.stderr.take();
+
+        if stdout.is_none() && stderr.is_none() {
+            return None;
+        }
+
+        Some(OutputEvents::new(stdout, stderr))
+    }
+
     /// Returns a future that will resolve to an `Output`, containing the exit
     /// status, stdout, and stderr of the child process.
     ///
@@ -524,6 +2176,198 @@ impl Child {
         }
     }
 
+    /// Like `wait_with_output`, but also returns the resources the child
+    /// used, e.g. to measure the cost of the commands run by a build tool.
+    ///
+    /// The child is reaped via `wait4(2)` in order to collect the CPU time it
+    /// spent and its peak memory usage, along with the wall-clock time from
+    /// spawning it (or adopting it via `from_std`) until it was reaped. Note
+    /// that the resource usage only covers the child itself and those of its
+    /// descendants it has waited for.
+    ///
+    /// The future fails if the child was already reaped before, e.g. through
+    /// `try_status`, as its resource usage cannot be collected then.
+    #[cfg(unix)]
+    pub fn wait_with_output_rusage(self) -> WaitWithOutputRusage {
+        WaitWithOutputRusage::new(self)
+    }
+
+    /// Like `wait_with_output`, but gives up on the child if it has not
+    /// finished within `timeout`.
+    ///
+    /// The returned future resolves to `Ok(Output)` if the child exits and
+    /// closes its stdout/stderr handles before the timeout elapses. Otherwise
+    /// it resolves to `Err(PartialOutput)` which contains any output captured
+    /// up to that point, rather than discarding it.
+    ///
+    /// When the timeout elapses the child is dropped, meaning it will be
+    /// killed unless `kill_on_drop(false)` was previously called on it, in
+    /// which case it is left running.
+    ///
+    /// The returned future must be polled within a runtime which provides a
+    /// timer.
+    pub fn wait_with_output_timeout(self, timeout: Duration) -> WaitWithOutputTimeout {
+        WaitWithOutputTimeout::new(self, timeout)
+    }
+
+    /// Like `wait_with_output`, but bounds the amount of output captured from
+    /// each of stdout and stderr to `max_bytes`.
+    ///
+    /// If the child writes more than `max_bytes` to either stream, reading
+    /// from that stream stops (closing our end of the pipe so the child can't
+    /// block writing to it), the child is killed, and only the first
+    /// `max_bytes` are retained. The returned `LimitedOutput` indicates
+    /// whether this happened.
+    pub fn wait_with_output_limited(self, max_bytes: usize) -> WaitWithOutputLimited {
+        WaitWithOutputLimited::new(self, max_bytes)
+    }
+
+    /// Like `wait_with_output_limited`, but bounds the amount of output
+    /// captured from stdout and stderr *combined* to `max_total` bytes.
+    ///
+    /// This bounds the memory used for the output without having to know
+    /// which of the streams the child will write the most to. Once the child
+    /// has written more than `max_total` bytes to the two streams together,
+    /// reading from the stream which exceeded the limit stops, the child is
+    /// killed, and only the output read up to that point is retained, i.e.
+    /// `max_total` bytes split between the streams in roughly the order they
+    /// were written. Any output still pending on the other stream is then
+    /// discarded as well.
+    pub fn wait_with_output_limited_total(self, max_total: usize) -> WaitWithOutputLimited {
+        WaitWithOutputLimited::with_total(self, max_total)
+    }
+
+    /// Like `wait_with_output`, but only retains the last `tail_bytes` of
+    /// each of stdout and stderr.
+    ///
+    /// Unlike `wait_with_output_limited`, which keeps the beginning of the
+    /// output and stops reading once the limit is exceeded, both streams are
+    /// read all the way to EOF, so the child never blocks writing to them and
+    /// is left to exit on its own. This suits diagnosing a chatty child, e.g.
+    /// showing the last few kilobytes it wrote before it crashed, while
+    /// bounding the memory used for its output.
+    pub fn wait_with_output_tail(self, tail_bytes: usize) -> WaitWithOutputTail {
+        WaitWithOutputTail::new(self, tail_bytes)
+    }
+
+    /// Returns a future which resolves once the child has exited
+    /// successfully, and fails otherwise.
+    ///
+    /// This suits failing a task whenever a child it runs fails: the error
+    /// (of kind `Other`) describes how the child exited, i.e. its exit code,
+    /// or on Unix the signal which terminated it. Errors waiting on the child
+    /// are passed through as is.
+    pub fn success(self) -> Success {
+        Success::new(self)
+    }
+
+    /// Splits the child into a stream over its output and a future resolving
+    /// to its exit status, which can be polled independently of each other.
+    ///
+    /// The stream yields chunks of the child's stdout and stderr tagged with
+    /// the stream they came from, like `output_events`. The future resolves
+    /// once the child has exited *and* the stream has ended or been dropped,
+    /// so by the time the exit status is known all of the output has been
+    /// seen, even if the child exited before it was read. This packages up
+    /// reading the output concurrently with waiting on the child, which is
+    /// needed to keep a child from blocking on a full pipe.
+    ///
+    /// Both halves have to be polled for the future to resolve, e.g. by
+    /// joining them or spawning them onto an executor separately, as the
+    /// future otherwise waits on the stream forever. Dropping the stream
+    /// stops reading the output and closes our ends of the pipes.
+    ///
+    /// Like `wait_with_output`, this closes the stdin handle of the child,
+    /// and only streams which were captured with `Stdio::piped()` are read.
+    pub fn run(self) -> (RunOutput, RunExit) {
+        run::run(self)
+    }
+
+    /// Like `wait_with_output`, but hands the output of the child to
+    /// `on_stdout` and `on_stderr` as it arrives instead of buffering it.
+    ///
+    /// Each callback is called with every chunk of data read from its stream,
+    /// and returns a future which must complete before any more data is read
+    /// from that stream (applying backpressure to the child). This suits
+    /// forwarding live logs or tracking progress without holding on to all
+    /// of the output. The returned future resolves to the exit status of the
+    /// child once it has exited and all of its output has been handled.
+    ///
+    /// Should either callback (or reading from either stream) fail, all
+    /// reading stops and the returned future fails with that error.
+    ///
+    /// Like `wait_with_output`, this closes the stdin handle of the child,
+    /// and only streams which were captured with `Stdio::piped()` are read.
+    pub fn wait_with_output_streaming<F, U, G, V>(self, on_stdout: F, on_stderr: G)
+        -> WaitWithOutputStreaming<F, U, G, V>
+        where F: FnMut(Bytes) -> U,
+              U: IntoFuture<Item = (), Error = io::Error>,
+              G: FnMut(Bytes) -> V,
+              V: IntoFuture<Item = (), Error = io::Error>,
+    {
+        WaitWithOutputStreaming::new(self, on_stdout, on_stderr)
+    }
+
+    /// Converts this `Child` into a `std::process::Child` so that it may be
+    /// used with synchronous code.
+    ///
+    /// Any stdio handles which have not been taken out of this `Child` are
+    /// deregistered from their event loop and restored to blocking mode before
+    /// being placed on the returned child. Ownership of the process is
+    /// transferred entirely: it will no longer be killed if dropped, nor will
+    /// it be reaped in the background, so the caller is responsible for
+    /// eventually waiting on it.
+    ///
+    /// On Windows the event loop reads ahead from captured stdout and stderr
+    /// in the background. Any output it has already read ahead, but which was
+    /// not yet read out of this `Child`, is lost in the conversion. Converting
+    /// the handles requires Windows 8.1 or later.
+    ///
+    /// If an error occurs while converting the stdio handles the `Child` is
+    /// dropped as usual. See `into_std_nonblocking` for keeping the handles
+    /// in nonblocking mode instead.
+    pub fn into_std(self) -> io::Result<process::Child> {
+        self.into_std_with(false)
+    }
+
+    /// Like `into_std`, but leaves any stdio handles in nonblocking mode.
+    ///
+    /// This is meant for callers which do their own readiness handling on
+    /// the handles, e.g. by registering them with another event loop, and
+    /// saves switching them back and forth. Note that the handles must not
+    /// be used as is with blocking reads or writes (such as through
+    /// `std::process::Child::wait_with_output`), which would fail with
+    /// `WouldBlock` errors instead of waiting for the child.
+    ///
+    /// On Windows the handles are overlapped named pipes either way, so this
+    /// behaves the same as `into_std`.
+    pub fn into_std_nonblocking(self) -> io::Result<process::Child> {
+        self.into_std_with(true)
+    }
+
+    fn into_std_with(self, nonblocking: bool) -> io::Result<process::Child> {
+        let Child { child, stdin, stdout, stderr, .. } = self;
+
+        let stdin = match stdin {
+            Some(io) => Some(imp::into_std_stdio(io.inner, nonblocking)?),
+            None => None,
+        };
+        let stdout = match stdout {
+            Some(io) => Some(imp::into_std_stdio(io.inner, nonblocking)?),
+            None => None,
+        };
+        let stderr = match stderr {
+            Some(io) => Some(imp::into_std_stdio(io.inner, nonblocking)?),
+            None => None,
+        };
+
+        let mut child = child.into_inner().into_std();
+        child.stdin = stdin;
+        child.stdout = stdout;
+        child.stderr = stderr;
+        Ok(child)
+    }
+
     /// Drop this `Child` without killing the underlying process.
     ///
     /// Normally a `Child` is killed if it's still alive when dropped, but this
@@ -565,10 +2409,32 @@ impl Future for Child {
     type Error = io::Error;
 
     fn poll(&mut self) -> Poll<ExitStatus, io::Error> {
-        self.child.poll()
+        self.poll_exit()
+    }
+}
+
+/// Writes to the child's stdin, failing if it was not captured (or has
+/// already been taken out of this `Child`).
+impl Write for Child {
+    fn write(&mut self, bytes: &[u8]) -> io::Result<usize> {
+        self.stdin.as_mut().ok_or_else(no_stdin)?.write(bytes)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.stdin.as_mut().ok_or_else(no_stdin)?.flush()
+    }
+}
+
+impl AsyncWrite for Child {
+    fn shutdown(&mut self) -> Poll<(), io::Error> {
+        self.stdin.as_mut().ok_or_else(no_stdin)?.shutdown()
     }
 }
 
+fn no_stdin() -> io::Error {
+    io::Error::new(io::ErrorKind::NotConnected, "child has no stdin to write to")
+}
+
 /// Future returned from the `Child::wait_with_output` method.
 ///
 /// This future will resolve to the standard library's `Output` type which
@@ -646,11 +2512,42 @@ impl Future for OutputAsync {
     }
 }
 
+/// The stdio handles of a child process, detached from the `Child` itself.
+///
+/// Each handle is only present if the corresponding stream of the child was
+/// configured to be piped.
+#[derive(Debug)]
+pub struct ChildStdio {
+    /// The handle for writing to the child's stdin.
+    pub stdin: Option<ChildStdin>,
+    /// The handle for reading from the child's stdout.
+    pub stdout: Option<ChildStdout>,
+    /// The handle for reading from the child's stderr.
+    pub stderr: Option<ChildStderr>,
+}
+
 /// The standard input stream for spawned children.
 ///
 /// This type implements the `Write` trait to pass data to the stdin handle of
 /// a child process. Note that this type is also "futures aware" meaning that it
 /// is both (a) nonblocking and (b) will panic if used off of a future's task.
+///
+/// Writing to a child which has closed its stdin (for example because it has
+/// exited) fails with an error of kind `io::ErrorKind::BrokenPipe` on all
+/// platforms, which can be used to tell this case apart from other errors.
+/// This includes writes which are already pending (e.g. on a full pipe) when
+/// the child goes away: closing the pipe wakes them up, rather than leaving
+/// them waiting for the pipe to become writable forever.
+///
+/// Writes are cancellation safe: no data is buffered by this type itself, so
+/// a write which isn't ready hasn't written anything, and a write which
+/// succeeds has handed exactly the bytes it reports to the child. Dropping a
+/// future which is writing to the stream (e.g. `tokio_io::io::write_all`)
+/// part way through thus leaves the stream consistent: the child receives
+/// some prefix of the data, directly followed by whatever is written next.
+/// Since such futures don't report the length of that prefix, protocols which
+/// rely on framing should either not abandon writes, or track their progress
+/// by calling `write` themselves.
 #[derive(Debug)]
 pub struct ChildStdin {
     inner: imp::ChildStdin,
@@ -665,6 +2562,7 @@ pub struct ChildStdin {
 #[derive(Debug)]
 pub struct ChildStdout {
     inner: imp::ChildStdout,
+    eof: EofFlag,
 }
 
 /// The standard error stream for spawned children.
@@ -676,11 +2574,39 @@ pub struct ChildStdout {
 #[derive(Debug)]
 pub struct ChildStderr {
     inner: imp::ChildStderr,
+    eof: EofFlag,
+}
+
+/// Tracks whether a stdio handle has reached EOF, shared between the handle
+/// and its `Child` so that the latter can tell even once the handle has been
+/// taken out of it.
+#[derive(Debug, Clone, Default)]
+struct EofFlag(Arc<AtomicBool>);
+
+impl EofFlag {
+    fn get(&self) -> bool {
+        self.0.load(Ordering::SeqCst)
+    }
+
+    fn set(&self) {
+        self.0.store(true, Ordering::SeqCst)
+    }
+
+    /// Records EOF if `ret` is the result of a read which reached it.
+    fn observe(&self, bytes: &[u8], ret: io::Result<usize>) -> io::Result<usize> {
+        if let Ok(0) = ret {
+            if !bytes.is_empty() {
+                self.set();
+            }
+        }
+
+        ret
+    }
 }
 
 impl Write for ChildStdin {
     fn write(&mut self, bytes: &[u8]) -> io::Result<usize> {
-        self.inner.write(bytes)
+        imp::write_stdin(&mut self.inner, bytes)
     }
 
     fn flush(&mut self) -> io::Result<()> {
@@ -696,26 +2622,109 @@ impl AsyncWrite for ChildStdin {
 
 impl Read for ChildStdout {
     fn read(&mut self, bytes: &mut [u8]) -> io::Result<usize> {
-        self.inner.read(bytes)
+        let ret = self.inner.read(bytes);
+        self.eof.observe(bytes, ret)
     }
 }
 
 impl AsyncRead for ChildStdout {
 }
 
-impl Read for ChildStderr {
+/// The parent's end of a pseudo-terminal, as created by
+/// `CommandConfigExt::spawn_pty_async`.
+///
+/// Reading from a `Pty` yields the output of the child attached to it, and
+/// writing to it provides the child with input. Reads return EOF once the
+/// child (and any process it shares the terminal with) has exited. Like the
+/// child's stdio handles, this type is "futures aware".
+#[cfg(unix)]
+#[derive(Debug)]
+pub struct Pty {
+    inner: imp::pty::PtyMaster,
+}
+
+#[cfg(unix)]
+impl Pty {
+    /// Changes the window size of the pty to `size`, given as
+    /// `(rows, columns)`.
+    ///
+    /// The child is notified of the change with a `SIGWINCH` signal.
+    pub fn resize(&self, size: (u16, u16)) -> io::Result<()> {
+        imp::pty::resize(&self.inner, size)
+    }
+}
+
+#[cfg(unix)]
+impl Read for Pty {
     fn read(&mut self, bytes: &mut [u8]) -> io::Result<usize> {
         self.inner.read(bytes)
     }
 }
 
+#[cfg(unix)]
+impl AsyncRead for Pty {
+}
+
+#[cfg(unix)]
+impl Write for Pty {
+    fn write(&mut self, bytes: &[u8]) -> io::Result<usize> {
+        self.inner.write(bytes)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(unix)]
+impl AsyncWrite for Pty {
+    fn shutdown(&mut self) -> Poll<(), io::Error> {
+        self.inner.shutdown()
+    }
+}
+
+impl Read for ChildStderr {
+    fn read(&mut self, bytes: &mut [u8]) -> io::Result<usize> {
+        let ret = self.inner.read(bytes);
+        self.eof.observe(bytes, ret)
+    }
+}
+
 impl AsyncRead for ChildStderr {
 }
 
 #[cfg(unix)]
 mod sys {
     use std::os::unix::io::{AsRawFd, RawFd};
-    use super::{ChildStdin, ChildStdout, ChildStderr};
+    use super::{Child, ChildStdin, ChildStdout, ChildStderr};
+
+    /// Access to the raw file descriptors of the child's stdio pipes.
+    ///
+    /// These are useful for advanced integrations such as `splice(2)`-ing
+    /// data between pipes or handing a pipe to another process. Note,
+    /// however, that performing I/O directly on a raw descriptor bypasses
+    /// the event loop's readiness tracking: the corresponding handle may then
+    /// wait for readiness which was already consumed, or observe data in an
+    /// unexpected state. The descriptors are also in nonblocking mode.
+    impl Child {
+        /// Returns the raw file descriptor of the child's stdin, if it has
+        /// been captured.
+        pub fn stdin_raw_fd(&self) -> Option<RawFd> {
+            self.stdin.as_ref().map(AsRawFd::as_raw_fd)
+        }
+
+        /// Returns the raw file descriptor of the child's stdout, if it has
+        /// been captured.
+        pub fn stdout_raw_fd(&self) -> Option<RawFd> {
+            self.stdout.as_ref().map(AsRawFd::as_raw_fd)
+        }
+
+        /// Returns the raw file descriptor of the child's stderr, if it has
+        /// been captured.
+        pub fn stderr_raw_fd(&self) -> Option<RawFd> {
+            self.stderr.as_ref().map(AsRawFd::as_raw_fd)
+        }
+    }
 
     impl AsRawFd for ChildStdin {
         fn as_raw_fd(&self) -> RawFd {
@@ -871,4 +2880,24 @@ mod test {
         assert_eq!(0, mock.num_kills);
         assert_eq!(0, mock.num_polls);
     }
+
+    #[test]
+    fn kill_on_drop_can_be_toggled() {
+        let mut mock_disabled = Mock::new();
+        let mut mock_reenabled = Mock::new();
+
+        {
+            let mut guard = ChildDropGuard::new(&mut mock_disabled);
+            guard.set_kill_on_drop(false);
+            drop(guard);
+
+            let mut guard = ChildDropGuard::new(&mut mock_reenabled);
+            guard.set_kill_on_drop(false);
+            guard.set_kill_on_drop(true);
+            drop(guard);
+        }
+
+        assert_eq!(0, mock_disabled.num_kills);
+        assert_eq!(1, mock_reenabled.num_kills);
+    }
 }
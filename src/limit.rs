@@ -0,0 +1,109 @@
+use capture::Capture;
+use futures::{Async, Future, Poll};
+use std::io;
+use std::process::{ExitStatus, Output};
+use super::{Child, ChildStderr, ChildStdout};
+
+/// Output collected from a child whose stdout and stderr may have been
+/// truncated.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LimitedOutput {
+    /// The output of the child, truncated to the requested limit.
+    pub output: Output,
+    /// Whether the child produced more output than the limit allowed, in
+    /// which case it was killed and its output truncated.
+    ///
+    /// This is only set once a read actually overshoots the limit. Output
+    /// which reaches EOF exactly at the limit is complete, so it isn't
+    /// considered truncated.
+    pub truncated: bool,
+}
+
+/// Future returned from the `Child::wait_with_output_limited` and
+/// `Child::wait_with_output_limited_total` methods.
+///
+/// This future will resolve to a `LimitedOutput` once the child has exited
+/// and its output has been collected.
+#[must_use = "futures do nothing unless polled"]
+#[derive(Debug)]
+pub struct WaitWithOutputLimited {
+    child: Child,
+    status: Option<ExitStatus>,
+    stdout: Capture<ChildStdout>,
+    stderr: Capture<ChildStderr>,
+    // The number of bytes which may still be captured across both streams,
+    // if limited
+    total: Option<usize>,
+    killed: bool,
+}
+
+impl WaitWithOutputLimited {
+    pub(crate) fn new(mut child: Child, max_bytes: usize) -> Self {
+        drop(child.stdin().take());
+        let stdout = Capture::with_limit(child.stdout().take(), max_bytes);
+        let stderr = Capture::with_limit(child.stderr().take(), max_bytes);
+
+        Self {
+            child,
+            status: None,
+            stdout,
+            stderr,
+            total: None,
+            killed: false,
+        }
+    }
+
+    pub(crate) fn with_total(child: Child, max_total: usize) -> Self {
+        Self {
+            total: Some(max_total),
+            ..Self::new(child, usize::MAX)
+        }
+    }
+
+    fn is_truncated(&self) -> bool {
+        self.stdout.is_truncated() || self.stderr.is_truncated()
+    }
+}
+
+impl Future for WaitWithOutputLimited {
+    type Item = LimitedOutput;
+    type Error = io::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        let mut stdout_done = self.stdout.poll_capture_within(self.total.as_mut())?.is_ready();
+        let mut stderr_done = self.stderr.poll_capture_within(self.total.as_mut())?.is_ready();
+
+        if self.total.is_some() && self.is_truncated() {
+            // Nothing more can be captured from the other stream either, and
+            // it may be kept open by a descendant which survives the child
+            self.stdout.close();
+            self.stderr.close();
+            stdout_done = true;
+            stderr_done = true;
+        }
+
+        if self.status.is_none() {
+            if self.is_truncated() && !self.killed {
+                // We've stopped reading so there's no point in letting the
+                // child keep running.
+                self.child.kill()?;
+                self.killed = true;
+            }
+
+            self.status = Some(try_ready!(self.child.poll()));
+        }
+
+        if !(stdout_done && stderr_done) {
+            return Ok(Async::NotReady);
+        }
+
+        Ok(Async::Ready(LimitedOutput {
+            truncated: self.is_truncated(),
+            output: Output {
+                status: self.status.take().expect("status went missing"),
+                stdout: self.stdout.take(),
+                stderr: self.stderr.take(),
+            },
+        }))
+    }
+}
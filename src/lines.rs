@@ -0,0 +1,52 @@
+use futures::{Async, Poll, Stream};
+use std::io::{self, BufRead, BufReader};
+use std::mem;
+use tokio_io::AsyncRead;
+
+/// A stream over the lines of text written by a child process.
+///
+/// This type is created by the `Child::stdout_lines` method. Each item is a
+/// line of UTF-8 text with its trailing newline (`\n` or `\r\n`) removed. A
+/// final line which is not terminated by a newline is still yielded. Invalid
+/// UTF-8 is reported as an error of kind `InvalidData`.
+#[derive(Debug)]
+pub struct Lines<R> {
+    reader: BufReader<R>,
+    buf: Vec<u8>,
+}
+
+impl<R: AsyncRead> Lines<R> {
+    pub(crate) fn new(reader: R) -> Self {
+        Self {
+            reader: BufReader::new(reader),
+            buf: Vec::new(),
+        }
+    }
+}
+
+impl<R: AsyncRead> Stream for Lines<R> {
+    type Item = String;
+    type Error = io::Error;
+
+    fn poll(&mut self) -> Poll<Option<String>, io::Error> {
+        // NB: we accumulate raw bytes rather than using `read_line` since the
+        // latter discards any partially read data if a multi-byte character
+        // happens to be split across two reads which would otherwise block.
+        let n = try_nb!(self.reader.read_until(b'\n', &mut self.buf));
+        if n == 0 && self.buf.is_empty() {
+            return Ok(Async::Ready(None));
+        }
+
+        if self.buf.ends_with(b"\n") {
+            self.buf.pop();
+            if self.buf.ends_with(b"\r") {
+                self.buf.pop();
+            }
+        }
+
+        let line = String::from_utf8(mem::take(&mut self.buf))
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+
+        Ok(Async::Ready(Some(line)))
+    }
+}
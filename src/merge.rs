@@ -0,0 +1,191 @@
+use bytes::Bytes;
+use chunks::Chunks;
+use futures::{Async, Poll, Stream};
+use std::io::{self, Read};
+use tokio_io::AsyncRead;
+use super::{ChildStderr, ChildStdout};
+
+/// The maximum size of the chunks yielded by `OutputEvents`.
+const CHUNK_SIZE: usize = 8 * 1024;
+
+/// A reader which interleaves the stdout and stderr of a child process.
+///
+/// This type is created by the `Child::merged_output` method. Data is yielded
+/// from whichever stream has some available, alternating between the two
+/// when both are ready so that neither can starve the other. Since the two
+/// streams are distinct pipes, the relative order in which the child wrote
+/// to them cannot be perfectly preserved, but data is yielded in roughly the
+/// order in which it arrived.
+///
+/// The reader reaches EOF once *both* streams have reached EOF.
+#[derive(Debug)]
+pub struct MergedOutput {
+    stdout: Option<ChildStdout>,
+    stderr: Option<ChildStderr>,
+    prefer_stderr: bool,
+}
+
+impl MergedOutput {
+    pub(crate) fn new(stdout: Option<ChildStdout>, stderr: Option<ChildStderr>) -> Self {
+        Self {
+            stdout,
+            stderr,
+            prefer_stderr: false,
+        }
+    }
+
+    fn read_stdout(&mut self, buf: &mut [u8]) -> Option<io::Result<usize>> {
+        read_stream(&mut self.stdout, buf)
+    }
+
+    fn read_stderr(&mut self, buf: &mut [u8]) -> Option<io::Result<usize>> {
+        read_stream(&mut self.stderr, buf)
+    }
+}
+
+/// Reads from `stream` if it hasn't yet reached EOF, returning `None` if there
+/// is nothing (more) to read from it.
+fn read_stream<R: Read>(stream: &mut Option<R>, buf: &mut [u8]) -> Option<io::Result<usize>> {
+    let ret = stream.as_mut()?.read(buf);
+
+    if let Ok(0) = ret {
+        *stream = None;
+        return None;
+    }
+
+    Some(ret)
+}
+
+impl Read for MergedOutput {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        let order = [self.prefer_stderr, !self.prefer_stderr];
+        let mut blocked = None;
+
+        for &from_stderr in &order {
+            let ret = if from_stderr {
+                self.read_stderr(buf)
+            } else {
+                self.read_stdout(buf)
+            };
+
+            match ret {
+                // Give the other stream a chance to go first next time
+                Some(Ok(n)) => {
+                    self.prefer_stderr = !from_stderr;
+                    return Ok(n);
+                },
+
+                // Try the other stream if this one has nothing for us right
+                // now; both will have registered interest for the current
+                // task if they end up blocking.
+                Some(Err(ref e)) if e.kind() == io::ErrorKind::WouldBlock => {},
+                Some(Err(e)) => return Err(e),
+
+                // This stream has reached EOF, nothing more to do
+                None => continue,
+            }
+
+            blocked = ret;
+        }
+
+        match blocked {
+            Some(ret) => ret,
+            None => Ok(0),
+        }
+    }
+}
+
+impl AsyncRead for MergedOutput {
+}
+
+/// A chunk of output written by a child process, tagged with the stream it
+/// was written to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OutputEvent {
+    /// Data written to the child's stdout.
+    Stdout(Bytes),
+    /// Data written to the child's stderr.
+    Stderr(Bytes),
+}
+
+/// A stream over the stdout and stderr of a child process, yielding chunks
+/// of either tagged with the stream they came from.
+///
+/// This type is created by the `Child::output_events` method. Like
+/// `MergedOutput`, it alternates between the two streams when both have data
+/// available so that neither can starve the other, yielding chunks in
+/// roughly the order in which they arrived. Each chunk holds at most 8 KiB.
+///
+/// The stream ends once *both* streams have reached EOF.
+#[derive(Debug)]
+pub struct OutputEvents {
+    stdout: Option<Chunks<ChildStdout>>,
+    stderr: Option<Chunks<ChildStderr>>,
+    prefer_stderr: bool,
+}
+
+impl OutputEvents {
+    pub(crate) fn new(stdout: Option<ChildStdout>, stderr: Option<ChildStderr>) -> Self {
+        Self {
+            stdout: stdout.map(|stdout| Chunks::new(stdout, CHUNK_SIZE)),
+            stderr: stderr.map(|stderr| Chunks::new(stderr, CHUNK_SIZE)),
+            prefer_stderr: false,
+        }
+    }
+}
+
+/// Polls `chunks` if it hasn't yet ended, dropping it (and thus closing our
+/// end of the pipe) once it has.
+fn poll_chunks<R: AsyncRead>(chunks: &mut Option<Chunks<R>>) -> Poll<Option<Bytes>, io::Error> {
+    let ret = match *chunks {
+        Some(ref mut chunks) => try_ready!(chunks.poll()),
+        None => return Ok(Async::Ready(None)),
+    };
+
+    if ret.is_none() {
+        *chunks = None;
+    }
+
+    Ok(Async::Ready(ret))
+}
+
+impl Stream for OutputEvents {
+    type Item = OutputEvent;
+    type Error = io::Error;
+
+    fn poll(&mut self) -> Poll<Option<OutputEvent>, io::Error> {
+        let order = [self.prefer_stderr, !self.prefer_stderr];
+
+        for &from_stderr in &order {
+            // Both streams register interest for the current task if they
+            // have nothing for us right now.
+            let ret = if from_stderr {
+                poll_chunks(&mut self.stderr)?
+            } else {
+                poll_chunks(&mut self.stdout)?
+            };
+
+            if let Async::Ready(Some(chunk)) = ret {
+                // Give the other stream a chance to go first next time
+                self.prefer_stderr = !from_stderr;
+
+                let event = if from_stderr {
+                    OutputEvent::Stderr(chunk)
+                } else {
+                    OutputEvent::Stdout(chunk)
+                };
+                return Ok(Async::Ready(Some(event)));
+            }
+        }
+
+        if self.stdout.is_none() && self.stderr.is_none() {
+            Ok(Async::Ready(None))
+        } else {
+            Ok(Async::NotReady)
+        }
+    }
+}
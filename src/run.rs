@@ -0,0 +1,102 @@
+use futures::sync::oneshot;
+use futures::{Async, Future, Poll, Stream};
+use merge::{OutputEvent, OutputEvents};
+use std::fmt;
+use std::io;
+use std::process::ExitStatus;
+use super::Child;
+
+/// Stream returned from the `Child::run` method, alongside a `RunExit`.
+///
+/// This stream yields the output of the child as chunks tagged with the
+/// stream they came from, like `OutputEvents`, and ends once both stdout and
+/// stderr have been closed. It ends immediately if neither was captured.
+#[must_use = "streams do nothing unless polled"]
+#[derive(Debug)]
+pub struct RunOutput {
+    events: Option<OutputEvents>,
+    // Dropped once the output has been drained, or the stream itself is
+    // dropped, to let the `RunExit` resolve
+    drained: Option<oneshot::Sender<()>>,
+}
+
+impl Stream for RunOutput {
+    type Item = OutputEvent;
+    type Error = io::Error;
+
+    fn poll(&mut self) -> Poll<Option<OutputEvent>, io::Error> {
+        let ret = match self.events {
+            Some(ref mut events) => events.poll(),
+            None => Ok(Async::Ready(None)),
+        };
+
+        match ret {
+            Ok(Async::Ready(None)) | Err(_) => {
+                // Close our ends of the pipes as soon as we're done with them
+                self.events = None;
+                self.drained = None;
+            },
+            Ok(Async::Ready(Some(_))) | Ok(Async::NotReady) => {},
+        }
+
+        ret
+    }
+}
+
+/// Future returned from the `Child::run` method, alongside a `RunOutput`.
+///
+/// This future resolves to the exit status of the child once it has exited
+/// *and* the accompanying `RunOutput` has ended (or was dropped), so that no
+/// output is still outstanding by the time the status is known.
+#[must_use = "futures do nothing unless polled"]
+pub struct RunExit {
+    child: Child,
+    status: Option<ExitStatus>,
+    drained: oneshot::Receiver<()>,
+}
+
+impl fmt::Debug for RunExit {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.debug_struct("RunExit")
+            .field("child", &self.child)
+            .field("status", &self.status)
+            .finish()
+    }
+}
+
+impl Future for RunExit {
+    type Item = ExitStatus;
+    type Error = io::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        if self.status.is_none() {
+            self.status = Some(try_ready!(self.child.poll()));
+        }
+
+        // The sender is only ever dropped, never used
+        match self.drained.poll() {
+            Ok(Async::NotReady) => return Ok(Async::NotReady),
+            Ok(Async::Ready(())) | Err(oneshot::Canceled) => {},
+        }
+
+        Ok(Async::Ready(self.status.take().expect("polled after completion")))
+    }
+}
+
+pub(crate) fn run(mut child: Child) -> (RunOutput, RunExit) {
+    drop(child.stdin().take());
+    let events = child.output_events();
+    let (tx, rx) = oneshot::channel();
+
+    let output = RunOutput {
+        events,
+        drained: Some(tx),
+    };
+    let exit = RunExit {
+        child,
+        status: None,
+        drained: rx,
+    };
+
+    (output, exit)
+}
@@ -0,0 +1,81 @@
+use capture::Capture;
+use futures::{Async, Future, Poll};
+use std::io;
+use std::process::{ExitStatus, Output};
+use std::time::Duration;
+use super::{Child, ChildStderr, ChildStdout};
+
+/// The resources used by a child process over its lifetime, as returned by
+/// `Child::wait_with_output_rusage`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ResourceUsage {
+    /// The wall-clock time from spawning the child until it was reaped.
+    pub wall_time: Duration,
+    /// The CPU time the child spent executing in user mode.
+    pub user_time: Duration,
+    /// The CPU time the child spent executing in kernel mode.
+    pub system_time: Duration,
+    /// The maximum resident set size of the child, in bytes.
+    pub max_rss: u64,
+}
+
+/// Future returned from the `Child::wait_with_output_rusage` method.
+///
+/// This future will resolve to the output of the child along with its
+/// resource usage once the child has exited and its output has been
+/// collected.
+#[must_use = "futures do nothing unless polled"]
+#[derive(Debug)]
+pub struct WaitWithOutputRusage {
+    child: Child,
+    exit: Option<(ExitStatus, ResourceUsage)>,
+    stdout: Capture<ChildStdout>,
+    stderr: Capture<ChildStderr>,
+}
+
+impl WaitWithOutputRusage {
+    pub(crate) fn new(mut child: Child) -> Self {
+        drop(child.stdin().take());
+        let stdout = Capture::new(child.stdout().take());
+        let stderr = Capture::new(child.stderr().take());
+
+        Self {
+            child,
+            exit: None,
+            stdout,
+            stderr,
+        }
+    }
+}
+
+impl Future for WaitWithOutputRusage {
+    type Item = (Output, ResourceUsage);
+    type Error = io::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        let stdout_done = self.stdout.poll_capture()?.is_ready();
+        let stderr_done = self.stderr.poll_capture()?.is_ready();
+
+        if self.exit.is_none() {
+            let spawned = self.child.spawned;
+            let (status, usage) = try_ready!(self.child.child.inner.poll_rusage(spawned));
+
+            // Avoid the overhead of trying to kill a reaped process
+            self.child.child.kill_on_drop = false;
+            self.child.exit_watch.publish(status);
+            self.exit = Some((status, usage));
+        }
+
+        if !(stdout_done && stderr_done) {
+            return Ok(Async::NotReady);
+        }
+
+        let (status, usage) = self.exit.take().expect("exit went missing");
+        let output = Output {
+            status,
+            stdout: self.stdout.take(),
+            stderr: self.stderr.take(),
+        };
+        Ok(Async::Ready((output, usage)))
+    }
+}
@@ -0,0 +1,202 @@
+use std::ffi::{OsStr, OsString};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use tokio_reactor::Handle;
+use super::{Child, CommandExt};
+
+/// How one of the stdio streams of a child described by a `ProcessSpec` is
+/// configured.
+///
+/// Unlike `Stdio`, this can be copied, so the same configuration can be used
+/// for any number of children.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StdioSpec {
+    /// The child inherits the stream from the current process.
+    Inherit,
+    /// A pipe to the child is created, see `Stdio::piped`.
+    Piped,
+    /// The stream is connected to the null device, see `Stdio::null`.
+    Null,
+}
+
+impl StdioSpec {
+    fn stdio(self) -> Stdio {
+        match self {
+            StdioSpec::Inherit => Stdio::inherit(),
+            StdioSpec::Piped => Stdio::piped(),
+            StdioSpec::Null => Stdio::null(),
+        }
+    }
+}
+
+/// A description of a process to spawn, which can be cloned and used to
+/// spawn any number of children, unlike `Command`.
+///
+/// The builder methods mirror those of `Command`, and every child is spawned
+/// from a fresh `Command` built via `command`, which may be configured
+/// further before spawning it (e.g. through `CommandExt`). This serves as a
+/// reusable launch template, e.g. to restart a child through `Supervised`:
+///
+/// ```no_run
+/// # extern crate tokio_process;
+/// # fn main() {
+/// use tokio_process::{ProcessSpec, Supervised};
+///
+/// let mut spec = ProcessSpec::new("server");
+/// spec.arg("--port").arg("8080");
+///
+/// let supervised = Supervised::spawn(move || spec.command());
+/// # drop(supervised);
+/// # }
+/// ```
+///
+/// If the stdio of a child isn't configured, it is left to the defaults of
+/// the method used to spawn it, as with `Command`.
+#[derive(Clone, Debug)]
+pub struct ProcessSpec {
+    program: OsString,
+    args: Vec<OsString>,
+    env_clear: bool,
+    // `None` removes a variable, in the order the changes were made
+    env: Vec<(OsString, Option<OsString>)>,
+    current_dir: Option<PathBuf>,
+    stdin: Option<StdioSpec>,
+    stdout: Option<StdioSpec>,
+    stderr: Option<StdioSpec>,
+}
+
+impl ProcessSpec {
+    /// Creates a description of a process running `program`, with no
+    /// arguments, like `Command::new`.
+    pub fn new<S: AsRef<OsStr>>(program: S) -> Self {
+        ProcessSpec {
+            program: program.as_ref().to_owned(),
+            args: Vec::new(),
+            env_clear: false,
+            env: Vec::new(),
+            current_dir: None,
+            stdin: None,
+            stdout: None,
+            stderr: None,
+        }
+    }
+
+    /// Adds an argument to pass to the program.
+    pub fn arg<S: AsRef<OsStr>>(&mut self, arg: S) -> &mut Self {
+        self.args.push(arg.as_ref().to_owned());
+        self
+    }
+
+    /// Adds multiple arguments to pass to the program.
+    pub fn args<I, S>(&mut self, args: I) -> &mut Self
+        where I: IntoIterator<Item = S>,
+              S: AsRef<OsStr>,
+    {
+        self.args.extend(args.into_iter().map(|arg| arg.as_ref().to_owned()));
+        self
+    }
+
+    /// Sets an environment variable of the child.
+    pub fn env<K, V>(&mut self, key: K, value: V) -> &mut Self
+        where K: AsRef<OsStr>,
+              V: AsRef<OsStr>,
+    {
+        self.env.push((key.as_ref().to_owned(), Some(value.as_ref().to_owned())));
+        self
+    }
+
+    /// Sets multiple environment variables of the child.
+    pub fn envs<I, K, V>(&mut self, vars: I) -> &mut Self
+        where I: IntoIterator<Item = (K, V)>,
+              K: AsRef<OsStr>,
+              V: AsRef<OsStr>,
+    {
+        for (key, value) in vars {
+            self.env(key, value);
+        }
+        self
+    }
+
+    /// Removes an environment variable from the child, whether it was
+    /// inherited or set before.
+    pub fn env_remove<K: AsRef<OsStr>>(&mut self, key: K) -> &mut Self {
+        self.env.push((key.as_ref().to_owned(), None));
+        self
+    }
+
+    /// Clears the environment of the child, including any variables set
+    /// before, so that it only sees the variables set afterwards.
+    pub fn env_clear(&mut self) -> &mut Self {
+        self.env_clear = true;
+        self.env.clear();
+        self
+    }
+
+    /// Sets the working directory of the child.
+    pub fn current_dir<P: AsRef<Path>>(&mut self, dir: P) -> &mut Self {
+        self.current_dir = Some(dir.as_ref().to_owned());
+        self
+    }
+
+    /// Configures the stdin of the child.
+    pub fn stdin(&mut self, stdin: StdioSpec) -> &mut Self {
+        self.stdin = Some(stdin);
+        self
+    }
+
+    /// Configures the stdout of the child.
+    pub fn stdout(&mut self, stdout: StdioSpec) -> &mut Self {
+        self.stdout = Some(stdout);
+        self
+    }
+
+    /// Configures the stderr of the child.
+    pub fn stderr(&mut self, stderr: StdioSpec) -> &mut Self {
+        self.stderr = Some(stderr);
+        self
+    }
+
+    /// Builds a fresh `Command` which spawns the described process.
+    pub fn command(&self) -> Command {
+        let mut cmd = Command::new(&self.program);
+        cmd.args(&self.args);
+
+        if self.env_clear {
+            cmd.env_clear();
+        }
+        for (key, value) in &self.env {
+            match *value {
+                Some(ref value) => cmd.env(key, value),
+                None => cmd.env_remove(key),
+            };
+        }
+
+        if let Some(ref dir) = self.current_dir {
+            cmd.current_dir(dir);
+        }
+        if let Some(stdin) = self.stdin {
+            cmd.stdin(stdin.stdio());
+        }
+        if let Some(stdout) = self.stdout {
+            cmd.stdout(stdout.stdio());
+        }
+        if let Some(stderr) = self.stderr {
+            cmd.stderr(stderr.stdio());
+        }
+
+        cmd
+    }
+
+    /// Spawns a child described by this spec, associating its I/O with the
+    /// current default event loop, like `CommandExt::spawn_async`.
+    pub fn spawn_async(&self) -> io::Result<Child> {
+        self.command().spawn_async()
+    }
+
+    /// Spawns a child described by this spec, associating its I/O with the
+    /// event loop of `handle`, like `CommandExt::spawn_async_with_handle`.
+    pub fn spawn_async_with_handle(&self, handle: &Handle) -> io::Result<Child> {
+        self.command().spawn_async_with_handle(handle)
+    }
+}
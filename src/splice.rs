@@ -0,0 +1,97 @@
+use futures::{Async, Future, Poll};
+use std::io;
+use std::mem;
+use tokio_io::io::{copy, Copy};
+use super::{no_stdin, ChildStdin, ChildStdout};
+
+/// Future returned from the `Child::splice_output_to` method.
+///
+/// This future moves all data the first child writes to its stdout into the
+/// stdin of the second child, and resolves to the number of bytes moved once
+/// the first child closes its stdout. The second child's stdin is closed
+/// afterwards, so that it sees EOF.
+#[must_use = "futures do nothing unless polled"]
+#[derive(Debug)]
+pub struct SpliceOutput {
+    state: State,
+}
+
+#[derive(Debug)]
+enum State {
+    #[cfg(target_os = "linux")]
+    Splice {
+        from: ChildStdout,
+        to: ChildStdin,
+        total: u64,
+    },
+    Copy {
+        copy: Copy<ChildStdout, ChildStdin>,
+        spliced: u64,
+    },
+    Failed(io::Error),
+    Done,
+}
+
+impl SpliceOutput {
+    pub(crate) fn new(from: Option<ChildStdout>, to: Option<ChildStdin>) -> Self {
+        let state = match (from, to) {
+            (None, _) => State::Failed(io::Error::new(
+                io::ErrorKind::NotConnected,
+                "child has no stdout to read from",
+            )),
+            (_, None) => State::Failed(no_stdin()),
+
+            #[cfg(target_os = "linux")]
+            (Some(from), Some(to)) => State::Splice { from, to, total: 0 },
+            #[cfg(not(target_os = "linux"))]
+            (Some(from), Some(to)) => State::Copy { copy: copy(from, to), spliced: 0 },
+        };
+
+        Self { state }
+    }
+}
+
+impl Future for SpliceOutput {
+    type Item = u64;
+    type Error = io::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        loop {
+            match self.state {
+                #[cfg(target_os = "linux")]
+                State::Splice { ref mut from, ref mut to, ref mut total } => {
+                    match ::imp::splice::poll_splice(&mut from.inner, &mut to.inner, total) {
+                        Ok(Async::Ready(())) => {
+                            from.eof.set();
+                            let total = *total;
+                            self.state = State::Done;
+                            return Ok(Async::Ready(total));
+                        },
+                        Ok(Async::NotReady) => return Ok(Async::NotReady),
+                        // Fall back to copying through userspace below
+                        Err(ref e) if ::imp::splice::is_unsupported(e) => {},
+                        Err(e) => return Err(e),
+                    }
+                },
+                State::Copy { ref mut copy, spliced } => {
+                    let (copied, _, _) = try_ready!(copy.poll());
+                    self.state = State::Done;
+                    return Ok(Async::Ready(spliced + copied));
+                },
+                State::Failed(_) => match mem::replace(&mut self.state, State::Done) {
+                    State::Failed(e) => return Err(e),
+                    _ => unreachable!(),
+                },
+                State::Done => panic!("poll after SpliceOutput resolved"),
+            }
+
+            #[cfg(target_os = "linux")]
+            {
+                if let State::Splice { from, to, total } = mem::replace(&mut self.state, State::Done) {
+                    debug!("splice unsupported, falling back to copying");
+                    self.state = State::Copy { copy: copy(from, to), spliced: total };
+                }
+            }
+        }
+    }
+}
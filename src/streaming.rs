@@ -0,0 +1,127 @@
+use bytes::Bytes;
+use chunks::Chunks;
+use futures::{Async, Future, IntoFuture, Poll, Stream};
+use std::fmt;
+use std::io;
+use std::process::ExitStatus;
+use tokio_io::AsyncRead;
+use super::{Child, ChildStderr, ChildStdout};
+
+/// The size of the chunks handed to the callbacks of `WaitWithOutputStreaming`.
+const CHUNK_SIZE: usize = 8 * 1024;
+
+/// Feeds every chunk read from a stream into a callback, waiting for the
+/// future the callback returns before reading any further.
+struct Forward<R, F, U: IntoFuture> {
+    chunks: Option<Chunks<R>>,
+    callback: F,
+    pending: Option<U::Future>,
+}
+
+impl<R, F, U> Forward<R, F, U>
+    where R: AsyncRead,
+          F: FnMut(Bytes) -> U,
+          U: IntoFuture<Item = (), Error = io::Error>,
+{
+    fn new(reader: Option<R>, callback: F) -> Self {
+        Self {
+            chunks: reader.map(|reader| Chunks::new(reader, CHUNK_SIZE)),
+            callback,
+            pending: None,
+        }
+    }
+
+    /// Resolves once the stream has ended and the last chunk was handled.
+    fn poll_forward(&mut self) -> Poll<(), io::Error> {
+        loop {
+            if let Some(ref mut pending) = self.pending {
+                try_ready!(pending.poll());
+            }
+            self.pending = None;
+
+            let chunk = match self.chunks {
+                Some(ref mut chunks) => try_ready!(chunks.poll()),
+                None => return Ok(Async::Ready(())),
+            };
+
+            match chunk {
+                Some(chunk) => self.pending = Some((self.callback)(chunk).into_future()),
+                // Close our end of the pipe as soon as we're done with it
+                None => self.chunks = None,
+            }
+        }
+    }
+}
+
+/// Future returned from the `Child::wait_with_output_streaming` method.
+///
+/// This future hands the output of the child to the callbacks as it arrives,
+/// and resolves to the exit status of the child once it has exited and every
+/// chunk of its output has been handled.
+#[must_use = "futures do nothing unless polled"]
+pub struct WaitWithOutputStreaming<F, U, G, V>
+    where U: IntoFuture,
+          V: IntoFuture,
+{
+    child: Child,
+    status: Option<ExitStatus>,
+    stdout: Forward<ChildStdout, F, U>,
+    stderr: Forward<ChildStderr, G, V>,
+}
+
+impl<F, U, G, V> WaitWithOutputStreaming<F, U, G, V>
+    where F: FnMut(Bytes) -> U,
+          U: IntoFuture<Item = (), Error = io::Error>,
+          G: FnMut(Bytes) -> V,
+          V: IntoFuture<Item = (), Error = io::Error>,
+{
+    pub(crate) fn new(mut child: Child, on_stdout: F, on_stderr: G) -> Self {
+        drop(child.stdin().take());
+        let stdout = Forward::new(child.stdout().take(), on_stdout);
+        let stderr = Forward::new(child.stderr().take(), on_stderr);
+
+        Self {
+            child,
+            status: None,
+            stdout,
+            stderr,
+        }
+    }
+}
+
+impl<F, U, G, V> fmt::Debug for WaitWithOutputStreaming<F, U, G, V>
+    where U: IntoFuture,
+          V: IntoFuture,
+{
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.debug_struct("WaitWithOutputStreaming")
+            .field("child", &self.child)
+            .field("status", &self.status)
+            .finish()
+    }
+}
+
+impl<F, U, G, V> Future for WaitWithOutputStreaming<F, U, G, V>
+    where F: FnMut(Bytes) -> U,
+          U: IntoFuture<Item = (), Error = io::Error>,
+          G: FnMut(Bytes) -> V,
+          V: IntoFuture<Item = (), Error = io::Error>,
+{
+    type Item = ExitStatus;
+    type Error = io::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        let stdout_done = self.stdout.poll_forward()?.is_ready();
+        let stderr_done = self.stderr.poll_forward()?.is_ready();
+
+        if self.status.is_none() {
+            self.status = Some(try_ready!(self.child.poll()));
+        }
+
+        if !(stdout_done && stderr_done) {
+            return Ok(Async::NotReady);
+        }
+
+        Ok(Async::Ready(self.status.take().expect("status went missing")))
+    }
+}
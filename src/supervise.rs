@@ -0,0 +1,91 @@
+use futures::{Future, Poll};
+use std::fmt;
+use std::io;
+use std::process::{Command, ExitStatus};
+use tokio_reactor::Handle;
+use super::{Child, CommandExt};
+
+/// A child process which can be restarted with the same configuration.
+///
+/// Since `Command` cannot be cloned, a `Supervised` child is created from a
+/// closure which builds a fresh `Command` every time a child needs to be
+/// spawned, e.g. through `ProcessSpec::command`.
+///
+/// As a future, a `Supervised` child resolves to the exit status of the
+/// child which is currently running. Once it has resolved, `restart` may be
+/// used to spawn a replacement, after which the future may be polled again.
+pub struct Supervised<F> {
+    make: F,
+    handle: Handle,
+    child: Child,
+    restarts: usize,
+}
+
+impl<F> Supervised<F>
+    where F: FnMut() -> Command,
+{
+    /// Spawns the first child built by `make`, associating its I/O (and that
+    /// of any subsequent restarts) with the current default event loop.
+    pub fn spawn(make: F) -> io::Result<Self> {
+        Self::spawn_with_handle(make, &Handle::default())
+    }
+
+    /// Spawns the first child built by `make`, associating its I/O (and that
+    /// of any subsequent restarts) with the event loop of `handle`.
+    pub fn spawn_with_handle(mut make: F, handle: &Handle) -> io::Result<Self> {
+        let child = make().spawn_async_with_handle(handle)?;
+
+        Ok(Self {
+            make,
+            handle: handle.clone(),
+            child,
+            restarts: 0,
+        })
+    }
+
+    /// Spawns a fresh child with the same configuration, replacing the
+    /// current one.
+    ///
+    /// This is intended to be called once the current child has exited. If
+    /// it is still running it is dropped, and thus killed unless
+    /// `Child::kill_on_drop` was used to disable that. If spawning fails, the
+    /// current child is kept.
+    pub fn restart(&mut self) -> io::Result<()> {
+        self.child = (self.make)().spawn_async_with_handle(&self.handle)?;
+        self.restarts += 1;
+        Ok(())
+    }
+
+    /// Returns the number of times the child has been restarted.
+    pub fn restarts(&self) -> usize {
+        self.restarts
+    }
+
+    /// Returns a reference to the child which is currently running.
+    pub fn child(&self) -> &Child {
+        &self.child
+    }
+
+    /// Returns a mutable reference to the child which is currently running.
+    pub fn child_mut(&mut self) -> &mut Child {
+        &mut self.child
+    }
+}
+
+impl<F> Future for Supervised<F> {
+    type Item = ExitStatus;
+    type Error = io::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        self.child.poll()
+    }
+}
+
+impl<F> fmt::Debug for Supervised<F> {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.debug_struct("Supervised")
+            .field("child", &self.child)
+            .field("restarts", &self.restarts)
+            .finish()
+    }
+}
@@ -0,0 +1,58 @@
+use capture::Capture;
+use futures::{Async, Future, Poll};
+use std::io;
+use std::process::{ExitStatus, Output};
+use super::{Child, ChildStderr, ChildStdout};
+
+/// Future returned from the `Child::wait_with_output_tail` method.
+///
+/// This future will resolve to an `Output` holding only the end of the
+/// child's stdout and stderr once the child has exited and both streams have
+/// been read to EOF.
+#[must_use = "futures do nothing unless polled"]
+#[derive(Debug)]
+pub struct WaitWithOutputTail {
+    child: Child,
+    status: Option<ExitStatus>,
+    stdout: Capture<ChildStdout>,
+    stderr: Capture<ChildStderr>,
+}
+
+impl WaitWithOutputTail {
+    pub(crate) fn new(mut child: Child, tail_bytes: usize) -> Self {
+        drop(child.stdin().take());
+        let stdout = Capture::with_tail(child.stdout().take(), tail_bytes);
+        let stderr = Capture::with_tail(child.stderr().take(), tail_bytes);
+
+        Self {
+            child,
+            status: None,
+            stdout,
+            stderr,
+        }
+    }
+}
+
+impl Future for WaitWithOutputTail {
+    type Item = Output;
+    type Error = io::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        let stdout_done = self.stdout.poll_capture()?.is_ready();
+        let stderr_done = self.stderr.poll_capture()?.is_ready();
+
+        if self.status.is_none() {
+            self.status = Some(try_ready!(self.child.poll()));
+        }
+
+        if !(stdout_done && stderr_done) {
+            return Ok(Async::NotReady);
+        }
+
+        Ok(Async::Ready(Output {
+            status: self.status.take().expect("status went missing"),
+            stdout: self.stdout.take(),
+            stderr: self.stderr.take(),
+        }))
+    }
+}
@@ -0,0 +1,165 @@
+use futures::{Async, Future, Poll};
+use std::io;
+use std::process::ExitStatus;
+use std::time::{Duration, Instant};
+use tokio_timer::Delay;
+use super::Child;
+
+/// The outcome of gracefully terminating a child process.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GracefulExit {
+    /// The exit status of the child process.
+    pub status: ExitStatus,
+    /// Whether the child failed to exit within the allotted time and had to
+    /// be forcefully killed.
+    pub escalated: bool,
+}
+
+/// The progress made gracefully terminating a child.
+#[derive(Debug)]
+pub(crate) enum State {
+    /// The child hasn't been asked to exit yet.
+    Start,
+    /// The child has been asked to exit, and is killed once the delay
+    /// elapses.
+    Terminating(Delay),
+    /// The child has been killed.
+    Killed,
+}
+
+/// Future returned from the `Child::terminate_graceful` method.
+///
+/// This future will resolve to a `GracefulExit` once the child process has
+/// been reaped.
+#[must_use = "futures do nothing unless polled"]
+#[derive(Debug)]
+pub struct TerminateGraceful<'a> {
+    child: &'a mut Child,
+    timeout: Duration,
+    state: State,
+}
+
+impl<'a> TerminateGraceful<'a> {
+    pub(crate) fn new(child: &'a mut Child, timeout: Duration) -> Self {
+        Self {
+            child,
+            timeout,
+            state: State::Start,
+        }
+    }
+}
+
+impl<'a> Future for TerminateGraceful<'a> {
+    type Item = GracefulExit;
+    type Error = io::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        poll_terminate(self.child, self.timeout, &mut self.state)
+    }
+}
+
+/// Future returned from the `Child::kill_timeout` method.
+///
+/// This future will resolve to the exit status of the child once it has been
+/// reaped.
+#[must_use = "futures do nothing unless polled"]
+#[derive(Debug)]
+pub struct KillTimeout {
+    child: Child,
+    timeout: Duration,
+    state: State,
+}
+
+impl KillTimeout {
+    pub(crate) fn new(mut child: Child, timeout: Duration) -> Self {
+        // The child is asked to terminate right away rather than once we're
+        // first polled, so that it has been signalled even if we never are.
+        // Should that fail, starting over once polled reports why.
+        let state = match child.try_status() {
+            Ok(None) => match child.child.inner.terminate() {
+                Ok(()) => {
+                    child.killed_by_us = true;
+                    State::Terminating(Delay::new(Instant::now() + timeout))
+                },
+                Err(_) => State::Start,
+            },
+            Ok(Some(_)) | Err(_) => State::Start,
+        };
+
+        Self {
+            child,
+            timeout,
+            state,
+        }
+    }
+}
+
+impl Future for KillTimeout {
+    type Item = ExitStatus;
+    type Error = io::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        let exit = try_ready!(poll_terminate(&mut self.child, self.timeout, &mut self.state));
+        Ok(Async::Ready(exit.status))
+    }
+}
+
+fn exited(status: ExitStatus, escalated: bool) -> Poll<GracefulExit, io::Error> {
+    Ok(Async::Ready(GracefulExit {
+        status,
+        escalated,
+    }))
+}
+
+/// Gracefully terminates `child`, killing it if it doesn't exit within
+/// `timeout` of being asked to, picking up from `state`.
+pub(crate) fn poll_terminate(child: &mut Child, timeout: Duration, state: &mut State)
+    -> Poll<GracefulExit, io::Error>
+{
+    loop {
+        let next = match *state {
+            State::Start => {
+                // Never signal a child which has already been reaped
+                if let Async::Ready(status) = child.poll()? {
+                    return exited(status, false);
+                }
+
+                if let Err(e) = child.child.inner.terminate() {
+                    // The child may have exited on its own right after
+                    // we last checked on it, which isn't an error.
+                    if let Async::Ready(status) = child.poll()? {
+                        return exited(status, false);
+                    }
+
+                    return Err(e);
+                }
+
+                child.killed_by_us = true;
+                State::Terminating(Delay::new(Instant::now() + timeout))
+            },
+
+            State::Terminating(ref mut delay) => {
+                if let Async::Ready(status) = child.poll()? {
+                    return exited(status, false);
+                }
+
+                match delay.poll().map_err(io::Error::other)? {
+                    Async::Ready(()) => {},
+                    Async::NotReady => return Ok(Async::NotReady),
+                }
+
+                // The child is still running (we would have reaped it
+                // above otherwise) so it is safe to kill it now.
+                child.kill()?;
+                State::Killed
+            },
+
+            State::Killed => {
+                let status = try_ready!(child.poll());
+                return exited(status, true);
+            },
+        };
+
+        *state = next;
+    }
+}
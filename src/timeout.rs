@@ -0,0 +1,307 @@
+use capture::Capture;
+use futures::{Async, Future, Poll};
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::process::{ExitStatus, Output};
+use std::time::{Duration, Instant};
+use tokio_timer::Delay;
+use super::{Child, ChildStderr, ChildStdout};
+use terminate::{self, poll_terminate};
+
+/// Output collected from a child which did not finish within its allotted
+/// time.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PartialOutput {
+    /// The exit status of the child, if it exited before the timeout elapsed.
+    ///
+    /// Note that a child may have exited but still not have finished
+    /// producing output, for example if its stdio handles were inherited by
+    /// another process which is still running.
+    pub status: Option<ExitStatus>,
+    /// The data the child wrote to stdout before the timeout elapsed.
+    pub stdout: Vec<u8>,
+    /// The data the child wrote to stderr before the timeout elapsed.
+    pub stderr: Vec<u8>,
+}
+
+/// Future returned from the `Child::with_timeout` method.
+///
+/// This future will resolve to `Some(ExitStatus)` if the child exits before
+/// the timeout elapses, or to `None` otherwise.
+#[must_use = "futures do nothing unless polled"]
+#[derive(Debug)]
+pub struct WithTimeout<'a> {
+    child: &'a mut Child,
+    delay: Delay,
+}
+
+impl<'a> WithTimeout<'a> {
+    pub(crate) fn new(child: &'a mut Child, timeout: Duration) -> Self {
+        Self {
+            child,
+            delay: Delay::new(Instant::now() + timeout),
+        }
+    }
+}
+
+impl<'a> Future for WithTimeout<'a> {
+    type Item = Option<ExitStatus>;
+    type Error = io::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        if let Async::Ready(status) = self.child.poll()? {
+            return Ok(Async::Ready(Some(status)));
+        }
+
+        try_ready!(self.delay.poll().map_err(io::Error::other));
+        Ok(Async::Ready(None))
+    }
+}
+
+/// Future returned from the `Child::wait_with_output_timeout` method.
+///
+/// This future will resolve to `Ok(Output)` if the child exits and closes its
+/// output streams before the timeout elapses, or to `Err(PartialOutput)`
+/// with everything captured so far otherwise.
+#[must_use = "futures do nothing unless polled"]
+#[derive(Debug)]
+pub struct WaitWithOutputTimeout {
+    child: Option<Child>,
+    status: Option<ExitStatus>,
+    stdout: Capture<ChildStdout>,
+    stderr: Capture<ChildStderr>,
+    delay: Delay,
+}
+
+impl WaitWithOutputTimeout {
+    pub(crate) fn new(mut child: Child, timeout: Duration) -> Self {
+        drop(child.stdin().take());
+        let stdout = Capture::new(child.stdout().take());
+        let stderr = Capture::new(child.stderr().take());
+
+        Self {
+            child: Some(child),
+            status: None,
+            stdout,
+            stderr,
+            delay: Delay::new(Instant::now() + timeout),
+        }
+    }
+}
+
+impl Future for WaitWithOutputTimeout {
+    type Item = Result<Output, PartialOutput>;
+    type Error = io::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        if self.status.is_none() {
+            let child = self.child.as_mut().expect("polled after completion");
+            if let Async::Ready(status) = child.poll()? {
+                self.status = Some(status);
+            }
+        }
+
+        let stdout_done = self.stdout.poll_capture()?.is_ready();
+        let stderr_done = self.stderr.poll_capture()?.is_ready();
+
+        if let Some(status) = self.status {
+            if stdout_done && stderr_done {
+                self.child = None;
+                return Ok(Async::Ready(Ok(Output {
+                    status,
+                    stdout: self.stdout.take(),
+                    stderr: self.stderr.take(),
+                })));
+            }
+        }
+
+        try_ready!(self.delay.poll().map_err(io::Error::other));
+
+        // Dropping the child here will either kill it or leave it running
+        // (and reap it in the background) depending on its `kill_on_drop`
+        // configuration.
+        drop(self.child.take());
+
+        Ok(Async::Ready(Err(PartialOutput {
+            status: self.status,
+            stdout: self.stdout.take(),
+            stderr: self.stderr.take(),
+        })))
+    }
+}
+
+/// Error returned from the `CommandExt::output_async_timeout` future.
+#[derive(Debug)]
+pub enum TimeoutError {
+    /// The child could not be spawned, or an error occurred while waiting on
+    /// it or reading its output.
+    Io(io::Error),
+    /// The child did not finish within its allotted time and was killed.
+    ///
+    /// The `status` of the output is that of the killed child, and the
+    /// captured data is everything the child wrote before it exited.
+    Elapsed(PartialOutput),
+}
+
+impl fmt::Display for TimeoutError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            TimeoutError::Io(ref e) => e.fmt(f),
+            TimeoutError::Elapsed(_) => f.write_str("child process timed out"),
+        }
+    }
+}
+
+impl Error for TimeoutError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match *self {
+            TimeoutError::Io(ref e) => Some(e),
+            TimeoutError::Elapsed(_) => None,
+        }
+    }
+}
+
+impl From<io::Error> for TimeoutError {
+    fn from(e: io::Error) -> Self {
+        TimeoutError::Io(e)
+    }
+}
+
+#[derive(Debug)]
+enum State {
+    Running(Delay),
+    Terminating(terminate::State),
+}
+
+/// Future returned from the `CommandExt::output_async_timeout` method.
+///
+/// This future will resolve to the `Output` of the child if it finishes
+/// before the timeout elapses, or fail with `TimeoutError::Elapsed` carrying
+/// any partially captured output otherwise.
+#[must_use = "futures do nothing unless polled"]
+#[derive(Debug)]
+pub struct OutputAsyncTimeout {
+    child: Option<Child>,
+    error: Option<io::Error>,
+    status: Option<ExitStatus>,
+    stdout: Capture<ChildStdout>,
+    stderr: Capture<ChildStderr>,
+    grace: Option<Duration>,
+    state: State,
+}
+
+impl OutputAsyncTimeout {
+    pub(crate) fn new(child: io::Result<Child>, timeout: Duration) -> Self {
+        let (mut child, error) = match child {
+            Ok(child) => (Some(child), None),
+            Err(e) => (None, Some(e)),
+        };
+
+        let (stdout, stderr) = match child {
+            Some(ref mut child) => {
+                drop(child.stdin().take());
+                (child.stdout().take(), child.stderr().take())
+            },
+            None => (None, None),
+        };
+
+        Self {
+            child,
+            error,
+            status: None,
+            stdout: Capture::new(stdout),
+            stderr: Capture::new(stderr),
+            grace: None,
+            state: State::Running(Delay::new(Instant::now() + timeout)),
+        }
+    }
+
+    /// Asks the child to exit with a `SIGTERM` once the timeout elapses, only
+    /// killing it if it is still running after a further `grace` period.
+    ///
+    /// By default the child is killed as soon as the timeout elapses. On
+    /// Windows the child is always killed immediately.
+    pub fn grace_period(mut self, grace: Duration) -> Self {
+        self.grace = Some(grace);
+        self
+    }
+
+    fn elapsed(&mut self, status: Option<ExitStatus>) -> Poll<Output, TimeoutError> {
+        // Pick up anything the child managed to write before exiting, without
+        // waiting on streams which may have been inherited by other processes.
+        self.stdout.poll_capture()?;
+        self.stderr.poll_capture()?;
+        self.child = None;
+
+        Err(TimeoutError::Elapsed(PartialOutput {
+            status,
+            stdout: self.stdout.take(),
+            stderr: self.stderr.take(),
+        }))
+    }
+}
+
+impl Future for OutputAsyncTimeout {
+    type Item = Output;
+    type Error = TimeoutError;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        if let Some(e) = self.error.take() {
+            return Err(TimeoutError::Io(e));
+        }
+
+        loop {
+            // Keep draining output while the child is being terminated so
+            // that it can't block on a full pipe while handling the signal.
+            let stdout_done = self.stdout.poll_capture()?.is_ready();
+            let stderr_done = self.stderr.poll_capture()?.is_ready();
+            let child = self.child.as_mut().expect("polled after completion");
+
+            let next = match self.state {
+                State::Running(ref mut delay) => {
+                    if self.status.is_none() {
+                        if let Async::Ready(status) = child.poll()? {
+                            self.status = Some(status);
+                        }
+                    }
+
+                    if let Some(status) = self.status {
+                        if stdout_done && stderr_done {
+                            self.child = None;
+                            return Ok(Async::Ready(Output {
+                                status,
+                                stdout: self.stdout.take(),
+                                stderr: self.stderr.take(),
+                            }));
+                        }
+                    }
+
+                    try_ready!(delay.poll().map_err(io::Error::other));
+
+                    // The child has exited but its output was never closed,
+                    // so there is nothing left to kill.
+                    if let Some(status) = self.status {
+                        return self.elapsed(Some(status));
+                    }
+
+                    match self.grace {
+                        Some(_) => State::Terminating(terminate::State::Start),
+                        None => {
+                            child.kill()?;
+                            State::Terminating(terminate::State::Killed)
+                        },
+                    }
+                },
+
+                State::Terminating(ref mut state) => {
+                    let grace = self.grace.unwrap_or_default();
+                    let exit = try_ready!(poll_terminate(child, grace, state));
+                    return self.elapsed(Some(exit.status));
+                },
+            };
+
+            self.state = next;
+        }
+    }
+}
@@ -0,0 +1,47 @@
+//! Per-child exit notifications via a file descriptor registered with the
+//! reactor, which becomes readable once the child exits.
+//!
+//! The descriptor itself is provided by a platform specific module: a pidfd
+//! on Linux, or a kqueue watching the child on macOS and FreeBSD.
+
+use futures::{Async, Poll, Stream};
+use std::io;
+use std::os::unix::io::AsRawFd;
+use super::Fd;
+use super::mio::Ready;
+use tokio_reactor::{Handle, PollEvented};
+
+/// A stream which yields whenever an exit descriptor becomes readable, i.e.
+/// when the process it refers to may have exited.
+#[derive(Debug)]
+pub(crate) struct ExitFdStream<T: AsRawFd> {
+    io: PollEvented<Fd<T>>,
+    yielded: bool,
+}
+
+impl<T: AsRawFd> ExitFdStream<T> {
+    pub(crate) fn new(fd: T, handle: &Handle) -> io::Result<Self> {
+        Ok(Self {
+            io: PollEvented::new_with_handle(Fd::new(fd), handle)?,
+            yielded: false,
+        })
+    }
+}
+
+impl<T: AsRawFd> Stream for ExitFdStream<T> {
+    type Item = ();
+    type Error = io::Error;
+
+    fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        // If we've already reported readiness and are being polled again the
+        // wakeup must have been spurious, so wait for the next event instead.
+        if self.yielded {
+            self.yielded = false;
+            self.io.clear_read_ready(Ready::readable())?;
+        }
+
+        try_ready!(self.io.poll_read_ready(Ready::readable()));
+        self.yielded = true;
+        Ok(Async::Ready(Some(())))
+    }
+}
@@ -0,0 +1,92 @@
+//! Exit notifications via kqueue on macOS and FreeBSD.
+//!
+//! A kqueue can watch a specific process with `EVFILT_PROC` and `NOTE_EXIT`,
+//! and a kqueue descriptor is itself readable whenever it has pending events.
+//! Giving each child a kqueue of its own and registering that with the
+//! reactor lets us wait on a single child without having to check on *every*
+//! spawned child whenever a SIGCHLD is received.
+
+use std::io;
+use std::mem;
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::ptr;
+use super::libc;
+
+/// An owned kqueue watching for the exit of a single process, which is
+/// closed on drop.
+#[derive(Debug)]
+pub(crate) struct ProcKqueue(RawFd);
+
+impl ProcKqueue {
+    /// Attempts to create a kqueue which becomes readable once the process
+    /// `pid` exits, returning `None` if the process could not be watched.
+    ///
+    /// The caller must ensure that `pid` refers to an unreaped child,
+    /// otherwise the pid may have already been recycled.
+    pub(crate) fn open(pid: u32) -> Option<Self> {
+        let fd = unsafe { libc::kqueue() };
+        if fd == -1 {
+            debug!("failed to create kqueue for process {}: {}", pid, io::Error::last_os_error());
+            return None;
+        }
+
+        // Closes the kqueue if registration fails below
+        let kqueue = ProcKqueue(fd);
+
+        let mut event: libc::kevent = unsafe { mem::zeroed() };
+        event.ident = pid as libc::uintptr_t;
+        event.filter = libc::EVFILT_PROC;
+        event.flags = libc::EV_ADD;
+        event.fflags = libc::NOTE_EXIT;
+
+        let ret = unsafe {
+            libc::kevent(kqueue.0, &event, 1, ptr::null_mut(), 0, ptr::null())
+        };
+
+        if ret == -1 {
+            debug!("failed to watch process {} with kqueue: {}", pid, io::Error::last_os_error());
+            return None;
+        }
+
+        Some(kqueue)
+    }
+}
+
+impl AsRawFd for ProcKqueue {
+    fn as_raw_fd(&self) -> RawFd {
+        self.0
+    }
+}
+
+impl Drop for ProcKqueue {
+    fn drop(&mut self) {
+        unsafe {
+            libc::close(self.0);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::os::unix::io::AsRawFd;
+    use std::process::Command;
+    use super::ProcKqueue;
+    use super::libc;
+
+    #[test]
+    fn readable_once_child_exits() {
+        let mut child = Command::new("true").spawn().expect("failed to spawn");
+
+        if let Some(kqueue) = ProcKqueue::open(child.id()) {
+            let mut pollfd = libc::pollfd {
+                fd: kqueue.as_raw_fd(),
+                events: libc::POLLIN,
+                revents: 0,
+            };
+
+            assert_eq!(1, unsafe { libc::poll(&mut pollfd, 1, 5000) });
+        }
+
+        assert!(child.wait().expect("failed to wait").success());
+    }
+}
@@ -20,30 +20,56 @@
 //! Note that this means that this isn't really scalable, but then again
 //! processes in general aren't scalable (e.g. millions) so it shouldn't be that
 //! bad in theory...
+//!
+//! On Linux we can do better when the kernel supports `pidfd_open(2)`: each
+//! child gets its own file descriptor registered with the reactor which
+//! becomes readable exactly when that child exits, so we only ever check on
+//! the children which may have actually exited. See the `pidfd` module. On
+//! macOS and FreeBSD the same is achieved by giving each child a kqueue which
+//! watches for its exit, see the `kqueue` module.
 
 extern crate libc;
 extern crate mio;
 extern crate tokio_signal;
 
-mod orphan;
-mod reap;
+#[cfg(any(target_os = "linux", target_os = "macos", target_os = "freebsd"))]
+mod exit_fd;
+#[cfg(any(target_os = "macos", target_os = "freebsd"))]
+mod kqueue;
+pub(crate) mod orphan;
+#[cfg(target_os = "linux")]
+mod pidfd;
+pub(crate) mod pty;
+pub(crate) mod reap;
+pub(crate) mod sigchld;
+#[cfg(target_os = "linux")]
+pub(crate) mod splice;
+mod subreaper;
 
-use futures::future::FlattenStream;
-use futures::{Future, Poll};
+use futures::{Async, Future, Poll, Stream};
+use events::ChildEvent;
 use kill::Kill;
 use self::mio::{Poll as MioPoll, PollOpt, Ready, Token};
 use self::mio::unix::{EventedFd, UnixReady};
 use self::mio::event::Evented;
 use self::orphan::{AtomicOrphanQueue, OrphanQueue, Wait};
-use self::reap::Reaper;
+use self::reap::{Generation, Reaper};
+use self::sigchld::Subscription;
+use self::subreaper::TrackedChild;
 use self::tokio_signal::unix::Signal;
+#[cfg(target_os = "linux")]
+use std::cmp;
 use std::fmt;
 use std::io;
+use std::mem;
 use std::os::unix::io::{AsRawFd, RawFd};
+use std::os::unix::process::{CommandExt, ExitStatusExt};
 use std::process::{self, ExitStatus};
-use super::SpawnedChild;
-use tokio_io::IoFuture;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use super::{ResourceUsage, SpawnOptions, SpawnedChild};
 use tokio_reactor::{Handle, PollEvented};
+use tokio_timer::Delay;
 
 impl Wait for process::Child {
     fn id(&self) -> u32 {
@@ -62,30 +88,263 @@ impl Kill for process::Child {
 }
 
 lazy_static! {
-    static ref ORPHAN_QUEUE: AtomicOrphanQueue<process::Child> = AtomicOrphanQueue::new();
+    static ref ORPHAN_QUEUE: Orphans = Orphans::new();
+    static ref SIGCHLD_GENERATION: Generation = Generation::new();
 }
 
-struct GlobalOrphanQueue;
+/// A shared queue of orphaned children which still need to be reaped.
+///
+/// Children are reaped through the queue they were spawned with, so children
+/// spawned with distinct queues never need to contend on (or wait for) each
+/// other's orphans.
+#[derive(Clone)]
+pub(crate) struct Orphans(Arc<AtomicOrphanQueue<TrackedChild>>);
+
+impl Orphans {
+    pub(crate) fn new() -> Self {
+        Orphans(Arc::new(AtomicOrphanQueue::new()))
+    }
+
+    /// The process-wide queue used by default.
+    pub(crate) fn global() -> Self {
+        ORPHAN_QUEUE.clone()
+    }
+
+    pub(crate) fn reap_orphans_now(&self, timeout: Duration) -> Vec<u32> {
+        subreaper::reap_reparented();
+        self.0.reap_orphans_now(timeout)
+    }
 
-impl fmt::Debug for GlobalOrphanQueue {
+    pub(crate) fn reap_orphan(&self, pid: u32) -> io::Result<Option<ExitStatus>> {
+        self.0.reap_one(pid)
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.0.len()
+    }
+}
+
+impl fmt::Debug for Orphans {
     fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
-        ORPHAN_QUEUE.fmt(fmt)
+        self.0.fmt(fmt)
     }
 }
 
-impl OrphanQueue<process::Child> for GlobalOrphanQueue {
-    fn push_orphan(&self, orphan: process::Child) {
-        ORPHAN_QUEUE.push_orphan(orphan)
+impl OrphanQueue<TrackedChild> for Orphans {
+    fn push_orphan(&self, orphan: TrackedChild) {
+        self.0.push_orphan(orphan)
     }
 
     fn reap_orphans(&self) {
-        ORPHAN_QUEUE.reap_orphans()
+        subreaper::reap_reparented();
+        self.0.reap_orphans()
+    }
+
+    fn reap_orphans_reporting(&self) -> Vec<(u32, io::Error)> {
+        subreaper::reap_reparented();
+        self.0.reap_orphans_reporting()
+    }
+
+    fn reap_one(&self, pid: u32) -> io::Result<Option<ExitStatus>> {
+        self.0.reap_one(pid)
+    }
+}
+
+/// A task which reaps the orphans of a queue as soon as they exit, rather
+/// than only whenever another child happens to be polled.
+pub(crate) struct OrphanReaper {
+    orphans: Orphans,
+    signal: SignalStream,
+}
+
+impl OrphanReaper {
+    pub(crate) fn new(orphans: Orphans, handle: &Handle, options: &SpawnOptions) -> io::Result<Self> {
+        let signal = match options.reap_signal {
+            Some(signal) => signal_stream(signal, handle)?,
+            None => sigchld_stream(handle)?,
+        };
+
+        Ok(OrphanReaper {
+            orphans,
+            signal,
+        })
+    }
+}
+
+impl fmt::Debug for OrphanReaper {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.debug_struct("OrphanReaper")
+            .field("orphans", &self.orphans)
+            .finish()
+    }
+}
+
+impl Future for OrphanReaper {
+    type Item = ();
+    type Error = io::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        loop {
+            // Reap before waiting for the next signal so that any orphans
+            // which exited before we started listening aren't missed.
+            self.orphans.reap_orphans();
+
+            if try_ready!(self.signal.poll()).is_none() {
+                return Ok(Async::Ready(()));
+            }
+        }
+    }
+}
+
+/// A stream of the signals received by the current process.
+pub type SignalStream = Box<dyn Stream<Item = libc::c_int, Error = io::Error> + Send>;
+
+/// Creates a stream of the `SIGCHLD` signals received by the current process,
+/// registered with the event loop of `handle`.
+///
+/// This is the same stream children use to learn that they may have exited
+/// when no per-child exit notifications are available. Any error registering
+/// for the signal is reported when the stream is first polled.
+pub fn sigchld_stream(handle: &Handle) -> io::Result<SignalStream> {
+    signal_stream(libc::SIGCHLD, handle)
+}
+
+fn signal_stream(signal: libc::c_int, handle: &Handle) -> io::Result<SignalStream> {
+    Ok(Box::new(Signal::with_handle(signal, handle).flatten_stream()))
+}
+
+/// A descriptor which becomes readable once a specific child exits.
+#[cfg(target_os = "linux")]
+type ExitFd = self::pidfd::PidFd;
+#[cfg(any(target_os = "macos", target_os = "freebsd"))]
+type ExitFd = self::kqueue::ProcKqueue;
+
+/// The source of notifications that a child may have exited.
+enum ExitEvents {
+    // The stream is only ever polled through `&mut self`, so the mutex is
+    // never locked, it merely allows `Child` to be `Sync`.
+    Signal(Mutex<SignalStream>),
+    SharedSignal(Subscription),
+    #[cfg(any(target_os = "linux", target_os = "macos", target_os = "freebsd"))]
+    Fd(self::exit_fd::ExitFdStream<ExitFd>),
+}
+
+impl ExitEvents {
+    fn new(child: &process::Child, handle: &Handle, options: &SpawnOptions)
+        -> io::Result<Self>
+    {
+        // Exit descriptors are only notified when the child exits, whereas
+        // SIGCHLD is also delivered when it is stopped or continued.
+        if !options.job_control {
+            if let Some(events) = Self::exit_fd(child, handle)? {
+                return Ok(events);
+            }
+        }
+
+        if let Some(ref sigchld) = options.sigchld {
+            return Ok(ExitEvents::SharedSignal(sigchld.subscribe()));
+        }
+
+        let signal = match options.reap_signal {
+            Some(signal) => signal_stream(signal, handle)?,
+            None => sigchld_stream(handle)?,
+        };
+        Ok(ExitEvents::Signal(Mutex::new(signal)))
+    }
+
+    #[cfg(any(target_os = "linux", target_os = "macos", target_os = "freebsd"))]
+    fn exit_fd(child: &process::Child, handle: &Handle) -> io::Result<Option<Self>> {
+        match ExitFd::open(child.id()) {
+            Some(fd) => Ok(Some(ExitEvents::Fd(self::exit_fd::ExitFdStream::new(fd, handle)?))),
+            None => Ok(None),
+        }
+    }
+
+    #[cfg(not(any(target_os = "linux", target_os = "macos", target_os = "freebsd")))]
+    fn exit_fd(_child: &process::Child, _handle: &Handle) -> io::Result<Option<Self>> {
+        Ok(None)
+    }
+
+    /// The generation counter to track these events with.
+    ///
+    /// Every SIGCHLD is delivered to every child listening for it, so all
+    /// such children share a counter. Children listening for a different
+    /// reap signal share it as well, which at worst makes them check on
+    /// themselves a little more often than needed. An exit descriptor only
+    /// ever concerns a single child and thus gets a counter of its own.
+    fn generation(&self) -> Generation {
+        match *self {
+            ExitEvents::Signal(_) |
+            ExitEvents::SharedSignal(_) => SIGCHLD_GENERATION.clone(),
+            #[cfg(any(target_os = "linux", target_os = "macos", target_os = "freebsd"))]
+            ExitEvents::Fd(_) => Generation::new(),
+        }
+    }
+}
+
+impl Stream for ExitEvents {
+    type Item = ();
+    type Error = io::Error;
+
+    fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        match *self {
+            ExitEvents::Signal(ref mut signal) => {
+                let signal = signal.get_mut().expect("signal mutex is never locked");
+                Ok(try_ready!(signal.poll()).map(|_| ()).into())
+            },
+            ExitEvents::SharedSignal(ref mut subscription) => subscription.poll(),
+            #[cfg(any(target_os = "linux", target_os = "macos", target_os = "freebsd"))]
+            ExitEvents::Fd(ref mut fd) => fd.poll(),
+        }
+    }
+}
+
+/// Exit notifications, optionally backed by checking on the child whenever
+/// none have arrived for a while, in case they are not delivered reliably.
+struct ChildEvents {
+    events: ExitEvents,
+    fallback: Option<(Duration, Delay)>,
+}
+
+impl ChildEvents {
+    fn new(events: ExitEvents, options: &SpawnOptions) -> Self {
+        ChildEvents {
+            events,
+            fallback: options.reap_poll_interval.map(|interval| {
+                (interval, Delay::new(Instant::now() + interval))
+            }),
+        }
+    }
+}
+
+impl Stream for ChildEvents {
+    type Item = ();
+    type Error = io::Error;
+
+    fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        let ret = self.events.poll()?;
+
+        let (interval, ref mut delay) = match self.fallback {
+            Some(ref mut fallback) => (fallback.0, &mut fallback.1),
+            None => return Ok(ret),
+        };
+
+        if ret.is_ready() {
+            delay.reset(Instant::now() + interval);
+            return Ok(ret);
+        }
+
+        try_ready!(delay.poll().map_err(io::Error::other));
+        delay.reset(Instant::now() + interval);
+        Ok(Async::Ready(Some(())))
     }
 }
 
 #[must_use = "futures do nothing unless polled"]
 pub struct Child {
-    inner: Reaper<process::Child, GlobalOrphanQueue, FlattenStream<IoFuture<Signal>>>,
+    inner: Reaper<TrackedChild, Orphans, ChildEvents>,
+    kill_tree: bool,
+    job_control: bool,
 }
 
 impl fmt::Debug for Child {
@@ -96,16 +355,91 @@ impl fmt::Debug for Child {
     }
 }
 
-pub(crate) fn spawn_child(cmd: &mut process::Command, handle: &Handle) -> io::Result<SpawnedChild> {
-    let mut child = cmd.spawn()?;
-    let stdin = stdio(child.stdin.take(), handle)?;
-    let stdout = stdio(child.stdout.take(), handle)?;
-    let stderr = stdio(child.stderr.take(), handle)?;
+/// Fails early if the reactor behind `handle` has gone away.
+///
+/// Registering the child's stdio and exit notifications would fail anyway,
+/// but only once the child has already been spawned (and then has to be
+/// killed again), with a rather cryptic error.
+///
+/// Note that not running inside a runtime isn't an error: the default handle
+/// then falls back to a reactor which tokio runs in the background.
+pub(crate) fn check_reactor(handle: &Handle) -> io::Result<()> {
+    // A purely user space registration, which doesn't cost a descriptor
+    let (registration, _readiness) = mio::Registration::new2();
+    match PollEvented::new_with_handle(registration, handle) {
+        Ok(_) => Ok(()),
+        Err(e) => Err(io::Error::new(e.kind(), format!("no tokio reactor is running to \
+                                                        drive the child: {}", e))),
+    }
+}
+
+pub(crate) fn spawn_child(cmd: &mut process::Command,
+                          handle: &Handle,
+                          orphans: Orphans,
+                          options: &SpawnOptions)
+    -> io::Result<SpawnedChild>
+{
+    if options.kill_tree {
+        // Make the child the leader of a new process group so that the whole
+        // group can be signalled together, its descendants will join it by
+        // default.
+        cmd.process_group(0);
+    }
+
+    // Reparented children mustn't be reaped until the child is tracked
+    let _spawning = subreaper::spawning();
+    let child = options.spawn_std(cmd)?;
 
-    let signal = Signal::with_handle(libc::SIGCHLD, handle).flatten_stream();
+    #[cfg(target_os = "linux")]
+    {
+        if let Some(capacity) = options.pipe_capacity {
+            set_pipe_capacity(child.stdin.as_ref(), capacity);
+            set_pipe_capacity(child.stdout.as_ref(), capacity);
+            set_pipe_capacity(child.stderr.as_ref(), capacity);
+        }
+    }
+
+    let mut spawned = wrap(child, handle, orphans, options)?;
+    spawned.child.kill_tree = options.kill_tree;
+    spawned.child.job_control = options.job_control;
+    Ok(spawned)
+}
+
+pub(crate) fn from_std(child: process::Child, handle: &Handle, orphans: Orphans)
+    -> io::Result<SpawnedChild>
+{
+    wrap(child, handle, orphans, &SpawnOptions::default())
+}
+
+fn wrap(mut child: process::Child,
+        handle: &Handle,
+        orphans: Orphans,
+        options: &SpawnOptions)
+    -> io::Result<SpawnedChild>
+{
+    let setup = (|| {
+        let stdin = stdio(child.stdin.take(), handle)?;
+        let stdout = stdio(child.stdout.take(), handle)?;
+        let stderr = stdio(child.stderr.take(), handle)?;
+        let events = ExitEvents::new(&child, handle, options)?;
+        Ok((stdin, stdout, stderr, events))
+    })();
+
+    let (stdin, stdout, stderr, events) = match setup {
+        Ok(setup) => setup,
+        Err(e) => {
+            abandon(child, &orphans, options);
+            return Err(e);
+        },
+    };
+
+    let generation = events.generation();
+    let events = ChildEvents::new(events, options);
     Ok(SpawnedChild {
         child: Child {
-            inner: Reaper::new(child, GlobalOrphanQueue, signal),
+            inner: Reaper::new(TrackedChild::new(child), orphans, events, generation),
+            kill_tree: false,
+            job_control: false,
         },
         stdin,
         stdout,
@@ -113,15 +447,195 @@ pub(crate) fn spawn_child(cmd: &mut process::Command, handle: &Handle) -> io::Re
     })
 }
 
+/// Kills a child which could not be set up after it was spawned, leaving it
+/// to be reaped through `orphans` rather than leaking it.
+fn abandon(mut child: process::Child, orphans: &Orphans, options: &SpawnOptions) {
+    if options.kill_tree {
+        unsafe {
+            libc::killpg(child.id() as libc::pid_t, libc::SIGKILL);
+        }
+    }
+    drop(child.kill());
+
+    if let Ok(Some(_)) = child.try_wait() {
+        return;
+    }
+
+    orphans.push_orphan(TrackedChild::new(child));
+}
+
 impl Child {
     pub fn id(&self) -> u32 {
         self.inner.id()
     }
+
+    /// The start time of the child in clock ticks since boot, as reported by
+    /// the `starttime` field of `/proc/<pid>/stat`.
+    ///
+    /// `None` if the process with the child's id isn't a child of ours, in
+    /// which case the child was already reaped (e.g. before being adopted via
+    /// `from_std`) and the id may have been reused.
+    #[cfg(target_os = "linux")]
+    pub fn start_time(&self) -> Option<u64> {
+        let stat = ::std::fs::read_to_string(format!("/proc/{}/stat", self.id())).ok()?;
+        // The command name may contain spaces and parentheses, so the fields
+        // are counted from the last closing parenthesis (after field 2)
+        let mut fields = stat.rsplit(')').next()?.split_whitespace();
+        let ppid = fields.nth(1)?;
+        if ppid != process::id().to_string() {
+            return None;
+        }
+
+        fields.nth(17)?.parse().ok()
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    pub fn start_time(&self) -> Option<u64> {
+        None
+    }
+
+    pub fn current_id(&self) -> Option<u32> {
+        if self.inner.is_reaped() {
+            None
+        } else {
+            Some(self.id())
+        }
+    }
+
+    pub fn try_wait(&mut self) -> io::Result<Option<ExitStatus>> {
+        self.inner.try_reap()
+    }
+
+    pub fn into_std(self) -> process::Child {
+        self.inner.into_inner().into_inner()
+    }
+
+    pub fn status(&self) -> Option<ExitStatus> {
+        self.inner.status()
+    }
+
+    pub fn raw_wait_status(&self) -> Option<libc::c_int> {
+        self.status().map(ExitStatus::into_raw)
+    }
+
+    /// Polls for the next state change of the child, which is only ever its
+    /// exit unless the child was spawned with job control tracking.
+    pub fn poll_event(&mut self) -> Poll<ChildEvent, io::Error> {
+        if !self.job_control {
+            let status = try_ready!(self.inner.poll());
+            return Ok(Async::Ready(ChildEvent::Exited(status)));
+        }
+
+        let pid = self.id() as libc::pid_t;
+        self.inner.poll_with(|reaper| {
+            // The pid may have been recycled once the child has been reaped
+            if !reaper.is_reaped() {
+                if let Some(event) = wait_job_control(pid)? {
+                    return Ok(Some(event));
+                }
+            }
+
+            Ok(reaper.try_reap()?.map(ChildEvent::Exited))
+        })
+    }
+
+    /// Polls for the child's exit, reaping it via `wait4(2)` in order to
+    /// collect its resource usage, with `spawned` being when it was spawned.
+    ///
+    /// This fails if the child has already been reaped by other means, as its
+    /// resource usage can no longer be collected then.
+    pub fn poll_rusage(&mut self, spawned: Instant) -> Poll<(ExitStatus, ResourceUsage), io::Error> {
+        if self.inner.is_reaped() {
+            return Err(io::Error::other("the resource usage of a child which has \
+                                         already been reaped is unavailable"));
+        }
+
+        let pid = self.id() as libc::pid_t;
+        self.inner.poll_with(|reaper| {
+            let (status, usage) = match wait4(pid)? {
+                Some(ret) => ret,
+                None => return Ok(None),
+            };
+
+            reaper.set_reaped(status);
+            Ok(Some((status, ResourceUsage {
+                wall_time: spawned.elapsed(),
+                user_time: timeval_to_duration(usage.ru_utime),
+                system_time: timeval_to_duration(usage.ru_stime),
+                max_rss: max_rss_bytes(usage.ru_maxrss),
+            })))
+        })
+    }
+
+    /// Requests that the child exit by delivering a `SIGTERM`.
+    pub fn terminate(&mut self) -> io::Result<()> {
+        self.signal(libc::SIGTERM)
+    }
+
+    pub fn signal(&mut self, signal: libc::c_int) -> io::Result<()> {
+        // Once a child has been reaped its pid may be recycled by the OS, so
+        // we must never signal it again. Until we reap it, however, the pid
+        // remains reserved (even if the child has become a zombie), so it is
+        // safe to deliver the signal as long as this check passes.
+        if self.inner.try_reap()?.is_some() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "cannot signal a process which has already exited",
+            ));
+        }
+
+        let ret = unsafe { libc::kill(self.id() as libc::pid_t, signal) };
+        if ret == -1 {
+            Err(io::Error::last_os_error())
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Sends `signal` to the process group led by the child.
+    ///
+    /// Like `signal`, this is refused once the child has been reaped: should
+    /// the rest of the group have exited too by then, its id may have been
+    /// recycled for an unrelated process (and the group it leads).
+    pub fn signal_group(&mut self, signal: libc::c_int) -> io::Result<()> {
+        if self.inner.try_reap()?.is_some() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "cannot signal the process group of a process which has already exited",
+            ));
+        }
+
+        self.killpg(signal)
+    }
+
+    fn killpg(&self, signal: libc::c_int) -> io::Result<()> {
+        let ret = unsafe { libc::killpg(self.id() as libc::pid_t, signal) };
+        if ret == -1 {
+            Err(io::Error::last_os_error())
+        } else {
+            Ok(())
+        }
+    }
+
+    pub fn kill_group(&mut self) -> io::Result<()> {
+        self.signal_group(libc::SIGKILL)
+    }
 }
 
 impl Kill for Child {
     fn kill(&mut self) -> io::Result<()> {
-        self.inner.kill()
+        if !self.kill_tree {
+            return self.inner.kill();
+        }
+
+        // The group may outlive the child itself, so we signal it even if
+        // the child has already exited, as documented on `kill_process_tree`
+        // (at the risk of the group id having been recycled). There is
+        // nothing left to kill if the group no longer exists.
+        match self.killpg(libc::SIGKILL) {
+            Err(ref e) if e.raw_os_error() == Some(libc::ESRCH) => Ok(()),
+            ret => ret,
+        }
     }
 }
 
@@ -134,28 +648,129 @@ impl Future for Child {
     }
 }
 
+/// Consumes a pending stop or continue notification of the child `pid`,
+/// without ever reaping it should it have exited.
+fn wait_job_control(pid: libc::pid_t) -> io::Result<Option<ChildEvent>> {
+    let mut info: libc::siginfo_t = unsafe { mem::zeroed() };
+    let flags = libc::WSTOPPED | libc::WCONTINUED | libc::WNOHANG;
+
+    if unsafe { libc::waitid(libc::P_PID, pid as libc::id_t, &mut info, flags) } == -1 {
+        let err = io::Error::last_os_error();
+
+        // A child which has exited can't change its state anymore, so it is
+        // no longer considered a candidate to wait for.
+        if err.raw_os_error() == Some(libc::ECHILD) {
+            return Ok(None);
+        }
+
+        return Err(err);
+    }
+
+    // No state change is reported by leaving the info zeroed
+    if unsafe { info.si_pid() } == 0 {
+        return Ok(None);
+    }
+
+    match info.si_code {
+        libc::CLD_STOPPED | libc::CLD_TRAPPED => {
+            Ok(Some(ChildEvent::Stopped(unsafe { info.si_status() })))
+        },
+        libc::CLD_CONTINUED => Ok(Some(ChildEvent::Continued)),
+        _ => Ok(None),
+    }
+}
+
+/// Reaps the child `pid` if it has exited, along with its resource usage.
+fn wait4(pid: libc::pid_t) -> io::Result<Option<(ExitStatus, libc::rusage)>> {
+    let mut status = 0;
+    let mut usage: libc::rusage = unsafe { mem::zeroed() };
+
+    let ret = retry_on_eintr(|| unsafe {
+        libc::wait4(pid, &mut status, libc::WNOHANG, &mut usage)
+    })?;
+
+    if ret == 0 {
+        return Ok(None);
+    }
+
+    Ok(Some((ExitStatus::from_raw(status), usage)))
+}
+
+fn timeval_to_duration(tv: libc::timeval) -> Duration {
+    Duration::new(tv.tv_sec as u64, tv.tv_usec as u32 * 1000)
+}
+
+/// Converts the maximum resident set size reported by `getrusage(2)`, which
+/// is in kilobytes except on Apple platforms, into bytes.
+fn max_rss_bytes(max_rss: libc::c_long) -> u64 {
+    let max_rss = max_rss as u64;
+    if cfg!(any(target_os = "macos", target_os = "ios")) {
+        max_rss
+    } else {
+        max_rss * 1024
+    }
+}
+
+/// A file descriptor which can be registered with the reactor.
+///
+/// By default every registration also asks to be notified of the descriptor
+/// being hung up on, which is what pipes need to report the other end being
+/// closed on every platform. Other kinds of descriptors may not want that.
 #[derive(Debug)]
-pub struct Fd<T>(T);
+pub struct Fd<T> {
+    inner: T,
+    hup: bool,
+}
+
+impl<T> Fd<T> {
+    /// Wraps `inner`, registering interest in it being hung up on in
+    /// addition to the interest requested by whoever registers it.
+    pub fn new(inner: T) -> Self {
+        Self::with_hup(inner, true)
+    }
+
+    /// Wraps `inner`, only registering interest in it being hung up on in
+    /// addition to the requested interest if `hup` is set.
+    ///
+    /// Note that some platforms (e.g. Linux) always report a descriptor
+    /// being hung up on, regardless of the registered interest.
+    pub fn with_hup(inner: T, hup: bool) -> Self {
+        Fd { inner, hup }
+    }
+
+    /// Consumes the wrapper, returning the wrapped descriptor.
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+
+    fn interest(&self, interest: Ready) -> Ready {
+        if self.hup {
+            interest | UnixReady::hup()
+        } else {
+            interest
+        }
+    }
+}
 
 impl<T: io::Read> io::Read for Fd<T> {
     fn read(&mut self, bytes: &mut [u8]) -> io::Result<usize> {
-        self.0.read(bytes)
+        self.inner.read(bytes)
     }
 }
 
 impl<T: io::Write> io::Write for Fd<T> {
     fn write(&mut self, bytes: &[u8]) -> io::Result<usize> {
-        self.0.write(bytes)
+        self.inner.write(bytes)
     }
 
     fn flush(&mut self) -> io::Result<()> {
-        self.0.flush()
+        self.inner.flush()
     }
 }
 
 impl<T> AsRawFd for Fd<T> where T: AsRawFd {
     fn as_raw_fd(&self) -> RawFd {
-        self.0.as_raw_fd()
+        self.inner.as_raw_fd()
     }
 }
 
@@ -163,6 +778,36 @@ pub type ChildStdin = PollEvented<Fd<process::ChildStdin>>;
 pub type ChildStdout = PollEvented<Fd<process::ChildStdout>>;
 pub type ChildStderr = PollEvented<Fd<process::ChildStderr>>;
 
+/// Writes to the stdin of a child, arranging for the current task to be
+/// notified once the write may succeed (or fail) if it would block.
+///
+/// Once a child closes its end of a full pipe, the pipe is reported as having
+/// an error, but never as writable again, and the reactor only notifies tasks
+/// reading from a descriptor of errors. Stdin is never read from, so a write
+/// which would block also waits for errors as a reader, which otherwise would
+/// never be woken up.
+pub(crate) fn write_stdin(io: &mut ChildStdin, buf: &[u8]) -> io::Result<usize> {
+    match io::Write::write(io, buf) {
+        Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => {},
+        ret => return ret,
+    }
+
+    let error = Ready::from(UnixReady::error());
+    if let Async::NotReady = io.poll_read_ready(error)? {
+        return Err(io::ErrorKind::WouldBlock.into());
+    }
+
+    // The descriptor isn't writable as far as the reactor is concerned, so
+    // the write must bypass it to surface the error
+    match io::Write::write(io.get_mut(), buf) {
+        Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => {
+            io.clear_read_ready(error)?;
+            Err(io::ErrorKind::WouldBlock.into())
+        },
+        ret => ret,
+    }
+}
+
 impl<T> Evented for Fd<T> where T: AsRawFd {
     fn register(&self,
                 poll: &MioPoll,
@@ -172,7 +817,7 @@ impl<T> Evented for Fd<T> where T: AsRawFd {
                 -> io::Result<()> {
         EventedFd(&self.as_raw_fd()).register(poll,
                                               token,
-                                              interest | UnixReady::hup(),
+                                              self.interest(interest),
                                               opts)
     }
 
@@ -184,7 +829,7 @@ impl<T> Evented for Fd<T> where T: AsRawFd {
                   -> io::Result<()> {
         EventedFd(&self.as_raw_fd()).reregister(poll,
                                                 token,
-                                                interest | UnixReady::hup(),
+                                                self.interest(interest),
                                                 opts)
     }
 
@@ -197,23 +842,382 @@ fn stdio<T>(option: Option<T>, handle: &Handle)
             -> io::Result<Option<PollEvented<Fd<T>>>>
     where T: AsRawFd
 {
+    // Streams which weren't piped (e.g. inherited, `Stdio::null()` or a
+    // descriptor of the caller's via `Stdio::from`) have no handle in the
+    // parent, so there is nothing to register.
     let io = match option {
         Some(io) => io,
         None => return Ok(None),
     };
 
-    // Set the fd to nonblocking before we pass it to the event loop
+    #[cfg(test)]
+    test::inject_stdio_fault()?;
+
+    // Set the fd to nonblocking before we pass it to the event loop. This
+    // only ever happens in the parent after the child has been spawned, and
+    // only affects the parent's end of the pipe: the child's end is a
+    // separate open file description, so the child always sees blocking
+    // stdio (unless it was explicitly configured otherwise, e.g. by a
+    // pre-exec hook).
+    set_nonblocking(&io, true)?;
+    let io = PollEvented::new_with_handle(Fd::new(io), handle)?;
+    Ok(Some(io))
+}
+
+/// Deregisters a stdio handle from its event loop, restoring it to blocking
+/// mode so it can be used with synchronous code.
+pub(crate) fn into_std_stdio<T>(io: PollEvented<Fd<T>>, nonblocking: bool) -> io::Result<T>
+    where T: AsRawFd
+{
+    let io = io.into_inner()?.into_inner();
+    if !nonblocking {
+        set_nonblocking(&io, false)?;
+    }
+    Ok(io)
+}
+
+/// Attempts to resize the pipe behind `io`, leaving it at its current
+/// capacity if that fails.
+#[cfg(target_os = "linux")]
+fn set_pipe_capacity<T: AsRawFd>(pipe: Option<&T>, capacity: usize) {
+    let pipe = match pipe {
+        Some(pipe) => pipe,
+        None => return,
+    };
+
+    let capacity = cmp::min(capacity, libc::c_int::MAX as usize) as libc::c_int;
+    if unsafe { libc::fcntl(pipe.as_raw_fd(), libc::F_SETPIPE_SZ, capacity) } == -1 {
+        debug!("failed to set pipe capacity to {}: {}", capacity, io::Error::last_os_error());
+    }
+}
+
+/// A resource limit to apply to a child before it executes its command.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Rlimit {
+    resource: libc::c_int,
+    limit: libc::rlimit,
+}
+
+impl Rlimit {
+    pub(crate) fn new(resource: libc::c_int, soft: u64, hard: u64) -> Self {
+        // Anything the platform can't represent (e.g. above 4 GiB where
+        // `rlim_t` has 32 bits) is as good as no limit at all.
+        fn to_rlim(limit: u64) -> libc::rlim_t {
+            use std::convert::TryFrom;
+
+            libc::rlim_t::try_from(limit)
+                .map_or(libc::RLIM_INFINITY, |limit| cmp::min(limit, libc::RLIM_INFINITY))
+        }
+
+        Rlimit {
+            resource,
+            limit: libc::rlimit {
+                rlim_cur: to_rlim(soft),
+                rlim_max: to_rlim(hard),
+            },
+        }
+    }
+
+    /// Applies the limit to the current process, which must only perform
+    /// async-signal-safe operations as it is called after `fork`.
+    pub(crate) fn apply(&self) -> io::Result<()> {
+        if unsafe { libc::setrlimit(self.resource as _, &self.limit) } == -1 {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(())
+    }
+}
+
+/// A set of CPUs to pin a child to before it executes its command.
+#[cfg(target_os = "linux")]
+#[derive(Clone, Copy)]
+pub(crate) struct CpuAffinity {
+    // `None` if the set is empty or refers to CPUs which don't exist
+    set: Option<libc::cpu_set_t>,
+}
+
+#[cfg(target_os = "linux")]
+impl CpuAffinity {
+    pub(crate) fn new(cpus: &[usize]) -> Self {
+        let configured = unsafe { libc::sysconf(libc::_SC_NPROCESSORS_CONF) };
+        let limit = cmp::min(cmp::max(configured, 0) as usize, libc::CPU_SETSIZE as usize);
+
+        if cpus.is_empty() || cpus.iter().any(|&cpu| cpu >= limit) {
+            return CpuAffinity { set: None };
+        }
+
+        let mut set: libc::cpu_set_t = unsafe { mem::zeroed() };
+        for &cpu in cpus {
+            unsafe { libc::CPU_SET(cpu, &mut set) };
+        }
+
+        CpuAffinity { set: Some(set) }
+    }
+
+    /// Applies the affinity to the current process, which must only perform
+    /// async-signal-safe operations as it is called after `fork`.
+    pub(crate) fn apply(&self) -> io::Result<()> {
+        let set = match self.set {
+            Some(ref set) => set,
+            None => return Err(io::Error::from_raw_os_error(libc::EINVAL)),
+        };
+
+        if unsafe { libc::sched_setaffinity(0, mem::size_of::<libc::cpu_set_t>(), set) } == -1 {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(())
+    }
+}
+
+/// Sets the niceness of the child spawned by `cmd` to `priority`.
+pub(crate) fn set_priority(cmd: &mut process::Command, priority: i32) {
+    // Safe since setting the priority is async-signal-safe
+    unsafe {
+        cmd.pre_exec(move || {
+            if libc::setpriority(libc::PRIO_PROCESS as _, 0, priority) == -1 {
+                return Err(io::Error::last_os_error());
+            }
+
+            Ok(())
+        });
+    }
+}
+
+/// Sets the file mode creation mask of the child spawned by `cmd` to `mode`.
+pub(crate) fn set_umask(cmd: &mut process::Command, mode: u32) {
+    let mode = (mode & 0o777) as libc::mode_t;
+
+    // Safe since setting the mask is async-signal-safe
+    unsafe {
+        cmd.pre_exec(move || {
+            libc::umask(mode);
+            Ok(())
+        });
+    }
+}
+
+/// Returns whether killing a child failed since it had already exited.
+pub(crate) fn is_already_exited(e: &io::Error) -> bool {
+    e.raw_os_error() == Some(libc::ESRCH) || e.kind() == io::ErrorKind::InvalidInput
+}
+
+/// Makes the child spawned by `cmd` the leader of a new session.
+pub(crate) fn start_session(cmd: &mut process::Command) {
+    // Safe since creating a session is async-signal-safe
+    unsafe {
+        cmd.pre_exec(|| {
+            if libc::setsid() == -1 {
+                return Err(io::Error::last_os_error());
+            }
+
+            Ok(())
+        });
+    }
+}
+
+/// Returns whether the default action of `signal` is to terminate the
+/// process, as opposed to e.g. ignoring it or stopping the process.
+pub(crate) fn terminates_by_default(signal: libc::c_int) -> bool {
+    match signal {
+        0 | libc::SIGCHLD | libc::SIGCONT | libc::SIGURG | libc::SIGWINCH => false,
+        libc::SIGSTOP | libc::SIGTSTP | libc::SIGTTIN | libc::SIGTTOU => false,
+        #[cfg(any(target_os = "macos",
+                  target_os = "ios",
+                  target_os = "freebsd",
+                  target_os = "dragonfly",
+                  target_os = "netbsd",
+                  target_os = "openbsd"))]
+        libc::SIGINFO => false,
+        _ => true,
+    }
+}
+
+/// Marks the current process as a child subreaper.
+#[cfg(target_os = "linux")]
+pub(crate) fn become_subreaper() -> io::Result<()> {
+    if unsafe { libc::prctl(libc::PR_SET_CHILD_SUBREAPER, 1, 0, 0, 0) } == -1 {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(())
+}
+
+/// Marks the current process as a child subreaper which reaps any children
+/// reparented to it.
+#[cfg(target_os = "linux")]
+pub(crate) fn reap_reparented_children() -> io::Result<()> {
+    become_subreaper()?;
+    subreaper::enable();
+    ORPHAN_QUEUE.reap_orphans();
+    Ok(())
+}
+
+/// The number of processes the OOM killer has killed in the cgroup of the
+/// current process (and any cgroups below it), as reported by the `oom_kill`
+/// field of its cgroup v2 `memory.events`.
+#[cfg(target_os = "linux")]
+pub(crate) fn oom_kills() -> Option<u64> {
+    let cgroups = ::std::fs::read_to_string("/proc/self/cgroup").ok()?;
+    let path = cgroups.lines().find_map(|line| line.strip_prefix("0::"))?;
+    let events = ::std::fs::read_to_string(format!("/sys/fs/cgroup{}/memory.events", path)).ok()?;
+    events.lines()
+        .find_map(|line| line.strip_prefix("oom_kill "))?
+        .parse()
+        .ok()
+}
+
+/// Calls `f` until it no longer fails with `EINTR`, converting any other
+/// failure (i.e. a return value of -1) into an error.
+///
+/// SIGCHLD is delivered to us every time a child exits, so any system call
+/// which can be interrupted by a signal is liable to be.
+fn retry_on_eintr<F>(mut f: F) -> io::Result<libc::c_int>
+    where F: FnMut() -> libc::c_int
+{
+    loop {
+        let ret = f();
+        if ret != -1 {
+            return Ok(ret);
+        }
+
+        let err = io::Error::last_os_error();
+        if err.kind() != io::ErrorKind::Interrupted {
+            return Err(err);
+        }
+    }
+}
+
+fn set_nonblocking<T: AsRawFd>(io: &T, nonblocking: bool) -> io::Result<()> {
     unsafe {
         let fd = io.as_raw_fd();
         let r = libc::fcntl(fd, libc::F_GETFL);
         if r == -1 {
             return Err(io::Error::last_os_error())
         }
-        let r = libc::fcntl(fd, libc::F_SETFL, r | libc::O_NONBLOCK);
+        let flags = if nonblocking {
+            r | libc::O_NONBLOCK
+        } else {
+            r & !libc::O_NONBLOCK
+        };
+        let r = libc::fcntl(fd, libc::F_SETFL, flags);
         if r == -1 {
             return Err(io::Error::last_os_error())
         }
     }
-    let io = try!(PollEvented::new_with_handle(Fd(io), handle));
-    Ok(Some(io))
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use futures::executor::{self, Notify};
+    use futures::{future, Async};
+    use std::cell::Cell;
+    use std::env;
+    use std::fs::{self, File};
+    use std::io::{self, Write};
+    use std::os::unix::io::FromRawFd;
+    use std::process::{self, Command, Stdio};
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::thread;
+    use std::time::{Duration, Instant};
+    use super::{libc, set_nonblocking, spawn_child, Fd, Orphans, SpawnOptions};
+    use tokio_io::AsyncRead;
+    use tokio_reactor::{Handle, PollEvented, Reactor};
+
+    thread_local! {
+        /// The number of stdio streams which may still be set up successfully
+        /// on this thread before failing, if any.
+        static STDIO_FAULT: Cell<Option<usize>> = const { Cell::new(None) };
+    }
+
+    pub(super) fn inject_stdio_fault() -> io::Result<()> {
+        STDIO_FAULT.with(|fault| match fault.get() {
+            Some(0) => {
+                fault.set(None);
+                Err(io::Error::from_raw_os_error(libc::EBADF))
+            },
+            Some(n) => {
+                fault.set(Some(n - 1));
+                Ok(())
+            },
+            None => Ok(()),
+        })
+    }
+
+    #[test]
+    fn failed_stdio_setup_does_not_leak_child() {
+        let pid_file = env::temp_dir().join(format!("tokio-process-stdio-fault-{}", process::id()));
+        drop(fs::remove_file(&pid_file));
+
+        let orphans = Orphans::new();
+        let mut cmd = Command::new("sh");
+        cmd.arg("-c")
+            .arg(format!("echo $$ > {}.tmp && mv {0}.tmp {0} && exec sleep 10", pid_file.display()))
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped());
+
+        // Setting up stdout succeeds, but stderr fails
+        STDIO_FAULT.with(|fault| fault.set(Some(1)));
+        let err = match spawn_child(&mut cmd, &Handle::default(), orphans.clone(), &SpawnOptions::default()) {
+            Ok(_) => panic!("spawned despite the fault"),
+            Err(e) => e,
+        };
+        assert_eq!(err.raw_os_error(), Some(libc::EBADF));
+
+        // The child was killed rather than left running, and is reaped
+        // instead of lingering as a zombie
+        assert!(orphans.reap_orphans_now(Duration::from_secs(3)).is_empty());
+        assert_eq!(orphans.len(), 0);
+
+        // Had the child been leaked it would still be around to record its pid
+        thread::sleep(Duration::from_millis(100));
+        if let Ok(pid) = fs::read_to_string(&pid_file) {
+            drop(fs::remove_file(&pid_file));
+            let pid = pid.trim().parse::<libc::pid_t>().unwrap();
+            assert_eq!(unsafe { libc::kill(pid, 0) }, -1, "child {} was leaked", pid);
+        }
+    }
+
+    #[derive(Default)]
+    struct Flag(AtomicBool);
+
+    impl Notify for Flag {
+        fn notify(&self, _id: usize) {
+            self.0.store(true, Ordering::SeqCst);
+        }
+    }
+
+    #[test]
+    fn fd_without_hup_is_readable() {
+        let mut fds = [0; 2];
+        assert_eq!(unsafe { libc::pipe(fds.as_mut_ptr()) }, 0);
+        let reader = unsafe { File::from_raw_fd(fds[0]) };
+        let mut writer = unsafe { File::from_raw_fd(fds[1]) };
+        set_nonblocking(&reader, true).unwrap();
+
+        let mut reactor = Reactor::new().unwrap();
+        let mut reader = PollEvented::new_with_handle(Fd::with_hup(reader, false), &reactor.handle())
+            .unwrap();
+
+        let flag = Arc::new(Flag::default());
+        let mut buf = [0; 8];
+        let mut read = executor::spawn(future::poll_fn(|| reader.poll_read(&mut buf)));
+        assert_eq!(read.poll_future_notify(&flag, 0).unwrap(), Async::NotReady);
+
+        writer.write_all(b"x").unwrap();
+        drop(writer);
+
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while !flag.0.load(Ordering::SeqCst) {
+            assert!(Instant::now() < deadline, "reader was not woken");
+            reactor.turn(Some(Duration::from_millis(10))).unwrap();
+        }
+
+        assert_eq!(read.poll_future_notify(&flag, 0).unwrap(), Async::Ready(1));
+        assert_eq!(read.poll_future_notify(&flag, 0).unwrap(), Async::Ready(0));
+    }
 }
@@ -1,11 +1,19 @@
 extern crate crossbeam_queue;
 
 use self::crossbeam_queue::SegQueue;
+use std::cmp;
 use std::io;
 use std::process::ExitStatus;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, MutexGuard, TryLockError};
+use std::thread;
+use std::time::{Duration, Instant};
 
 /// An interface for waiting on a process to exit.
-pub(crate) trait Wait {
+///
+/// Implementations of `try_wait` must never block, as they are called while
+/// polling futures (and even from within `Drop` implementations).
+pub trait Wait {
     /// Get the identifier for this process or diagnostics.
     fn id(&self) -> u32;
     /// Try waiting for a process to exit in a non-blocking manner.
@@ -23,12 +31,28 @@ impl<'a, T: 'a + Wait> Wait for &'a mut T {
 }
 
 /// An interface for queueing up an orphaned process so that it can be reaped.
-pub(crate) trait OrphanQueue<T> {
+pub trait OrphanQueue<T> {
     /// Add an orphan to the queue.
     fn push_orphan(&self, orphan: T);
     /// Attempt to reap every process in the queue, ignoring any errors and
     /// enqueueing any orphans which have not yet exited.
     fn reap_orphans(&self);
+    /// Attempt to reap every process in the queue like `reap_orphans`, but
+    /// return the id of each orphan for which `try_wait` failed along with
+    /// its error.
+    ///
+    /// Orphans which fail to be waited on are removed from the queue (i.e.
+    /// they will never be reaped by us), so each one is reported only once.
+    fn reap_orphans_reporting(&self) -> Vec<(u32, io::Error)>;
+    /// Attempt to reap only the orphan with the id `pid`, leaving any other
+    /// orphans in the queue alone.
+    ///
+    /// Returns the exit status of the orphan if it has exited, in which case
+    /// it is removed from the queue, or `None` if it is still running. An
+    /// orphan which fails to be waited on is removed from the queue as well.
+    /// Fails with an error of kind `NotFound` if no orphan with the id is
+    /// queued, e.g. since it has already been reaped.
+    fn reap_one(&self, pid: u32) -> io::Result<Option<ExitStatus>>;
 }
 
 impl<'a, T, O: 'a + OrphanQueue<T>> OrphanQueue<T> for &'a O {
@@ -39,20 +63,107 @@ impl<'a, T, O: 'a + OrphanQueue<T>> OrphanQueue<T> for &'a O {
     fn reap_orphans(&self) {
         (**self).reap_orphans()
     }
+
+    fn reap_orphans_reporting(&self) -> Vec<(u32, io::Error)> {
+        (**self).reap_orphans_reporting()
+    }
+
+    fn reap_one(&self, pid: u32) -> io::Result<Option<ExitStatus>> {
+        (**self).reap_one(pid)
+    }
 }
 
 /// An atomic implementation of `OrphanQueue`.
+///
+/// The queue itself is lock-free, so pushing an orphan never blocks. Only a
+/// single thread scans the queue for orphans to reap at any given time: any
+/// other thread which tries to reap the queue meanwhile returns immediately
+/// rather than contending for the same orphans, leaving the scanning thread
+/// to scan the queue once more (as orphans it already checked on may have
+/// exited since).
 #[derive(Debug)]
-pub(crate) struct AtomicOrphanQueue<T> {
+pub struct AtomicOrphanQueue<T> {
     queue: SegQueue<T>,
+    /// Held while the queue is being scanned.
+    reaping: Mutex<()>,
+    /// Set whenever the queue is to be scanned, so that the scanning thread
+    /// scans it again should that be requested while it was busy.
+    rescan: AtomicBool,
 }
 
 impl<T> AtomicOrphanQueue<T> {
-    pub(crate) fn new() -> Self {
+    /// Creates a new, empty queue.
+    pub fn new() -> Self {
         Self {
             queue: SegQueue::new(),
+            reaping: Mutex::new(()),
+            rescan: AtomicBool::new(false),
         }
     }
+
+    /// Returns the number of orphans in the queue.
+    ///
+    /// This doesn't lock the queue, so it is only a snapshot which may be
+    /// outdated by the time it is returned. In particular, orphans which are
+    /// in the middle of being reaped are not counted.
+    pub fn len(&self) -> usize {
+        self.queue.len()
+    }
+
+    /// Returns whether the queue holds no orphans, subject to the same
+    /// caveats as `len`.
+    pub fn is_empty(&self) -> bool {
+        self.queue.is_empty()
+    }
+}
+
+impl<T: Wait> AtomicOrphanQueue<T> {
+    /// Repeatedly attempts to reap every process in the queue, blocking the
+    /// current thread until the queue is empty or `timeout` has elapsed.
+    ///
+    /// Returns the ids of all orphans which could not be reaped, either
+    /// because they failed to be waited on, or because they were still
+    /// running once `timeout` elapsed. The latter remain in the queue.
+    pub fn reap_orphans_now(&self, timeout: Duration) -> Vec<u32> {
+        let deadline = Instant::now() + timeout;
+        let mut failed = Vec::new();
+
+        loop {
+            for (id, e) in self.reap_orphans_reporting() {
+                error!("leaking orphaned process {} due to try_wait() error: {}", id, e);
+                failed.push(id);
+            }
+
+            if self.is_empty() {
+                return failed;
+            }
+
+            let now = Instant::now();
+            if now >= deadline {
+                break;
+            }
+
+            thread::sleep(cmp::min(deadline - now, Duration::from_millis(10)));
+        }
+
+        let mut orphans = Vec::with_capacity(self.queue.len());
+        while let Ok(orphan) = self.queue.pop() {
+            failed.push(orphan.id());
+            orphans.push(orphan);
+        }
+
+        for orphan in orphans {
+            self.queue.push(orphan);
+        }
+
+        failed
+    }
+}
+
+impl<T> Default for AtomicOrphanQueue<T> {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl<T: Wait> OrphanQueue<T> for AtomicOrphanQueue<T> {
@@ -61,21 +172,88 @@ impl<T: Wait> OrphanQueue<T> for AtomicOrphanQueue<T> {
     }
 
     fn reap_orphans(&self) {
-        let len = self.queue.len();
+        for (id, e) in self.reap_orphans_reporting() {
+            error!("leaking orphaned process {} due to try_wait() error: {}", id, e);
+        }
+    }
+
+    fn reap_orphans_reporting(&self) -> Vec<(u32, io::Error)> {
+        let mut errors = Vec::new();
+
+        // Nothing is queued, nor taken out of the queue by a scan in progress
+        if self.queue.is_empty() {
+            if let Ok(_guard) = self.reaping.try_lock() {
+                if self.queue.is_empty() {
+                    return errors;
+                }
+            }
+        }
 
-        if len == 0 {
-            return;
+        self.rescan.store(true, Ordering::SeqCst);
+
+        // A request to scan the queue may be made right after the scanning
+        // thread last checked for one, but before it stopped scanning, so it
+        // checks once more afterwards.
+        while self.rescan.load(Ordering::SeqCst) {
+            let _guard = match self.reaping.try_lock() {
+                Ok(guard) => guard,
+                Err(TryLockError::Poisoned(e)) => e.into_inner(),
+                // Whoever is scanning the queue will scan it once more
+                Err(TryLockError::WouldBlock) => return errors,
+            };
+
+            while self.rescan.swap(false, Ordering::SeqCst) {
+                self.scan(&mut errors);
+            }
         }
 
-        let mut orphans = Vec::with_capacity(len);
+        errors
+    }
+
+    fn reap_one(&self, pid: u32) -> io::Result<Option<ExitStatus>> {
+        // Unlike a scan of every orphan this can't be skipped, so wait for
+        // any scan in progress (which never blocks) to put its orphans back.
+        let _guard = self.lock();
+
+        let mut orphans = Vec::with_capacity(self.queue.len());
+        let mut ret = None;
+        while let Ok(mut orphan) = self.queue.pop() {
+            if ret.is_some() || orphan.id() != pid {
+                orphans.push(orphan);
+                continue;
+            }
+
+            let status = orphan.try_wait();
+            if let Ok(None) = status {
+                orphans.push(orphan);
+            }
+            ret = Some(status);
+        }
+
+        for orphan in orphans {
+            self.queue.push(orphan);
+        }
+
+        ret.unwrap_or_else(|| {
+            Err(io::Error::new(io::ErrorKind::NotFound, format!("no orphaned process {} is queued", pid)))
+        })
+    }
+}
+
+impl<T: Wait> AtomicOrphanQueue<T> {
+    fn lock(&self) -> MutexGuard<'_, ()> {
+        // Nothing is guarded by the lock, so there is nothing to be poisoned
+        self.reaping.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Attempts to reap every process in the queue once, which must only be
+    /// done while holding `reaping`.
+    fn scan(&self, errors: &mut Vec<(u32, io::Error)>) {
+        let mut orphans = Vec::with_capacity(self.queue.len());
         while let Ok(mut orphan) = self.queue.pop() {
             match orphan.try_wait() {
                 Ok(Some(_)) => {},
-                Err(e) => error!(
-                    "leaking orphaned process {} due to try_wait() error: {}",
-                    orphan.id(),
-                    e,
-                ),
+                Err(e) => errors.push((orphan.id(), e)),
 
                 // Still not done yet, we need to put it back in the queue
                 // when were done draining it, so that we don't get stuck
@@ -96,7 +274,10 @@ mod test {
     use std::io;
     use std::os::unix::process::ExitStatusExt;
     use std::process::ExitStatus;
-    use std::rc::Rc;
+    use std::rc::{Rc, Weak};
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::Duration;
     use super::{AtomicOrphanQueue, OrphanQueue};
     use super::Wait;
 
@@ -136,7 +317,7 @@ mod test {
                 if self.return_err {
                     Ok(Some(ExitStatus::from_raw(0)))
                 } else {
-                    Err(io::Error::new(io::ErrorKind::Other, "mock err"))
+                    Err(io::Error::other("mock err"))
                 }
             } else {
                 Ok(None)
@@ -160,29 +341,30 @@ mod test {
         let fourth_waits = fourth_orphan.total_waits.clone();
 
         let orphanage = AtomicOrphanQueue::new();
+        assert!(orphanage.is_empty());
         orphanage.push_orphan(first_orphan);
         orphanage.push_orphan(third_orphan);
         orphanage.push_orphan(second_orphan);
         orphanage.push_orphan(fourth_orphan);
 
-        assert_eq!(orphanage.queue.len(), 4);
+        assert_eq!(orphanage.len(), 4);
 
         orphanage.reap_orphans();
-        assert_eq!(orphanage.queue.len(), 2);
+        assert_eq!(orphanage.len(), 2);
         assert_eq!(first_waits.get(), 1);
         assert_eq!(second_waits.get(), 1);
         assert_eq!(third_waits.get(), 1);
         assert_eq!(fourth_waits.get(), 1);
 
         orphanage.reap_orphans();
-        assert_eq!(orphanage.queue.len(), 1);
+        assert_eq!(orphanage.len(), 1);
         assert_eq!(first_waits.get(), 1);
         assert_eq!(second_waits.get(), 2);
         assert_eq!(third_waits.get(), 2);
         assert_eq!(fourth_waits.get(), 1);
 
         orphanage.reap_orphans();
-        assert_eq!(orphanage.queue.len(), 0);
+        assert_eq!(orphanage.len(), 0);
         assert_eq!(first_waits.get(), 1);
         assert_eq!(second_waits.get(), 2);
         assert_eq!(third_waits.get(), 3);
@@ -190,4 +372,168 @@ mod test {
 
         orphanage.reap_orphans(); // Safe to reap when empty
     }
+
+    #[test]
+    fn reap_orphans_reporting_returns_errors() {
+        let orphanage = AtomicOrphanQueue::new();
+        assert!(orphanage.reap_orphans_reporting().is_empty());
+
+        // Fails on its first wait
+        orphanage.push_orphan(MockWait::new(0));
+        // Still running on its first two waits
+        orphanage.push_orphan(MockWait::new(2));
+
+        let errors = orphanage.reap_orphans_reporting();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].0, 42);
+        assert_eq!(errors[0].1.to_string(), "mock err");
+        assert_eq!(orphanage.len(), 1);
+
+        // The failed orphan is only ever reported once
+        assert!(orphanage.reap_orphans_reporting().is_empty());
+        assert_eq!(orphanage.len(), 1);
+    }
+
+    /// An orphan which exits after a number of waits, which unlike `MockWait`
+    /// can be sent to other threads.
+    struct CountdownWait(usize);
+
+    impl Wait for CountdownWait {
+        fn id(&self) -> u32 {
+            42
+        }
+
+        fn try_wait(&mut self) -> io::Result<Option<ExitStatus>> {
+            if self.0 == 0 {
+                return Ok(Some(ExitStatus::from_raw(0)));
+            }
+
+            self.0 -= 1;
+            Ok(None)
+        }
+    }
+
+    #[test]
+    fn concurrent_pushes_and_reaps() {
+        let orphanage = Arc::new(AtomicOrphanQueue::new());
+
+        let threads = (0..8).map(|_| {
+            let orphanage = orphanage.clone();
+            thread::spawn(move || {
+                for i in 0..10_000 {
+                    orphanage.push_orphan(CountdownWait(i % 3));
+                    orphanage.reap_orphans();
+                }
+            })
+        }).collect::<Vec<_>>();
+
+        for thread in threads {
+            thread.join().unwrap();
+        }
+
+        // No orphan is ever lost, so all of them are reaped eventually
+        assert!(orphanage.reap_orphans_now(Duration::from_secs(5)).is_empty());
+        assert!(orphanage.is_empty());
+    }
+
+    /// An orphan which exits right after it was first checked on, asking the
+    /// queue it was taken out of to be reaped like `SIGCHLD` would.
+    struct ExitsWhileScanned {
+        orphanage: Weak<AtomicOrphanQueue<ExitsWhileScanned>>,
+        exited: bool,
+    }
+
+    impl Wait for ExitsWhileScanned {
+        fn id(&self) -> u32 {
+            42
+        }
+
+        fn try_wait(&mut self) -> io::Result<Option<ExitStatus>> {
+            if self.exited {
+                return Ok(Some(ExitStatus::from_raw(0)));
+            }
+
+            self.exited = true;
+            self.orphanage.upgrade().unwrap().reap_orphans();
+            Ok(None)
+        }
+    }
+
+    #[test]
+    fn reap_requested_during_scan_is_not_lost() {
+        let orphanage = Rc::new(AtomicOrphanQueue::new());
+        orphanage.push_orphan(ExitsWhileScanned {
+            orphanage: Rc::downgrade(&orphanage),
+            exited: false,
+        });
+
+        // The request made while the queue was being scanned makes the
+        // scanning thread check on the orphan once more
+        orphanage.reap_orphans();
+        assert!(orphanage.is_empty());
+    }
+
+    /// An orphan with a distinct id which exits after a number of waits.
+    struct IdWait(u32, usize);
+
+    impl Wait for IdWait {
+        fn id(&self) -> u32 {
+            self.0
+        }
+
+        fn try_wait(&mut self) -> io::Result<Option<ExitStatus>> {
+            if self.1 == 0 {
+                return Ok(Some(ExitStatus::from_raw(self.0 as i32 * 256)));
+            }
+
+            self.1 -= 1;
+            Ok(None)
+        }
+    }
+
+    #[test]
+    fn reap_one_only_reaps_the_matching_orphan() {
+        let orphanage = AtomicOrphanQueue::new();
+        orphanage.push_orphan(IdWait(1, 0));
+        orphanage.push_orphan(IdWait(2, 1));
+        orphanage.push_orphan(IdWait(3, 0));
+
+        // Still running, so it stays queued
+        assert_eq!(orphanage.reap_one(2).unwrap(), None);
+        assert_eq!(orphanage.len(), 3);
+
+        assert_eq!(orphanage.reap_one(2).unwrap(), Some(ExitStatus::from_raw(2 * 256)));
+        assert_eq!(orphanage.len(), 2);
+
+        let err = orphanage.reap_one(2).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+        assert_eq!(orphanage.len(), 2);
+
+        // The other orphans were never waited on
+        orphanage.reap_orphans();
+        assert!(orphanage.is_empty());
+    }
+
+    #[test]
+    fn reap_one_removes_failed_orphans() {
+        let orphanage = AtomicOrphanQueue::new();
+        orphanage.push_orphan(MockWait::new(0));
+
+        assert_eq!(orphanage.reap_one(42).unwrap_err().to_string(), "mock err");
+        assert!(orphanage.is_empty());
+    }
+
+    #[test]
+    fn reap_orphans_now_waits_for_orphans() {
+        let orphanage = AtomicOrphanQueue::new();
+        assert!(orphanage.reap_orphans_now(Duration::from_secs(1)).is_empty());
+
+        orphanage.push_orphan(MockWait::with_err()); // Exits straight away
+        orphanage.push_orphan(MockWait::new(3)); // Fails on its fourth wait
+        orphanage.push_orphan(MockWait::new(usize::MAX)); // Never exits
+
+        let failed = orphanage.reap_orphans_now(Duration::from_millis(100));
+        assert_eq!(failed, vec![42, 42]);
+        assert_eq!(orphanage.len(), 1);
+    }
 }
@@ -0,0 +1,92 @@
+//! Exit notifications via `pidfd_open(2)` on Linux.
+//!
+//! Since Linux 5.3 it is possible to obtain a file descriptor which refers to
+//! a specific process, and which becomes readable once that process exits.
+//! Registering such a descriptor with the reactor lets us wait on a single
+//! child without having to check on *every* spawned child whenever a SIGCHLD
+//! is received.
+//!
+//! Support is detected at runtime: if the kernel does not implement the
+//! syscall we remember that fact and fall back to the SIGCHLD based approach
+//! for all subsequent children.
+
+use std::io;
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::sync::atomic::{AtomicBool, Ordering};
+use super::libc;
+
+static UNSUPPORTED: AtomicBool = AtomicBool::new(false);
+
+/// An owned pidfd which is closed on drop.
+#[derive(Debug)]
+pub(crate) struct PidFd(RawFd);
+
+impl PidFd {
+    /// Attempts to open a pidfd referring to the process `pid`, returning
+    /// `None` if pidfds are not supported by the running kernel.
+    ///
+    /// The caller must ensure that `pid` refers to an unreaped child,
+    /// otherwise the pid may have already been recycled.
+    pub(crate) fn open(pid: u32) -> Option<Self> {
+        if UNSUPPORTED.load(Ordering::Relaxed) {
+            return None;
+        }
+
+        let fd = unsafe {
+            libc::syscall(libc::SYS_pidfd_open, pid as libc::pid_t, 0 as libc::c_uint)
+        };
+
+        if fd == -1 {
+            let err = io::Error::last_os_error();
+            if err.raw_os_error() == Some(libc::ENOSYS) {
+                UNSUPPORTED.store(true, Ordering::Relaxed);
+            } else {
+                debug!("failed to open pidfd for process {}: {}", pid, err);
+            }
+
+            return None;
+        }
+
+        Some(PidFd(fd as RawFd))
+    }
+}
+
+impl AsRawFd for PidFd {
+    fn as_raw_fd(&self) -> RawFd {
+        self.0
+    }
+}
+
+impl Drop for PidFd {
+    fn drop(&mut self) {
+        unsafe {
+            libc::close(self.0);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::os::unix::io::AsRawFd;
+    use std::process::Command;
+    use super::PidFd;
+    use super::libc;
+
+    #[test]
+    fn readable_once_child_exits() {
+        let mut child = Command::new("true").spawn().expect("failed to spawn");
+
+        // Nothing to check if not supported by the running kernel
+        if let Some(pidfd) = PidFd::open(child.id()) {
+            let mut pollfd = libc::pollfd {
+                fd: pidfd.as_raw_fd(),
+                events: libc::POLLIN,
+                revents: 0,
+            };
+
+            assert_eq!(1, unsafe { libc::poll(&mut pollfd, 1, 5000) });
+        }
+
+        assert!(child.wait().expect("failed to wait").success());
+    }
+}
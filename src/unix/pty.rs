@@ -0,0 +1,153 @@
+//! Spawning children attached to a pseudo-terminal.
+//!
+//! A pty is allocated with `openpty(3)`, and the slave end becomes the
+//! child's stdin, stdout and stderr as well as its controlling terminal. The
+//! master end stays in the parent, where it's registered with the reactor
+//! like any other stdio handle.
+
+use std::fs::File;
+use std::io;
+use std::os::unix::io::{AsRawFd, FromRawFd, RawFd};
+use std::os::unix::process::CommandExt;
+use std::process::{Command, Stdio};
+use std::ptr;
+use super::{libc, set_nonblocking, Fd};
+use tokio_reactor::{Handle, PollEvented};
+
+pub type PtyMaster = PollEvented<Fd<Master>>;
+
+/// The master end of a pty.
+#[derive(Debug)]
+pub struct Master(File);
+
+impl io::Read for Master {
+    fn read(&mut self, bytes: &mut [u8]) -> io::Result<usize> {
+        match self.0.read(bytes) {
+            // Linux reports `EIO` rather than EOF once every slave has been
+            // closed, i.e. once the child (and anything it spawned) exited.
+            Err(ref e) if e.raw_os_error() == Some(libc::EIO) => Ok(0),
+            ret => ret,
+        }
+    }
+}
+
+impl io::Write for Master {
+    fn write(&mut self, bytes: &[u8]) -> io::Result<usize> {
+        self.0.write(bytes)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.flush()
+    }
+}
+
+impl AsRawFd for Master {
+    fn as_raw_fd(&self) -> RawFd {
+        self.0.as_raw_fd()
+    }
+}
+
+fn winsize((rows, cols): (u16, u16)) -> libc::winsize {
+    libc::winsize {
+        ws_row: rows,
+        ws_col: cols,
+        ws_xpixel: 0,
+        ws_ypixel: 0,
+    }
+}
+
+fn set_cloexec(fd: RawFd) -> io::Result<()> {
+    if unsafe { libc::fcntl(fd, libc::F_SETFD, libc::FD_CLOEXEC) } == -1 {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(())
+}
+
+/// Allocates a new pty of the given size, returning its master and slave
+/// ends.
+pub(crate) fn open(size: (u16, u16)) -> io::Result<(Master, File)> {
+    let mut master = -1;
+    let mut slave = -1;
+    let size = winsize(size);
+
+    // The BSDs declare the size as mutable, although it is only ever read
+    let ret = unsafe {
+        libc::openpty(&mut master,
+                      &mut slave,
+                      ptr::null_mut(),
+                      ptr::null_mut(),
+                      &size as *const _ as *mut _)
+    };
+    if ret == -1 {
+        return Err(io::Error::last_os_error());
+    }
+
+    let master = unsafe { File::from_raw_fd(master) };
+    let slave = unsafe { File::from_raw_fd(slave) };
+
+    // Neither end should leak into any child: the slave only reaches the
+    // child through its stdio, which is duplicated onto fds 0-2.
+    set_cloexec(master.as_raw_fd())?;
+    set_cloexec(slave.as_raw_fd())?;
+
+    Ok((Master(master), slave))
+}
+
+/// Configures `cmd` to use `slave` for its stdio and as its controlling
+/// terminal.
+///
+/// The hook acquiring the terminal can't be removed from `cmd` again, so it
+/// stays installed after `detach`.
+pub(crate) fn attach(cmd: &mut Command, slave: &File) -> io::Result<()> {
+    cmd.stdin(Stdio::from(slave.try_clone()?));
+    cmd.stdout(Stdio::from(slave.try_clone()?));
+    cmd.stderr(Stdio::from(slave.try_clone()?));
+
+    unsafe {
+        cmd.pre_exec(|| {
+            // Only a session leader can acquire a controlling terminal.
+            // Creating a session fails if the child already leads one (e.g.
+            // since the same command was spawned on a pty before, leaving an
+            // earlier copy of this hook behind), which is harmless, but not
+            // if it merely leads a process group.
+            if libc::setsid() == -1 && libc::getsid(0) != libc::getpid() {
+                return Err(io::Error::last_os_error());
+            }
+
+            if libc::ioctl(0, libc::TIOCSCTTY as _, 0) == -1 {
+                return Err(io::Error::last_os_error());
+            }
+
+            Ok(())
+        });
+    }
+
+    Ok(())
+}
+
+/// Releases the copies of the slave held by `cmd`, which would otherwise
+/// prevent the master from ever seeing the child hang up.
+pub(crate) fn detach(cmd: &mut Command) {
+    cmd.stdin(Stdio::inherit());
+    cmd.stdout(Stdio::inherit());
+    cmd.stderr(Stdio::inherit());
+}
+
+/// Registers the master end of a pty with the event loop of `handle`.
+pub(crate) fn register(master: Master, handle: &Handle) -> io::Result<PtyMaster> {
+    set_nonblocking(&master, true)?;
+    PollEvented::new_with_handle(Fd::new(master), handle)
+}
+
+/// Changes the window size of the pty behind `master`.
+pub(crate) fn resize(master: &PtyMaster, size: (u16, u16)) -> io::Result<()> {
+    let size = winsize(size);
+    let fd = master.get_ref().as_raw_fd();
+
+    if unsafe { libc::ioctl(fd, libc::TIOCSWINSZ as _, &size) } == -1 {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(())
+}
@@ -1,20 +1,57 @@
 use futures::{Async, Future, Poll, Stream};
 use kill::Kill;
 use std::io;
-use std::ops::Deref;
+use std::ops::{Deref, DerefMut};
 use std::process::ExitStatus;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use super::orphan::{OrphanQueue, Wait};
 
+/// A counter of the exit notifications received so far, which may be shared
+/// between many reapers.
+///
+/// Each reaper remembers the generation at which it last checked on its
+/// child and only checks again once the generation has advanced, so that
+/// being polled without any new notification (e.g. when joined with the
+/// child's stdio) does not cost a `waitpid` call every time.
+#[derive(Debug, Clone, Default)]
+pub struct Generation(Arc<AtomicUsize>);
+
+impl Generation {
+    /// Creates a new counter, to be shared by all reapers receiving the same
+    /// notifications.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn current(&self) -> usize {
+        self.0.load(Ordering::SeqCst)
+    }
+
+    fn bump(&self) {
+        self.0.fetch_add(1, Ordering::SeqCst);
+    }
+}
+
 /// Orchestrates between registering interest for receiving signals when a
 /// child process has exited, and attempting to poll for process completion.
+///
+/// The reaper waits on the process `W` whenever the stream `S` yields a
+/// notification that it may have exited, and resolves to its exit status.
+/// Should the reaper be dropped before then, the process is pushed onto the
+/// orphan queue `Q` so that it can still be reaped later on. The orphan queue
+/// is in turn drained whenever the reaper is polled.
 #[derive(Debug)]
-pub(crate) struct Reaper<W, Q, S>
+pub struct Reaper<W, Q, S>
     where W: Wait,
           Q: OrphanQueue<W>,
 {
     inner: Option<W>,
     orphan_queue: Q,
     signal: S,
+    generation: Generation,
+    last_checked: Option<usize>,
+    status: Option<ExitStatus>,
 }
 
 impl<W, Q, S> Deref for Reaper<W, Q, S>
@@ -28,18 +65,78 @@ impl<W, Q, S> Deref for Reaper<W, Q, S>
     }
 }
 
+impl<W, Q, S> DerefMut for Reaper<W, Q, S>
+    where W: Wait,
+          Q: OrphanQueue<W>,
+{
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.inner_mut()
+    }
+}
+
 impl<W, Q, S> Reaper<W, Q, S>
     where W: Wait,
           Q: OrphanQueue<W>,
 {
-    pub(crate) fn new(inner: W, orphan_queue: Q, signal: S) -> Self {
+    /// Creates a new reaper, where `generation` is shared with any other
+    /// reapers which receive the same notifications from their `signal`.
+    pub fn new(inner: W, orphan_queue: Q, signal: S, generation: Generation) -> Self {
         Self {
             inner: Some(inner),
             orphan_queue,
             signal,
+            generation,
+            last_checked: None,
+            status: None,
         }
     }
 
+    /// Attempts to reap the child without registering interest in any future
+    /// signals, reaping any orphans as well if the child has exited.
+    pub fn try_reap(&mut self) -> io::Result<Option<ExitStatus>> {
+        if self.status.is_some() {
+            return Ok(self.status);
+        }
+
+        let ret = self.inner_mut().try_wait()?;
+
+        if ret.is_some() {
+            self.status = ret;
+            self.orphan_queue.reap_orphans();
+        }
+
+        Ok(ret)
+    }
+
+    /// Returns whether the reaper has observed the child exiting, i.e. either
+    /// resolved as a future or returned a status from `try_reap`.
+    ///
+    /// Once a child has been reaped its id may be reused by the OS at any
+    /// moment, so it must no longer be used to refer to the child.
+    pub fn is_reaped(&self) -> bool {
+        self.status.is_some()
+    }
+
+    /// Returns the exit status the child was reaped with, if it has been
+    /// reaped.
+    pub fn status(&self) -> Option<ExitStatus> {
+        self.status
+    }
+
+    /// Records that the child was reaped with `status` by means other than
+    /// `Wait::try_wait`, e.g. via `wait4(2)`, after which it is never waited
+    /// on again.
+    pub(crate) fn set_reaped(&mut self, status: ExitStatus) {
+        self.status = Some(status);
+        self.orphan_queue.reap_orphans();
+    }
+
+    /// Consumes the reaper, handing back the child without ever queueing it
+    /// up as an orphan.
+    pub fn into_inner(mut self) -> W {
+        self.inner.take().expect("inner has gone away")
+    }
+
     fn inner(&self) -> &W {
         self.inner.as_ref().expect("inner has gone away")
     }
@@ -49,6 +146,36 @@ impl<W, Q, S> Reaper<W, Q, S>
     }
 }
 
+impl<W, Q, S> Reaper<W, Q, S>
+    where W: Wait,
+          Q: OrphanQueue<W>,
+{
+    /// Checks on the child one last time once no further notifications can
+    /// arrive, failing with `err` if it is still running, as there would be
+    /// nothing left to wake us up once it exits.
+    fn poll_final(&mut self, err: io::Error) -> Poll<ExitStatus, io::Error> {
+        poll_final_with(self, Self::try_reap, err)
+    }
+}
+
+fn poll_final_with<T, R, F>(reaper: &mut R, mut check: F, err: io::Error) -> Poll<T, io::Error>
+    where F: FnMut(&mut R) -> io::Result<Option<T>>,
+{
+    match check(reaper)? {
+        Some(ret) => Ok(Async::Ready(ret)),
+        None => Err(err),
+    }
+}
+
+fn notifications_ended() -> io::Error {
+    io::Error::other("the stream of child exit notifications has ended, \
+                      so the child's exit can no longer be observed")
+}
+
+fn notifications_failed(err: io::Error) -> io::Error {
+    io::Error::new(err.kind(), format!("failed to receive child exit notifications: {}", err))
+}
+
 impl<W, Q, S> Future for Reaper<W, Q, S>
     where W: Wait,
           Q: OrphanQueue<W>,
@@ -58,6 +185,10 @@ impl<W, Q, S> Future for Reaper<W, Q, S>
     type Error = io::Error;
 
     fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        if let Some(status) = self.status {
+            return Ok(Async::Ready(status));
+        }
+
         loop {
             // If the child hasn't exited yet, then it's our responsibility to
             // ensure the current task gets notified when it might be able to
@@ -78,11 +209,30 @@ impl<W, Q, S> Future for Reaper<W, Q, S>
             // this future's task will be notified/woken up again. Since the
             // futures model allows for spurious wake ups this extra wakeup
             // should not cause significant issues with parent futures.
-            let registered_interest = self.signal.poll()?.is_not_ready();
+            let registered_interest = match self.signal.poll() {
+                Ok(Async::NotReady) => true,
+                Ok(Async::Ready(Some(_))) => false,
+                Ok(Async::Ready(None)) => return self.poll_final(notifications_ended()),
+                Err(e) => return self.poll_final(notifications_failed(e)),
+            };
+            if !registered_interest {
+                self.generation.bump();
+            }
 
-            self.orphan_queue.reap_orphans();
-            if let Some(status) = self.inner_mut().try_wait()? {
-                return Ok(Async::Ready(status));
+            // Nothing can have changed if no notification has arrived since
+            // we last checked (after having registered interest), in which
+            // case this was just a spurious wakeup and the child can be left
+            // alone. The very first poll must always check, however, since
+            // the child may have exited before we started listening.
+            let generation = self.generation.current();
+            if self.last_checked != Some(generation) {
+                self.orphan_queue.reap_orphans();
+                if let Some(status) = self.inner_mut().try_wait()? {
+                    self.status = Some(status);
+                    return Ok(Async::Ready(status));
+                }
+
+                self.last_checked = Some(generation);
             }
 
             // If our attempt to poll for the next signal was not ready, then
@@ -99,6 +249,44 @@ impl<W, Q, S> Future for Reaper<W, Q, S>
     }
 }
 
+impl<W, Q, S> Reaper<W, Q, S>
+    where W: Wait,
+          Q: OrphanQueue<W>,
+          S: Stream<Error = io::Error>,
+{
+    /// Like polling the reaper as a future, but resolves as soon as `check`
+    /// returns a value, which it is given the chance to do whenever the child
+    /// may have changed its state.
+    ///
+    /// Unlike polling the reaper as a future, `check` is called on every
+    /// poll, since it may look for state changes other than the child
+    /// exiting (which no other reaper tracks the generation of).
+    pub(crate) fn poll_with<T, F>(&mut self, mut check: F) -> Poll<T, io::Error>
+        where F: FnMut(&mut Self) -> io::Result<Option<T>>,
+    {
+        loop {
+            // Register before checking, see the `Future` implementation.
+            let registered_interest = match self.signal.poll() {
+                Ok(Async::NotReady) => true,
+                Ok(Async::Ready(Some(_))) => false,
+                Ok(Async::Ready(None)) => return poll_final_with(self, check, notifications_ended()),
+                Err(e) => return poll_final_with(self, check, notifications_failed(e)),
+            };
+            if !registered_interest {
+                self.generation.bump();
+            }
+
+            if let Some(ret) = check(self)? {
+                return Ok(Async::Ready(ret));
+            }
+
+            if registered_interest {
+                return Ok(Async::NotReady);
+            }
+        }
+    }
+}
+
 impl<W, Q, S> Kill for Reaper<W, Q, S>
     where W: Kill + Wait,
           Q: OrphanQueue<W>,
@@ -114,11 +302,24 @@ impl<W, Q, S> Drop for Reaper<W, Q, S>
           Q: OrphanQueue<W>,
 {
     fn drop(&mut self) {
-        if let Ok(Some(_)) = self.inner_mut().try_wait() {
+        let mut orphan = match self.inner.take() {
+            Some(inner) => inner,
+            None => return, // Ownership was handed off via `into_inner`
+        };
+
+        // Never wait on a child which has already been reaped, whose pid may
+        // have been reused already.
+        if self.status.is_some() {
+            return;
+        }
+
+        // Never block here: if the child hasn't exited yet (e.g. it was only
+        // just killed) leave it to be reaped in the background whenever any
+        // other child is next polled.
+        if let Ok(Some(_)) = orphan.try_wait() {
             return;
         }
 
-        let orphan = self.inner.take().unwrap();
         self.orphan_queue.push_orphan(orphan);
     }
 }
@@ -201,6 +402,22 @@ mod test {
         }
     }
 
+    /// A stream which has stopped delivering notifications, either by ending
+    /// or by failing with an error.
+    struct DeadStream(Option<io::Error>);
+
+    impl Stream for DeadStream {
+        type Item = ();
+        type Error = io::Error;
+
+        fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+            match self.0.take() {
+                Some(err) => Err(err),
+                None => Ok(Async::Ready(None)),
+            }
+        }
+    }
+
     struct MockQueue<W> {
         all_enqueued: RefCell<Vec<W>>,
         total_reaps: Cell<usize>,
@@ -224,19 +441,28 @@ mod test {
         fn reap_orphans(&self) {
             self.total_reaps.set(self.total_reaps.get() + 1);
         }
+
+        fn reap_orphans_reporting(&self) -> Vec<(u32, io::Error)> {
+            self.reap_orphans();
+            Vec::new()
+        }
+
+        fn reap_one(&self, _pid: u32) -> io::Result<Option<ExitStatus>> {
+            Ok(None)
+        }
     }
 
     #[test]
     fn reaper() {
         let exit = ExitStatus::from_raw(0);
-        let mock = MockWait::new(exit, 3);
+        let mock = MockWait::new(exit, 2);
         let mut grim = Reaper::new(mock, MockQueue::new(), MockStream::new(vec!(
             None,
             Some(()),
             None,
             None,
-            None,
-        )));
+            Some(()),
+        )), Generation::new());
 
         // Not yet exited, interest registered
         assert_eq!(Async::NotReady, grim.poll().expect("failed to wait"));
@@ -249,25 +475,117 @@ mod test {
         // but managed to register interest the second time around
         assert_eq!(Async::NotReady, grim.poll().expect("failed to wait"));
         assert_eq!(3, grim.signal.total_polls);
-        assert_eq!(3, grim.total_waits);
-        assert_eq!(3, grim.orphan_queue.total_reaps.get());
+        assert_eq!(2, grim.total_waits);
+        assert_eq!(2, grim.orphan_queue.total_reaps.get());
+        assert!(grim.orphan_queue.all_enqueued.borrow().is_empty());
+
+        // Spurious wakeup, no signal received so nothing to check
+        assert_eq!(Async::NotReady, grim.poll().expect("failed to wait"));
+        assert_eq!(4, grim.signal.total_polls);
+        assert_eq!(2, grim.total_waits);
+        assert_eq!(2, grim.orphan_queue.total_reaps.get());
         assert!(grim.orphan_queue.all_enqueued.borrow().is_empty());
 
         // Exited
         assert_eq!(Async::Ready(exit), grim.poll().expect("failed to wait"));
-        assert_eq!(4, grim.signal.total_polls);
-        assert_eq!(4, grim.total_waits);
-        assert_eq!(4, grim.orphan_queue.total_reaps.get());
+        assert_eq!(5, grim.signal.total_polls);
+        assert_eq!(3, grim.total_waits);
+        assert_eq!(3, grim.orphan_queue.total_reaps.get());
         assert!(grim.orphan_queue.all_enqueued.borrow().is_empty());
     }
 
+    #[test]
+    fn reaper_checks_after_shared_generation_advances() {
+        let exit = ExitStatus::from_raw(0);
+        let generation = Generation::new();
+        let mut grim = Reaper::new(
+            MockWait::new(exit, 1),
+            MockQueue::new(),
+            MockStream::new(vec!(None, None)),
+            generation.clone(),
+        );
+
+        assert_eq!(Async::NotReady, grim.poll().expect("failed to wait"));
+        assert_eq!(1, grim.total_waits);
+
+        // Another reaper observed a signal on our behalf
+        generation.bump();
+        assert_eq!(Async::Ready(exit), grim.poll().expect("failed to wait"));
+        assert_eq!(2, grim.total_waits);
+    }
+
+    #[test]
+    fn reaper_fails_once_notifications_stop() {
+        let exit = ExitStatus::from_raw(0);
+
+        let mut grim = Reaper::new(MockWait::new(exit, 1), MockQueue::new(),
+                                   DeadStream(None), Generation::new());
+        let err = grim.poll().expect_err("waited on a stream which has ended");
+        assert!(err.to_string().contains("has ended"));
+        assert!(!grim.is_reaped());
+
+        let failure = io::Error::new(io::ErrorKind::BrokenPipe, "reactor gone");
+        let mut grim = Reaper::new(MockWait::new(exit, 1), MockQueue::new(),
+                                   DeadStream(Some(failure)), Generation::new());
+        let err = grim.poll().expect_err("waited on a stream which has failed");
+        assert_eq!(err.kind(), io::ErrorKind::BrokenPipe);
+        assert!(err.to_string().contains("reactor gone"));
+
+        // A child which has already exited is still reaped
+        let mut grim = Reaper::new(MockWait::new(exit, 0), MockQueue::new(),
+                                   DeadStream(None), Generation::new());
+        assert_eq!(Async::Ready(exit), grim.poll().expect("failed to wait"));
+        assert_eq!(Some(exit), grim.status());
+        assert_eq!(1, grim.orphan_queue.total_reaps.get());
+    }
+
+    #[test]
+    fn never_waits_once_reaped_elsewhere() {
+        let exit = ExitStatus::from_raw(0);
+        let mut mock = MockWait::new(exit, 0);
+
+        {
+            let queue = MockQueue::new();
+
+            let mut grim = Reaper::new(&mut mock, &queue, MockStream::new(vec!()), Generation::new());
+            grim.set_reaped(exit);
+
+            assert_eq!(Async::Ready(exit), grim.poll().expect("failed to wait"));
+            assert_eq!(Some(exit), grim.try_reap().expect("failed to wait"));
+            assert_eq!(0, grim.signal.total_polls);
+
+            drop(grim);
+            assert!(queue.all_enqueued.borrow().is_empty());
+        }
+
+        assert_eq!(0, mock.total_waits);
+    }
+
+    #[test]
+    fn try_reap() {
+        let exit = ExitStatus::from_raw(0);
+        let mock = MockWait::new(exit, 1);
+        let mut grim = Reaper::new(mock, MockQueue::new(), MockStream::new(vec!()), Generation::new());
+
+        assert_eq!(None, grim.try_reap().expect("failed to wait"));
+        assert_eq!(0, grim.orphan_queue.total_reaps.get());
+
+        assert_eq!(Some(exit), grim.try_reap().expect("failed to wait"));
+        assert_eq!(1, grim.orphan_queue.total_reaps.get());
+
+        // Never touches the signal stream
+        assert_eq!(0, grim.signal.total_polls);
+        assert_eq!(2, grim.total_waits);
+    }
+
     #[test]
     fn kill() {
         let exit = ExitStatus::from_raw(0);
         let mut grim = Reaper::new(
             MockWait::new(exit, 0),
             MockQueue::new(),
-            MockStream::new(vec!(None))
+            MockStream::new(vec!(None)),
+            Generation::new(),
         );
 
         grim.kill().unwrap();
@@ -287,7 +605,8 @@ mod test {
             let grim = Reaper::new(
                 &mut mock,
                 &queue,
-                MockStream::new(vec!())
+                MockStream::new(vec!()),
+                Generation::new(),
             );
 
             drop(grim);
@@ -300,6 +619,29 @@ mod test {
         assert_eq!(0, mock.total_kills);
     }
 
+    #[test]
+    fn into_inner_does_not_enqueue_orphan() {
+        let exit = ExitStatus::from_raw(0);
+        let mut mock = MockWait::new(exit, 2);
+
+        {
+            let queue = MockQueue::<&mut MockWait>::new();
+            let grim = Reaper::new(
+                &mut mock,
+                &queue,
+                MockStream::new(vec!()),
+                Generation::new(),
+            );
+            grim.into_inner();
+
+            assert_eq!(0, queue.total_reaps.get());
+            assert!(queue.all_enqueued.borrow().is_empty());
+        }
+
+        assert_eq!(0, mock.total_waits);
+        assert_eq!(0, mock.total_kills);
+    }
+
     #[test]
     fn drop_enqueues_orphan_if_wait_fails() {
         let exit = ExitStatus::from_raw(0);
@@ -310,7 +652,8 @@ mod test {
             let grim = Reaper::new(
                 &mut mock,
                 &queue,
-                MockStream::new(vec!())
+                MockStream::new(vec!()),
+                Generation::new(),
             );
             drop(grim);
 
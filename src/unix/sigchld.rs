@@ -0,0 +1,229 @@
+//! A single SIGCHLD stream shared between many children.
+//!
+//! The stream may also be configured to listen for a different signal, for
+//! environments in which children exiting are signalled differently.
+//!
+//! Every `Signal` stream costs a file descriptor and a registration with the
+//! reactor, and every SIGCHLD is delivered to all of them alike. Children
+//! which are known to share an event loop can thus share a single stream
+//! instead: whichever child polls the stream registers a notification which
+//! wakes up *every* child subscribed to it, after which each child checks
+//! on itself as usual.
+
+use futures::executor::{self, Notify, Spawn};
+use futures::task::{self, Task};
+use futures::{Async, Poll, Stream};
+use std::collections::HashMap;
+use std::fmt;
+use std::io;
+use std::mem;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use super::{libc, signal_stream, SignalStream};
+use tokio_reactor::Handle;
+
+/// The tasks of all children waiting for the next SIGCHLD, keyed by the id
+/// of their subscription.
+#[derive(Default)]
+struct Waiters(Mutex<HashMap<usize, Task>>);
+
+impl Notify for Waiters {
+    fn notify(&self, _id: usize) {
+        let tasks = mem::take(&mut *self.0.lock().unwrap());
+        for task in tasks.values() {
+            task.notify();
+        }
+    }
+}
+
+struct Inner {
+    handle: Handle,
+    signum: libc::c_int,
+    // Only created once first polled, so that handles which never spawn a
+    // child falling back to SIGCHLD never register for the signal.
+    signal: Mutex<Option<Spawn<SignalStream>>>,
+    waiters: Arc<Waiters>,
+    next_id: AtomicUsize,
+    // The number of signals observed by any subscriber so far
+    observed: AtomicUsize,
+}
+
+/// A SIGCHLD stream registered with a specific event loop, which can be
+/// subscribed to by any number of children.
+#[derive(Clone)]
+pub(crate) struct SharedSigchld(Arc<Inner>);
+
+impl SharedSigchld {
+    pub(crate) fn new(handle: &Handle) -> Self {
+        Self::with_signal(handle, libc::SIGCHLD)
+    }
+
+    pub(crate) fn with_signal(handle: &Handle, signum: libc::c_int) -> Self {
+        SharedSigchld(Arc::new(Inner {
+            handle: handle.clone(),
+            signum,
+            signal: Mutex::new(None),
+            waiters: Arc::new(Waiters::default()),
+            next_id: AtomicUsize::new(0),
+            observed: AtomicUsize::new(0),
+        }))
+    }
+
+    pub(crate) fn subscribe(&self) -> Subscription {
+        Subscription {
+            shared: self.0.clone(),
+            id: self.0.next_id.fetch_add(1, Ordering::Relaxed),
+            seen: self.0.observed.load(Ordering::SeqCst),
+        }
+    }
+}
+
+impl fmt::Debug for SharedSigchld {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.debug_struct("SharedSigchld")
+            .field("signum", &self.0.signum)
+            .field("waiting", &self.0.waiters.0.lock().unwrap().len())
+            .finish()
+    }
+}
+
+/// A stream which yields whenever the shared SIGCHLD stream does, or once
+/// another subscriber has observed a SIGCHLD since this one last yielded.
+pub(crate) struct Subscription {
+    shared: Arc<Inner>,
+    id: usize,
+    // The number of signals observed by any subscriber as of when this one
+    // last yielded
+    seen: usize,
+}
+
+impl Stream for Subscription {
+    type Item = ();
+    type Error = io::Error;
+
+    fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        // Register before polling the signal, so that no SIGCHLD observed by
+        // another subscriber in the meantime can go unnoticed.
+        self.shared.waiters.0.lock().unwrap().insert(self.id, task::current());
+
+        // Another subscriber observed a signal since we last yielded. This
+        // must be reported as such, as whoever polls us only checks on its
+        // child once told of a signal.
+        let observed = self.shared.observed.load(Ordering::SeqCst);
+        if observed != self.seen {
+            self.seen = observed;
+            return Ok(Async::Ready(Some(())));
+        }
+
+        let mut signal = self.shared.signal.lock().unwrap();
+        if signal.is_none() {
+            *signal = Some(executor::spawn(signal_stream(self.shared.signum, &self.shared.handle)?));
+        }
+
+        let ret = signal.as_mut().unwrap().poll_stream_notify(&self.shared.waiters, 0);
+
+        match ret {
+            Ok(Async::Ready(Some(_))) => {
+                drop(signal);
+
+                // Every other subscriber needs to check on its child as well.
+                // The signal is recorded before waking them up, so that any
+                // of them polled in the meantime yields as well.
+                self.seen = self.shared.observed.fetch_add(1, Ordering::SeqCst) + 1;
+                self.shared.waiters.notify(0);
+                Ok(Async::Ready(Some(())))
+            },
+            Ok(Async::Ready(None)) => Ok(Async::Ready(None)),
+            Ok(Async::NotReady) => Ok(Async::NotReady),
+            Err(e) => {
+                // Start afresh with a new registration when next polled
+                *signal = None;
+                Err(e)
+            },
+        }
+    }
+}
+
+impl Drop for Subscription {
+    fn drop(&mut self) {
+        self.shared.waiters.0.lock().unwrap().remove(&self.id);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use futures::Async;
+    use futures::executor::{self, Notify};
+    use std::process::Command;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::time::{Duration, Instant};
+    use super::SharedSigchld;
+    use super::libc;
+    use tokio_reactor::Reactor;
+
+    #[derive(Default)]
+    struct Flag(AtomicBool);
+
+    impl Notify for Flag {
+        fn notify(&self, _id: usize) {
+            self.0.store(true, Ordering::SeqCst);
+        }
+    }
+
+    impl Flag {
+        fn is_set(&self) -> bool {
+            self.0.load(Ordering::SeqCst)
+        }
+    }
+
+    #[test]
+    fn every_subscriber_is_woken() {
+        let mut reactor = Reactor::new().unwrap();
+        let shared = SharedSigchld::new(&reactor.handle());
+
+        let first_flag = Arc::new(Flag::default());
+        let second_flag = Arc::new(Flag::default());
+        let mut first = executor::spawn(shared.subscribe());
+        let mut second = executor::spawn(shared.subscribe());
+
+        assert_eq!(first.poll_stream_notify(&first_flag, 0).unwrap(), Async::NotReady);
+        assert_eq!(second.poll_stream_notify(&second_flag, 0).unwrap(), Async::NotReady);
+
+        let mut child = Command::new("true").spawn().unwrap();
+        child.wait().unwrap();
+
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while !(first_flag.is_set() && second_flag.is_set()) {
+            assert!(Instant::now() < deadline, "subscribers were not woken");
+            reactor.turn(Some(Duration::from_millis(10))).unwrap();
+        }
+
+        // Only one subscriber actually receives the signal, but every other
+        // one yields once as well, so that it checks on its child
+        assert_eq!(first.poll_stream_notify(&first_flag, 0).unwrap(), Async::Ready(Some(())));
+        assert_eq!(second.poll_stream_notify(&second_flag, 0).unwrap(), Async::Ready(Some(())));
+        assert_eq!(first.poll_stream_notify(&first_flag, 0).unwrap(), Async::NotReady);
+        assert_eq!(second.poll_stream_notify(&second_flag, 0).unwrap(), Async::NotReady);
+    }
+
+    #[test]
+    fn listens_for_configured_signal() {
+        let mut reactor = Reactor::new().unwrap();
+        let shared = SharedSigchld::with_signal(&reactor.handle(), libc::SIGUSR2);
+
+        let flag = Arc::new(Flag::default());
+        let mut subscription = executor::spawn(shared.subscribe());
+        assert_eq!(subscription.poll_stream_notify(&flag, 0).unwrap(), Async::NotReady);
+
+        assert_eq!(unsafe { libc::raise(libc::SIGUSR2) }, 0);
+
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while !flag.is_set() {
+            assert!(Instant::now() < deadline, "subscriber was not woken");
+            reactor.turn(Some(Duration::from_millis(10))).unwrap();
+        }
+
+        assert_eq!(subscription.poll_stream_notify(&flag, 0).unwrap(), Async::Ready(Some(())));
+    }
+}
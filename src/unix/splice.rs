@@ -0,0 +1,81 @@
+//! Moving data between pipes with `splice(2)`, without copying it through
+//! userspace.
+
+use futures::{Async, Poll};
+use std::io;
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::ptr;
+use super::{libc, retry_on_eintr, ChildStdin, ChildStdout};
+use super::mio::Ready;
+
+/// The most data to move with a single call, which matches the default
+/// capacity of a pipe.
+const MAX_LEN: usize = 64 * 1024;
+
+/// Returns whether `err` indicates that the descriptors cannot be spliced
+/// between, e.g. because neither of them is a pipe.
+pub(crate) fn is_unsupported(err: &io::Error) -> bool {
+    err.raw_os_error() == Some(libc::EINVAL)
+}
+
+/// Returns whether `from` is readable and `to` is writable, without blocking.
+fn poll_ready(from: RawFd, to: RawFd) -> io::Result<(bool, bool)> {
+    let mut fds = [
+        libc::pollfd { fd: from, events: libc::POLLIN, revents: 0 },
+        libc::pollfd { fd: to, events: libc::POLLOUT, revents: 0 },
+    ];
+
+    retry_on_eintr(|| unsafe { libc::poll(fds.as_mut_ptr(), 2, 0) })?;
+
+    // A hang up (or error) must be reported by the splice itself
+    let readable = fds[0].revents != 0;
+    let writable = fds[1].revents != 0;
+    Ok((readable, writable))
+}
+
+/// Splices everything from `from` into `to` until `from` reaches EOF,
+/// adding the number of bytes moved to `total` as it goes.
+pub(crate) fn poll_splice(from: &mut ChildStdout, to: &mut ChildStdin, total: &mut u64)
+    -> Poll<(), io::Error>
+{
+    loop {
+        try_ready!(from.poll_read_ready(Ready::readable()));
+        try_ready!(to.poll_write_ready());
+
+        let from_fd = from.get_ref().as_raw_fd();
+        let to_fd = to.get_ref().as_raw_fd();
+
+        let ret = unsafe {
+            libc::splice(from_fd,
+                         ptr::null_mut(),
+                         to_fd,
+                         ptr::null_mut(),
+                         MAX_LEN,
+                         libc::SPLICE_F_MOVE | libc::SPLICE_F_NONBLOCK)
+        };
+
+        match ret {
+            0 => return Ok(Async::Ready(())),
+            n if n > 0 => *total += n as u64,
+            _ => {
+                let err = io::Error::last_os_error();
+                match err.kind() {
+                    io::ErrorKind::Interrupted => continue,
+                    io::ErrorKind::WouldBlock => {},
+                    _ => return Err(err),
+                }
+
+                // There is no telling which side would have blocked, so we
+                // only stop waiting for readiness of whichever side actually
+                // isn't ready. If both appear ready, we simply try again.
+                let (readable, writable) = poll_ready(from_fd, to_fd)?;
+                if !readable {
+                    from.clear_read_ready(Ready::readable())?;
+                }
+                if !writable {
+                    to.clear_write_ready()?;
+                }
+            },
+        }
+    }
+}
@@ -0,0 +1,163 @@
+//! Reaping of descendants which were reparented to us as a child subreaper.
+//!
+//! Once the current process is a subreaper, orphaned descendants of our
+//! children become children of ours, which nobody else will ever wait on.
+//! Reaping them is only safe as long as we never reap a child which some
+//! `Child` (or the orphan queue) is still going to wait on, as its exit
+//! status would be lost otherwise.
+//!
+//! Every child spawned (or adopted from the standard library) by this crate
+//! is thus tracked by its id until it has been dropped, and reparented
+//! children are only ever reaped if they are not tracked. Spawning a child
+//! and tracking it are not atomic, so reparented children are never reaped
+//! while any child is in the middle of being spawned, lest it exits in the
+//! meantime and is mistaken for a reparented one.
+
+use std::collections::HashMap;
+use std::io;
+use std::process::{self, ExitStatus};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, RwLockReadGuard, RwLock};
+use kill::Kill;
+use super::libc;
+use super::orphan::Wait;
+
+lazy_static! {
+    // The number of children tracked by each id, as a reaped child's id may
+    // be reused by a new child before the former is dropped.
+    static ref TRACKED: Mutex<HashMap<u32, usize>> = Mutex::new(HashMap::new());
+    static ref SPAWNING: RwLock<()> = RwLock::new(());
+}
+
+static REAP_REPARENTED: AtomicBool = AtomicBool::new(false);
+
+/// A child which is never reaped as a reparented child for as long as it
+/// exists.
+#[derive(Debug)]
+pub(crate) struct TrackedChild {
+    child: process::Child,
+    _registration: Registration,
+}
+
+impl TrackedChild {
+    pub(crate) fn new(child: process::Child) -> Self {
+        let registration = Registration::new(child.id());
+        TrackedChild {
+            child,
+            _registration: registration,
+        }
+    }
+
+    /// Stops tracking the child, handing it back.
+    pub(crate) fn into_inner(self) -> process::Child {
+        self.child
+    }
+}
+
+impl Wait for TrackedChild {
+    fn id(&self) -> u32 {
+        Wait::id(&self.child)
+    }
+
+    fn try_wait(&mut self) -> io::Result<Option<ExitStatus>> {
+        Wait::try_wait(&mut self.child)
+    }
+}
+
+impl Kill for TrackedChild {
+    fn kill(&mut self) -> io::Result<()> {
+        Kill::kill(&mut self.child)
+    }
+}
+
+#[derive(Debug)]
+struct Registration(u32);
+
+impl Registration {
+    fn new(pid: u32) -> Self {
+        *TRACKED.lock().unwrap().entry(pid).or_insert(0) += 1;
+        Registration(pid)
+    }
+}
+
+impl Drop for Registration {
+    fn drop(&mut self) {
+        let mut tracked = TRACKED.lock().unwrap();
+        let remaining = {
+            let count = tracked.get_mut(&self.0).expect("registration went missing");
+            *count -= 1;
+            *count
+        };
+
+        if remaining == 0 {
+            tracked.remove(&self.0);
+        }
+    }
+}
+
+/// Keeps reparented children from being reaped while a child is spawned,
+/// which must be held until the child is tracked.
+pub(crate) fn spawning() -> RwLockReadGuard<'static, ()> {
+    SPAWNING.read().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Starts reaping reparented children whenever orphans are reaped.
+#[cfg(target_os = "linux")]
+pub(crate) fn enable() {
+    REAP_REPARENTED.store(true, Ordering::Release);
+}
+
+/// Reaps every child which has exited without being tracked, if reaping
+/// reparented children was enabled.
+pub(crate) fn reap_reparented() {
+    if !REAP_REPARENTED.load(Ordering::Acquire) {
+        return;
+    }
+
+    // Whoever is spawning a child will reap orphans once done, at which
+    // point we will get another chance.
+    let _spawning = match SPAWNING.try_write() {
+        Ok(guard) => guard,
+        Err(_) => return,
+    };
+
+    for pid in children() {
+        if TRACKED.lock().unwrap().contains_key(&pid) {
+            continue;
+        }
+
+        let mut status = 0;
+        match unsafe { libc::waitpid(pid as libc::pid_t, &mut status, libc::WNOHANG) } {
+            0 => {},
+            -1 => debug!("failed to reap reparented process {}: {}", pid, io::Error::last_os_error()),
+            _ => debug!("reaped reparented process {}", pid),
+        }
+    }
+}
+
+/// Lists the children of every thread of the current process.
+#[cfg(target_os = "linux")]
+fn children() -> Vec<u32> {
+    use std::fs;
+
+    let tasks = match fs::read_dir("/proc/self/task") {
+        Ok(tasks) => tasks,
+        Err(e) => {
+            debug!("failed to list the threads of the current process: {}", e);
+            return Vec::new();
+        },
+    };
+
+    tasks.filter_map(|task| fs::read_to_string(task.ok()?.path().join("children")).ok())
+        .flat_map(|children| {
+            children.split_whitespace()
+                .filter_map(|pid| pid.parse().ok())
+                .collect::<Vec<_>>()
+        })
+        .collect()
+}
+
+#[cfg(not(target_os = "linux"))]
+fn children() -> Vec<u32> {
+    Vec::new()
+}
@@ -0,0 +1,132 @@
+use futures::task::{self, Task};
+use futures::{Async, Future, Poll};
+use std::fmt;
+use std::io;
+use std::process::ExitStatus;
+use std::sync::{Arc, Mutex};
+
+#[derive(Debug, Default)]
+struct Inner {
+    status: Option<ExitStatus>,
+    tasks: Vec<Task>,
+    closed: bool,
+}
+
+impl Inner {
+    fn notify_all(&mut self) {
+        for task in self.tasks.drain(..) {
+            task.notify();
+        }
+    }
+}
+
+/// A callback invoked with the exit status of a child once it is published.
+type OnPublish = Box<dyn Fn(ExitStatus) + Send + Sync>;
+
+/// The sending half of an `ExitHandle`, owned by a `Child` which publishes
+/// the exit status once it has observed it.
+#[derive(Default)]
+pub(crate) struct ExitWatch {
+    inner: Arc<Mutex<Inner>>,
+    on_publish: Option<OnPublish>,
+}
+
+impl ExitWatch {
+    pub(crate) fn handle(&self) -> ExitHandle {
+        ExitHandle(self.inner.clone())
+    }
+
+    /// The exit status, if it has been published.
+    pub(crate) fn status(&self) -> Option<ExitStatus> {
+        self.inner.lock().unwrap().status
+    }
+
+    /// Arranges for `f` to be called once the exit status is published.
+    pub(crate) fn on_publish<F>(&mut self, f: F)
+        where F: Fn(ExitStatus) + Send + Sync + 'static,
+    {
+        self.on_publish = Some(Box::new(f));
+    }
+
+    pub(crate) fn publish(&self, status: ExitStatus) {
+        {
+            let mut inner = self.inner.lock().unwrap();
+            if inner.status.is_some() {
+                return;
+            }
+
+            inner.status = Some(status);
+            inner.notify_all();
+        }
+
+        // Called without holding the lock, so the callback is free to do
+        // anything with the child's handles.
+        if let Some(ref f) = self.on_publish {
+            f(status);
+        }
+    }
+}
+
+impl fmt::Debug for ExitWatch {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.debug_struct("ExitWatch")
+            .field("inner", &self.inner)
+            .field("on_publish", &self.on_publish.is_some())
+            .finish()
+    }
+}
+
+impl Drop for ExitWatch {
+    fn drop(&mut self) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.closed = true;
+        inner.notify_all();
+    }
+}
+
+/// A cloneable future which resolves to the exit status of a child, as
+/// returned by `Child::exit_handle`.
+///
+/// The handle does not drive the child in any way: it resolves once the
+/// `Child` it was created from observes the child exiting, i.e. when the
+/// `Child` is polled to completion or its status is collected via
+/// `try_status`. Should the `Child` be dropped (or converted into a
+/// `std::process::Child`) before then, the handle resolves to an error.
+#[must_use = "futures do nothing unless polled"]
+#[derive(Debug, Clone)]
+pub struct ExitHandle(Arc<Mutex<Inner>>);
+
+impl ExitHandle {
+    /// Returns the exit status of the child if it has already been observed,
+    /// without waiting for it.
+    ///
+    /// Unlike polling the handle, this doesn't require a task, nor does it
+    /// fail once the `Child` has been dropped: a status observed before then
+    /// remains available.
+    pub fn status(&self) -> Option<ExitStatus> {
+        self.0.lock().unwrap().status
+    }
+}
+
+impl Future for ExitHandle {
+    type Item = ExitStatus;
+    type Error = io::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        let mut inner = self.0.lock().unwrap();
+
+        if let Some(status) = inner.status {
+            return Ok(Async::Ready(status));
+        }
+
+        if inner.closed {
+            return Err(io::Error::other("child was dropped before its exit was observed"));
+        }
+
+        if !inner.tasks.iter().any(Task::will_notify_current) {
+            inner.tasks.push(task::current());
+        }
+
+        Ok(Async::NotReady)
+    }
+}
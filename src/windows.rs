@@ -20,31 +20,36 @@ extern crate mio_named_pipes;
 
 use std::fmt;
 use std::io;
+use std::mem;
 use std::os::windows::prelude::*;
 use std::os::windows::process::ExitStatusExt;
 use std::process::{self, ExitStatus};
 use std::ptr;
+use std::time::Duration;
 
 use futures::future::Fuse;
 use futures::sync::oneshot;
+use events::ChildEvent;
 use futures::{Future, Poll, Async};
 use kill::Kill;
 use self::mio_named_pipes::NamedPipe;
 use self::winapi::shared::minwindef::*;
 use self::winapi::shared::winerror::*;
 use self::winapi::um::handleapi::*;
+use self::winapi::um::jobapi2::*;
 use self::winapi::um::processthreadsapi::*;
 use self::winapi::um::synchapi::*;
 use self::winapi::um::threadpoollegacyapiset::*;
 use self::winapi::um::winbase::*;
 use self::winapi::um::winnt::*;
-use super::SpawnedChild;
+use super::{SpawnOptions, SpawnedChild};
 use tokio_reactor::{Handle, PollEvented};
 
 #[must_use = "futures do nothing unless polled"]
 pub struct Child {
     child: process::Child,
     waiting: Option<Waiting>,
+    job: Option<Job>,
 }
 
 impl fmt::Debug for Child {
@@ -66,16 +71,173 @@ struct Waiting {
 unsafe impl Sync for Waiting {}
 unsafe impl Send for Waiting {}
 
-pub(crate) fn spawn_child(cmd: &mut process::Command, handle: &Handle) -> io::Result<SpawnedChild> {
-    let mut child = cmd.spawn()?;
-    let stdin = stdio(child.stdin.take(), handle)?;
-    let stdout = stdio(child.stdout.take(), handle)?;
-    let stderr = stdio(child.stderr.take(), handle)?;
+/// A job object used to kill a child along with all of its descendants.
+///
+/// The job is deliberately not configured with
+/// `JOB_OBJECT_LIMIT_KILL_ON_JOB_CLOSE`, as closing it would then kill the
+/// child even when it is dropped without being killed (e.g. after
+/// `Child::forget`). Instead the job is explicitly terminated when the child
+/// is killed.
+struct Job(HANDLE);
+
+unsafe impl Sync for Job {}
+unsafe impl Send for Job {}
+
+impl Job {
+    fn assign(child: &process::Child) -> io::Result<Job> {
+        let job = unsafe { CreateJobObjectW(ptr::null_mut(), ptr::null()) };
+        if job.is_null() {
+            return Err(io::Error::last_os_error());
+        }
+
+        // Closes the job if assignment fails below
+        let job = Job(job);
+
+        if unsafe { AssignProcessToJobObject(job.0, child.as_raw_handle()) } == 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(job)
+    }
+
+    fn terminate(&self) -> io::Result<()> {
+        if unsafe { TerminateJobObject(self.0, 1) } == 0 {
+            Err(io::Error::last_os_error())
+        } else {
+            Ok(())
+        }
+    }
+}
+
+impl Drop for Job {
+    fn drop(&mut self) {
+        unsafe {
+            CloseHandle(self.0);
+        }
+    }
+}
+
+/// Windows has no notion of zombie processes, so there is nothing to track.
+#[derive(Clone, Debug)]
+pub(crate) struct Orphans;
+
+impl Orphans {
+    pub(crate) fn new() -> Self {
+        Orphans
+    }
+
+    pub(crate) fn global() -> Self {
+        Orphans
+    }
+
+    pub(crate) fn reap_orphans_now(&self, _timeout: Duration) -> Vec<u32> {
+        Vec::new()
+    }
+
+    pub(crate) fn reap_orphan(&self, pid: u32) -> io::Result<Option<ExitStatus>> {
+        Err(io::Error::new(io::ErrorKind::NotFound, format!("no orphaned process {} is queued", pid)))
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        0
+    }
+}
+
+/// Windows has no orphans to reap, so this completes straight away.
+#[derive(Debug)]
+pub(crate) struct OrphanReaper;
+
+impl OrphanReaper {
+    pub(crate) fn new(_orphans: Orphans, _handle: &Handle, _options: &SpawnOptions)
+        -> io::Result<Self>
+    {
+        Ok(OrphanReaper)
+    }
+}
+
+impl Future for OrphanReaper {
+    type Item = ();
+    type Error = io::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        Ok(Async::Ready(()))
+    }
+}
+
+/// The only registrations with the reactor are those of the child's stdio,
+/// whose errors are reported as they are.
+pub(crate) fn check_reactor(_handle: &Handle) -> io::Result<()> {
+    Ok(())
+}
+
+pub(crate) fn spawn_child(cmd: &mut process::Command,
+                          handle: &Handle,
+                          orphans: Orphans,
+                          options: &SpawnOptions)
+    -> io::Result<SpawnedChild>
+{
+    let mut child = options.spawn_std(cmd)?;
+
+    let job = if options.kill_tree {
+        match Job::assign(&child) {
+            Ok(job) => Some(job),
+            Err(e) => {
+                // Don't leave behind a child which can't be torn down the
+                // way it was asked to be.
+                drop(child.kill());
+                drop(child.wait());
+                return Err(e);
+            },
+        }
+    } else {
+        None
+    };
+
+    let mut spawned = from_std(child, handle, orphans)?;
+    spawned.child.job = job;
+    Ok(spawned)
+}
+
+/// Sets the priority class of the child spawned by `cmd` to the one closest
+/// to the niceness `priority`.
+pub(crate) fn set_priority(cmd: &mut process::Command, priority: i32) {
+    use std::os::windows::process::CommandExt;
+
+    let class = match priority {
+        i32::MIN..=-11 => HIGH_PRIORITY_CLASS,
+        -10..=-1 => ABOVE_NORMAL_PRIORITY_CLASS,
+        0 => NORMAL_PRIORITY_CLASS,
+        1..=10 => BELOW_NORMAL_PRIORITY_CLASS,
+        _ => IDLE_PRIORITY_CLASS,
+    };
+
+    cmd.creation_flags(class);
+}
+
+pub(crate) fn from_std(mut child: process::Child, handle: &Handle, _orphans: Orphans)
+    -> io::Result<SpawnedChild>
+{
+    let setup = (|| {
+        let stdin = stdio(child.stdin.take(), handle)?;
+        let stdout = stdio(child.stdout.take(), handle)?;
+        let stderr = stdio(child.stderr.take(), handle)?;
+        Ok((stdin, stdout, stderr))
+    })();
+
+    let (stdin, stdout, stderr) = match setup {
+        Ok(setup) => setup,
+        Err(e) => {
+            // Don't leave behind a child which nobody can wait on anymore
+            drop(child.kill());
+            return Err(e);
+        },
+    };
 
     Ok(SpawnedChild {
         child: Child {
             child,
             waiting: None,
+            job: None,
         },
         stdin,
         stdout,
@@ -87,11 +249,65 @@ impl Child {
     pub fn id(&self) -> u32 {
         self.child.id()
     }
+
+    /// The process handle we hold keeps the id from being reused, even once
+    /// the child has exited, so the id remains valid for as long as we exist.
+    pub fn current_id(&self) -> Option<u32> {
+        Some(self.id())
+    }
+
+    /// The creation time of the child as reported by `GetProcessTimes`, in
+    /// 100-nanosecond intervals since January 1, 1601 (UTC).
+    pub fn start_time(&self) -> Option<u64> {
+        unsafe {
+            let mut creation: FILETIME = mem::zeroed();
+            let mut exit: FILETIME = mem::zeroed();
+            let mut kernel: FILETIME = mem::zeroed();
+            let mut user: FILETIME = mem::zeroed();
+            let rc = GetProcessTimes(self.child.as_raw_handle(),
+                                     &mut creation,
+                                     &mut exit,
+                                     &mut kernel,
+                                     &mut user);
+            if rc == 0 {
+                return None;
+            }
+            Some((u64::from(creation.dwHighDateTime) << 32) | u64::from(creation.dwLowDateTime))
+        }
+    }
+
+    pub fn try_wait(&mut self) -> io::Result<Option<ExitStatus>> {
+        try_wait(&self.child)
+    }
+
+    pub fn into_std(self) -> process::Child {
+        // Dropping any pending wait unregisters it from the thread pool
+        let Child { child, waiting, job } = self;
+        drop(waiting);
+        drop(job);
+        child
+    }
+
+    /// Windows has no notion of job control, so the only state change a child
+    /// ever goes through is exiting.
+    pub fn poll_event(&mut self) -> Poll<ChildEvent, io::Error> {
+        let status = try_ready!(self.poll());
+        Ok(Async::Ready(ChildEvent::Exited(status)))
+    }
+
+    /// Windows has no notion of a "polite" termination request for arbitrary
+    /// processes, so this is equivalent to killing the child.
+    pub fn terminate(&mut self) -> io::Result<()> {
+        self.kill()
+    }
 }
 
 impl Kill for Child {
     fn kill(&mut self) -> io::Result<()> {
-        self.child.kill()
+        match self.job {
+            Some(ref job) => job.terminate(),
+            None => self.child.kill(),
+        }
     }
 }
 
@@ -106,6 +322,14 @@ impl Future for Child {
                     Async::Ready(()) => {}
                     Async::NotReady => return Ok(Async::NotReady),
                 }
+            }
+
+            if self.waiting.is_some() {
+                // The receiver isn't fused: polling it again after it has
+                // completed would fail as if the sender had been dropped. So
+                // stop waiting on it, and should we be polled again once the
+                // child has exited, look the status up afresh instead
+                self.waiting = None;
                 let status = try!(try_wait(&self.child)).expect("not ready yet");
                 return Ok(status.into())
             }
@@ -178,6 +402,99 @@ pub type ChildStdin = PollEvented<NamedPipe>;
 pub type ChildStdout = PollEvented<NamedPipe>;
 pub type ChildStderr = PollEvented<NamedPipe>;
 
+pub(crate) fn write_stdin(io: &mut ChildStdin, buf: &[u8]) -> io::Result<usize> {
+    io::Write::write(io, buf)
+}
+
+/// Returns whether killing a child failed since it had already exited.
+pub(crate) fn is_already_exited(e: &io::Error) -> bool {
+    e.kind() == io::ErrorKind::InvalidInput
+}
+
+/// `FILE_COMPLETION_INFORMATION`, for use with `NtSetInformationFile`.
+#[repr(C)]
+struct FileCompletionInformation {
+    port: HANDLE,
+    key: PVOID,
+}
+
+/// `IO_STATUS_BLOCK`, whose first field is a union of an `NTSTATUS` and a
+/// pointer.
+#[repr(C)]
+struct IoStatusBlock {
+    status: PVOID,
+    information: usize,
+}
+
+/// `FileReplaceCompletionInformation`, available since Windows 8.1.
+const FILE_REPLACE_COMPLETION_INFORMATION: i32 = 61;
+
+#[link(name = "ntdll")]
+extern "system" {
+    fn NtSetInformationFile(file: HANDLE,
+                            io_status: *mut IoStatusBlock,
+                            info: PVOID,
+                            len: ULONG,
+                            class: i32) -> LONG;
+}
+
+/// Deregisters a stdio handle from its event loop so it can be used with
+/// synchronous code.
+///
+/// A `NamedPipe` can't give up its handle, so we duplicate it instead.
+/// Dropping the `NamedPipe` then cancels the read it keeps pending in the
+/// background, though anything it had already read is lost. Finally the
+/// handle is detached from the reactor's completion port, which would
+/// otherwise be handed the completions of any overlapped I/O done through
+/// the handle (such as by the standard library's pipes) as if they were its
+/// own.
+pub(crate) fn into_std_stdio<T>(io: PollEvented<NamedPipe>, _nonblocking: bool) -> io::Result<T>
+    where T: FromRawHandle,
+{
+    let pipe = io.into_inner()?;
+
+    let mut handle = ptr::null_mut();
+    unsafe {
+        let process = GetCurrentProcess();
+        if DuplicateHandle(process, pipe.as_raw_handle() as HANDLE, process, &mut handle,
+                           0, FALSE, DUPLICATE_SAME_ACCESS) == 0 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+    drop(pipe);
+
+    let mut info = FileCompletionInformation {
+        port: ptr::null_mut(),
+        key: ptr::null_mut(),
+    };
+    let mut status = IoStatusBlock {
+        status: ptr::null_mut(),
+        information: 0,
+    };
+    let ret = unsafe {
+        NtSetInformationFile(handle,
+                             &mut status,
+                             &mut info as *mut _ as PVOID,
+                             mem::size_of::<FileCompletionInformation>() as ULONG,
+                             FILE_REPLACE_COMPLETION_INFORMATION)
+    };
+    if ret < 0 {
+        unsafe { CloseHandle(handle); }
+        return Err(io::Error::other(format!("failed to detach a stdio handle from its \
+                                             event loop (NTSTATUS {:#x})", ret)));
+    }
+
+    Ok(unsafe { T::from_raw_handle(handle as RawHandle) })
+}
+
+/// Registers our end of one of the child's stdio pipes with the reactor.
+///
+/// The standard library already creates piped stdio as a named pipe whose
+/// end in the parent is opened with `FILE_FLAG_OVERLAPPED` (only the child's
+/// end is synchronous, as most programs expect), so the handle can be driven
+/// by the reactor's completion port as is, and reading from or writing to it
+/// never blocks a thread. Creating the pipes ourselves via `CreateNamedPipe`
+/// would gain nothing over that.
 fn stdio<T>(option: Option<T>, handle: &Handle)
             -> io::Result<Option<PollEvented<NamedPipe>>>
     where T: IntoRawHandle,
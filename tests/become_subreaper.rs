@@ -0,0 +1,45 @@
+#![cfg(target_os = "linux")]
+
+// Becoming a subreaper affects the whole process, so this lives in a test
+// binary of its own.
+
+extern crate futures;
+extern crate tokio_process;
+
+use futures::Future;
+use std::fs;
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+use tokio_process::CommandExt;
+
+#[test]
+fn become_subreaper() {
+    tokio_process::become_subreaper().expect("failed to become a subreaper");
+
+    // The grandchild outlives the shell which spawned it
+    let child = Command::new("sh")
+        .arg("-c")
+        .arg("sleep 0.2 & echo $!")
+        .stdout(Stdio::piped())
+        .spawn_async()
+        .unwrap();
+    let output = child.wait_with_output().wait().expect("failed to wait");
+    assert!(output.status.success());
+
+    let grandchild = String::from_utf8(output.stdout).unwrap();
+    let grandchild = grandchild.trim();
+    let stat = fs::read_to_string(format!("/proc/{}/stat", grandchild)).unwrap();
+    let ppid = stat.rsplit(')').next().unwrap().split_whitespace().nth(1).unwrap();
+    assert_eq!(ppid, tokio_process::current_pid().to_string());
+
+    // Don't leave the grandchild behind as a zombie
+    tokio_process::reap_reparented_children().expect("failed to reap reparented children");
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while Path::new(&format!("/proc/{}", grandchild)).exists() {
+        assert!(Instant::now() < deadline, "reparented child {} was not reaped", grandchild);
+        tokio_process::reap_orphans_now(Duration::from_millis(0));
+        thread::sleep(Duration::from_millis(10));
+    }
+}
@@ -0,0 +1,564 @@
+#![cfg(unix)]
+
+extern crate futures;
+extern crate tokio;
+extern crate tokio_io;
+extern crate tokio_process;
+
+use std::io::{self, BufReader};
+use std::os::unix::process::ExitStatusExt;
+use std::process::{Command, ExitStatus, Stdio};
+use futures::{Future, Stream};
+use std::time::{Duration, Instant};
+use tokio::timer::Interval;
+use tokio_io::io::{read_to_end, read_until};
+use tokio_process::{Child, ChildEvent, CommandConfigExt, CommandExt, ExitReason, ProcessExit, SpawnHandle};
+
+mod support;
+
+const SIGINT: i32 = 2;
+const SIGKILL: i32 = 9;
+const SIGSEGV: i32 = 11;
+const SIGTERM: i32 = 15;
+
+/// Spawns a shell which ignores `SIGTERM` and waits until it has installed its
+/// trap before handing it back.
+fn spawn_ignoring_sigterm() -> Child {
+    let mut child = Command::new("sh")
+        .arg("-c")
+        .arg("trap '' TERM; echo ready; read line")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn_async()
+        .expect("failed to spawn");
+
+    let stdout = child.stdout().take().unwrap();
+    let (_, line) = support::run_with_timeout(read_until(BufReader::new(stdout), b'\n', Vec::new()))
+        .expect("failed to read from child");
+    assert_eq!(line, b"ready\n");
+
+    child
+}
+
+#[test]
+fn terminate_graceful_without_escalation() {
+    let mut child = support::cmd("cat")
+        .stdin(Stdio::piped())
+        .spawn_async()
+        .expect("failed to spawn");
+
+    let exit = support::run_with_timeout(child.terminate_graceful(Duration::from_secs(1)))
+        .expect("failed to terminate");
+
+    assert!(!exit.escalated);
+    assert_eq!(exit.status.signal(), Some(SIGTERM));
+}
+
+#[test]
+fn terminate_graceful_escalates_to_kill() {
+    let mut child = spawn_ignoring_sigterm();
+
+    let exit = support::run_with_timeout(child.terminate_graceful(Duration::from_millis(100)))
+        .expect("failed to terminate");
+
+    assert!(exit.escalated);
+    assert_eq!(exit.status.signal(), Some(SIGKILL));
+    assert!(child.was_killed_by_us());
+}
+
+#[test]
+fn terminate_graceful_already_exited() {
+    let mut rt = support::CurrentThreadRuntime::new().expect("failed to get runtime");
+    let mut child = support::cmd("exit").arg("3").spawn_async().unwrap();
+    let status = rt.block_on(support::with_timeout(&mut child)).expect("failed to wait");
+    assert_eq!(status.code(), Some(3));
+
+    let exit = rt.block_on(support::with_timeout(child.terminate_graceful(Duration::from_secs(1))))
+        .expect("failed to terminate");
+
+    assert!(!exit.escalated);
+    assert_eq!(exit.status, status);
+}
+
+#[test]
+fn kill_timeout_escalates_to_kill() {
+    let child = spawn_ignoring_sigterm();
+
+    let status = support::run_with_timeout(child.kill_timeout(Duration::from_millis(100)))
+        .expect("failed to kill");
+    assert_eq!(status.signal(), Some(SIGKILL));
+}
+
+#[test]
+fn kill_timeout_signals_before_polled() {
+    let mut rt = support::CurrentThreadRuntime::new().expect("failed to get runtime");
+    let mut child = Command::new("sh")
+        .arg("-c")
+        .arg("trap 'echo terminated; exit 0' TERM; echo ready; while :; do sleep 0.01; done")
+        .stdout(Stdio::piped())
+        .spawn_async()
+        .expect("failed to spawn");
+    child.kill_on_drop(false);
+
+    let stdout = BufReader::new(child.stdout().take().unwrap());
+    let (stdout, line) = rt.block_on(support::with_timeout(read_until(stdout, b'\n', Vec::new())))
+        .expect("failed to read from child");
+    assert_eq!(line, b"ready\n");
+
+    // Never polled, yet the child has still been asked to terminate
+    drop(child.kill_timeout(Duration::from_secs(10)));
+
+    let (_, rest) = rt.block_on(support::with_timeout(read_to_end(stdout, Vec::new())))
+        .expect("failed to read from child");
+    assert_eq!(rest, b"terminated\n");
+}
+
+#[test]
+fn signal_delivers_to_child() {
+    let mut rt = support::CurrentThreadRuntime::new().expect("failed to get runtime");
+    let mut child = support::cmd("cat")
+        .stdin(Stdio::piped())
+        .spawn_async()
+        .expect("failed to spawn");
+
+    child.signal(SIGINT).expect("failed to signal child");
+    let status = rt.block_on(support::with_timeout(&mut child)).expect("failed to wait");
+    assert_eq!(status.signal(), Some(SIGINT));
+
+    // The child has been reaped, so its pid should no longer be signalled
+    let err = child.signal(SIGINT).expect_err("signalled a reaped child");
+    assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+}
+
+#[test]
+fn raw_wait_status() {
+    let mut rt = support::CurrentThreadRuntime::new().expect("failed to get runtime");
+
+    let mut child = support::cmd("exit").arg("3").spawn_async().unwrap();
+    assert_eq!(child.raw_wait_status(), None);
+    rt.block_on(support::with_timeout(&mut child)).expect("failed to wait");
+    assert_eq!(child.raw_wait_status(), Some(3 << 8));
+
+    let mut child = support::cmd("cat")
+        .stdin(Stdio::piped())
+        .spawn_async()
+        .expect("failed to spawn");
+    child.signal(SIGTERM).expect("failed to signal child");
+    let deadline = Instant::now() + Duration::from_secs(3);
+    let status = loop {
+        if let Some(status) = child.try_status().expect("failed to check status") {
+            break status;
+        }
+        assert!(Instant::now() < deadline, "child did not exit");
+        std::thread::sleep(Duration::from_millis(10));
+    };
+    assert_eq!(child.raw_wait_status(), Some(status.into_raw()));
+    assert_eq!(child.raw_wait_status(), Some(SIGTERM));
+}
+
+#[test]
+fn wait_fails_once_reactor_is_gone() {
+    use tokio::reactor::Reactor;
+
+    let reactor = Reactor::new().expect("failed to create reactor");
+    let mut child = support::cmd("cat")
+        .stdin(Stdio::piped())
+        .spawn_async_with_handle(&reactor.handle())
+        .expect("failed to spawn");
+    drop(reactor);
+
+    // Nothing would ever wake us up once the child exits, so waiting on it
+    // must fail instead of hanging
+    let err = support::run_with_timeout(&mut child).expect_err("waited without a reactor");
+    assert!(err.to_string().contains("exit notifications"), "{}", err);
+}
+
+#[test]
+fn spawn_fails_once_reactor_is_gone() {
+    use std::env;
+    use std::fs;
+    use std::process;
+    use tokio::reactor::Reactor;
+
+    let marker = env::temp_dir().join(format!("tokio-process-no-reactor-{}", process::id()));
+    drop(fs::remove_file(&marker));
+
+    let reactor = Reactor::new().expect("failed to create reactor");
+    let handle = reactor.handle();
+    drop(reactor);
+
+    let err = Command::new("touch")
+        .arg(&marker)
+        .spawn_async_with_handle(&handle)
+        .map(drop)
+        .expect_err("spawned without a reactor");
+    assert!(err.to_string().starts_with("no tokio reactor is running"), "{}", err);
+
+    // The child was never spawned in the first place
+    assert!(!marker.exists());
+}
+
+#[test]
+fn kill_process_tree() {
+    let mut spawner = SpawnHandle::new(Default::default());
+    spawner.kill_process_tree(true);
+
+    // The backgrounded grandchild holds on to stdout until it is killed too
+    let mut child = spawner.spawn(Command::new("sh")
+        .arg("-c")
+        .arg("sleep 30 & echo ready; wait")
+        .stdout(Stdio::piped()))
+        .expect("failed to spawn");
+
+    let mut rt = support::CurrentThreadRuntime::new().expect("failed to get runtime");
+
+    let stdout = child.stdout().take().unwrap();
+    let (stdout, line) = rt.block_on(support::with_timeout(read_until(BufReader::new(stdout), b'\n', Vec::new())))
+        .expect("failed to read from child");
+    assert_eq!(line, b"ready\n");
+
+    child.kill().expect("failed to kill");
+    let status = rt.block_on(support::with_timeout(child))
+        .expect("failed to wait");
+    assert_eq!(status.signal(), Some(SIGKILL));
+
+    let (_, rest) = rt.block_on(support::with_timeout(read_to_end(stdout, Vec::new())))
+        .expect("failed to read from child");
+    assert!(rest.is_empty());
+}
+
+#[test]
+fn kill_group() {
+    let mut child = Command::new("sh")
+        .arg("-c")
+        .arg("sleep 30 | sleep 30")
+        .new_process_group()
+        .spawn_async()
+        .expect("failed to spawn");
+
+    child.kill_group().expect("failed to kill group");
+
+    let status = support::run_with_timeout(child)
+        .expect("failed to wait");
+    assert_eq!(ProcessExit::from(status), ProcessExit::Signaled {
+        signal: SIGKILL,
+        core_dumped: false,
+    });
+}
+
+#[test]
+fn core_dumped() {
+    // Rule out actually dumping a core, which may be disabled anyway
+    let mut child = Command::new("sh")
+        .arg("-c")
+        .arg("ulimit -c 0; kill -SEGV $$")
+        .spawn_async()
+        .expect("failed to spawn");
+
+    let status = support::run_with_timeout(&mut child)
+        .expect("failed to wait");
+    assert_eq!(status.signal(), Some(SIGSEGV));
+    assert!(!ProcessExit::from(status).core_dumped());
+
+    // The wait status of a process which dumped a core after a `SIGSEGV`
+    let dumped = ExitStatus::from_raw(0x80 | SIGSEGV);
+    assert!(ProcessExit::from(dumped).core_dumped());
+}
+
+#[test]
+fn process_exit_stopped() {
+    // The wait status of a process stopped by a `SIGSTOP`
+    let stopped = ExitStatus::from_raw(0x137f);
+    assert_eq!(ProcessExit::from(stopped), ProcessExit::Other(0x137f));
+    assert!(!ProcessExit::from(stopped).success());
+    assert!(!ProcessExit::from(stopped).core_dumped());
+}
+
+/// Spawns a shell running `script`, returning once it has printed `ready`.
+fn spawn_ready(rt: &mut support::CurrentThreadRuntime, script: &str) -> Child {
+    let mut child = Command::new("sh")
+        .arg("-c")
+        .arg(script)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn_async()
+        .expect("failed to spawn");
+
+    let stdout = BufReader::new(child.stdout().take().unwrap());
+    let (_, line) = rt.block_on(support::with_timeout(read_until(stdout, b'\n', Vec::new())))
+        .expect("failed to read stdout");
+    assert_eq!(line, b"ready\n");
+    child
+}
+
+#[test]
+fn kill_and_collect_stderr() {
+    let mut rt = support::CurrentThreadRuntime::new().expect("failed to get runtime");
+
+    let mut child = spawn_ready(&mut rt, "echo crashing >&2; echo ready; exec sleep 10");
+    let start = Instant::now();
+    let stderr = rt.block_on(support::with_timeout(child.kill_and_collect_stderr(Duration::from_secs(10))))
+        .expect("failed to collect stderr");
+    assert_eq!(stderr, b"crashing\n");
+    assert!(start.elapsed() < Duration::from_secs(5));
+    assert!(child.try_status().unwrap().is_some());
+
+    // The stderr of the child is held open by the `sleep`
+    let mut child = spawn_ready(&mut rt, "echo crashing >&2; sleep 10 & echo ready; wait");
+    let start = Instant::now();
+    let stderr = rt.block_on(support::with_timeout(child.kill_and_collect_stderr(Duration::from_millis(200))))
+        .expect("failed to collect stderr");
+    assert_eq!(stderr, b"crashing\n");
+    assert!(start.elapsed() >= Duration::from_millis(200));
+}
+
+#[test]
+fn kill_group_after_exit() {
+    let mut child = support::cmd("exit")
+        .arg("0")
+        .new_process_group()
+        .spawn_async()
+        .expect("failed to spawn");
+
+    let status = support::run_with_timeout(&mut child).expect("failed to wait");
+    assert!(status.success());
+
+    // The group id may have been recycled once the child was reaped
+    let err = child.kill_group().unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+}
+
+#[test]
+fn kill_group_without_group() {
+    let mut child = support::cmd("cat")
+        .stdin(Stdio::piped())
+        .spawn_async()
+        .expect("failed to spawn");
+
+    assert!(child.kill_group().is_err());
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn drop_does_not_block_and_reaps_in_background() {
+    use std::path::Path;
+
+    // Keeps running until it is killed on drop
+    let child = spawn_ignoring_sigterm();
+    let proc_entry = format!("/proc/{}", child.current_id().unwrap());
+
+    let start = Instant::now();
+    drop(child);
+    assert!(start.elapsed() < Duration::from_secs(1));
+
+    // The killed child is reaped once any other child gets polled. Zombies
+    // keep their `/proc` entry until they've been reaped.
+    let deadline = Instant::now() + Duration::from_secs(3);
+    while Path::new(&proc_entry).exists() {
+        assert!(Instant::now() < deadline, "orphan was never reaped");
+
+        let status = support::run_with_timeout(support::cmd("exit").arg("0").spawn_async().unwrap())
+            .expect("failed to wait");
+        assert!(status.success());
+    }
+}
+
+#[test]
+fn reap_orphans_now_reaps_exited_orphans() {
+    let spawner = SpawnHandle::new(Default::default());
+
+    let mut child = spawner.spawn(Command::new("sh")
+        .arg("-c")
+        .arg("read line")
+        .stdin(Stdio::piped()))
+        .expect("failed to spawn");
+    let id = child.current_id().unwrap();
+    let stdin = child.stdin().take().unwrap();
+
+    assert_eq!(spawner.orphan_queue_len(), 0);
+    child.kill_on_drop(false);
+    drop(child);
+    assert_eq!(spawner.orphan_queue_len(), 1);
+
+    // Still blocked reading its stdin
+    assert_eq!(spawner.reap_orphans_now(Duration::from_millis(50)), vec![id]);
+    assert_eq!(spawner.orphan_queue_len(), 1);
+
+    // Once it exits it can be reaped without polling any other children
+    drop(stdin);
+    assert!(spawner.reap_orphans_now(Duration::from_secs(3)).is_empty());
+    assert_eq!(spawner.orphan_queue_len(), 0);
+}
+
+#[test]
+fn spawn_and_drop_many_children_concurrently() {
+    let spawner = SpawnHandle::new(Default::default());
+
+    let threads = (0..8).map(|_| {
+        let spawner = spawner.clone();
+        std::thread::spawn(move || {
+            for _ in 0..1250 {
+                let mut child = spawner.spawn(&mut Command::new("true")).expect("failed to spawn");
+                child.kill_on_drop(false);
+                drop(child);
+            }
+        })
+    }).collect::<Vec<_>>();
+
+    for thread in threads {
+        thread.join().unwrap();
+    }
+
+    // Every one of the 10k children is reaped, none is lost in the queue
+    assert!(spawner.reap_orphans_now(Duration::from_secs(10)).is_empty());
+    assert_eq!(spawner.orphan_queue_len(), 0);
+}
+
+#[test]
+fn reap_orphan_by_id() {
+    let spawner = SpawnHandle::new(Default::default());
+
+    let mut child = spawner.spawn(support::cmd("cat")
+        .stdin(Stdio::piped()))
+        .expect("failed to spawn");
+    let id = child.current_id().unwrap();
+    let stdin = child.stdin().take().unwrap();
+
+    child.kill_on_drop(false);
+    drop(child);
+
+    // Still blocked reading its stdin
+    assert!(spawner.reap_orphan(id).expect("failed to reap orphan").is_none());
+    assert_eq!(spawner.orphan_queue_len(), 1);
+
+    drop(stdin);
+    let deadline = Instant::now() + Duration::from_secs(3);
+    let status = loop {
+        if let Some(status) = spawner.reap_orphan(id).expect("failed to reap orphan") {
+            break status;
+        }
+        assert!(Instant::now() < deadline, "orphan did not exit");
+        std::thread::sleep(Duration::from_millis(10));
+    };
+    assert!(status.success());
+    assert_eq!(spawner.orphan_queue_len(), 0);
+
+    let err = spawner.reap_orphan(id).expect_err("reaped an orphan twice");
+    assert_eq!(err.kind(), io::ErrorKind::NotFound);
+}
+
+#[test]
+fn orphan_reaper_reaps_orphans_eagerly() {
+    let mut rt = support::CurrentThreadRuntime::new().expect("failed to get runtime");
+    let spawner = SpawnHandle::new(Default::default());
+
+    let mut child = spawner.spawn(Command::new("sh")
+        .arg("-c")
+        .arg("read line")
+        .stdin(Stdio::piped()))
+        .expect("failed to spawn");
+    let stdin = child.stdin().take().unwrap();
+
+    child.kill_on_drop(false);
+    drop(child);
+    assert_eq!(spawner.orphan_queue_len(), 1);
+
+    let reaper = spawner.orphan_reaper().expect("failed to create reaper");
+    rt.spawn(reaper.map_err(|e| panic!("failed to reap orphans: {}", e)));
+
+    // No other child is ever polled, yet the orphan is reaped once it exits
+    drop(stdin);
+    let reaped = Interval::new_interval(Duration::from_millis(10))
+        .skip_while(|_| Ok(spawner.orphan_queue_len() > 0))
+        .into_future()
+        .map(|_| ())
+        .map_err(|(e, _)| e);
+    rt.block_on(support::with_timeout(reaped)).expect("failed to wait");
+}
+
+#[test]
+fn status_stream_reports_job_control() {
+    extern crate libc;
+
+    let mut rt = support::CurrentThreadRuntime::new().expect("failed to get runtime");
+    let mut spawner = SpawnHandle::new(Default::default());
+    spawner.track_job_control(true);
+
+    let mut child = spawner.spawn(Command::new("sleep").arg("10"))
+        .expect("failed to spawn");
+
+    let mut next_event = |child: &mut Child| {
+        let (event, _) = rt.block_on(support::with_timeout(child.status_stream().into_future()))
+            .map_err(|(e, _)| e)
+            .expect("failed to wait");
+        event
+    };
+
+    child.signal(libc::SIGSTOP).expect("failed to stop child");
+    assert_eq!(next_event(&mut child), Some(ChildEvent::Stopped(libc::SIGSTOP)));
+
+    child.signal(libc::SIGCONT).expect("failed to continue child");
+    assert_eq!(next_event(&mut child), Some(ChildEvent::Continued));
+
+    // Neither signal terminates the child
+    assert!(!child.was_killed_by_us());
+
+    child.kill().expect("failed to kill child");
+    match next_event(&mut child) {
+        Some(ChildEvent::Exited(status)) => assert_eq!(status.signal(), Some(SIGKILL)),
+        event => panic!("unexpected event: {:?}", event),
+    }
+}
+
+#[test]
+fn reap_poll_interval_without_notifications() {
+    extern crate libc;
+
+    let mut spawner = SpawnHandle::new(Default::default());
+    // Job control tracking rules out per-child exit descriptors, so the
+    // child is only ever notified through a signal which is never sent
+    spawner.reap_signal(libc::SIGUSR2)
+        .track_job_control(true)
+        .reap_poll_interval(Duration::from_millis(50));
+
+    let child = spawner.spawn(Command::new("sh").arg("-c").arg("sleep 0.2"))
+        .expect("failed to spawn");
+
+    let status = support::run_with_timeout(child).expect("failed to wait");
+    assert!(status.success());
+}
+
+#[test]
+fn exit_reason() {
+    let mut rt = support::CurrentThreadRuntime::new().expect("failed to get runtime");
+
+    let mut exited = support::cmd("exit").arg("2").spawn_async().unwrap();
+    rt.block_on(support::with_timeout(&mut exited)).expect("failed to wait");
+    assert_eq!(exited.exit_reason().unwrap(), Some(ExitReason::Exited(2)));
+    assert!(!exited.was_killed_by_us());
+
+    let mut killed = spawn_ignoring_sigterm();
+    killed.kill().expect("failed to kill");
+    rt.block_on(support::with_timeout(&mut killed)).expect("failed to wait");
+    assert_eq!(killed.exit_reason().unwrap(), Some(ExitReason::Killed));
+    assert!(killed.was_killed_by_us());
+
+    let mut signaled = spawn_ignoring_sigterm();
+    signaled.signal(SIGINT).expect("failed to signal");
+    rt.block_on(support::with_timeout(&mut signaled)).expect("failed to wait");
+    assert_eq!(signaled.exit_reason().unwrap(), Some(ExitReason::Signaled(SIGINT)));
+    assert!(signaled.was_killed_by_us());
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn exit_reason_external_kill() {
+    let mut child = Command::new("sh")
+        .arg("-c")
+        .arg("kill -9 $$")
+        .spawn_async()
+        .expect("failed to spawn");
+
+    support::run_with_timeout(&mut child).expect("failed to wait");
+    assert_eq!(child.exit_reason().unwrap(), Some(ExitReason::Signaled(SIGKILL)));
+}
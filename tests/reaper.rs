@@ -0,0 +1,63 @@
+#![cfg(all(unix, feature = "unstable"))]
+
+extern crate futures;
+extern crate tokio;
+extern crate tokio_process;
+
+use futures::Future;
+use futures::stream;
+use std::io;
+use std::os::unix::process::ExitStatusExt;
+use std::process::{Command, ExitStatus};
+use tokio::reactor::Handle;
+use tokio::runtime::current_thread::Runtime;
+use tokio_process::unix::{sigchld_stream, AtomicOrphanQueue, Generation, Reaper, Wait};
+
+/// A process which exits after having been waited on a number of times.
+struct MockProcess {
+    waits_until_exit: usize,
+}
+
+impl Wait for MockProcess {
+    fn id(&self) -> u32 {
+        42
+    }
+
+    fn try_wait(&mut self) -> io::Result<Option<ExitStatus>> {
+        if self.waits_until_exit == 0 {
+            return Ok(Some(ExitStatus::from_raw(0)));
+        }
+
+        self.waits_until_exit -= 1;
+        Ok(None)
+    }
+}
+
+#[test]
+fn custom_reaper() {
+    let queue = AtomicOrphanQueue::new();
+    let notifications = stream::iter_ok::<_, io::Error>(vec![(), (), ()]);
+
+    let reaper = Reaper::new(
+        MockProcess { waits_until_exit: 2 },
+        &queue,
+        notifications,
+        Generation::new(),
+    );
+
+    let status = reaper.wait().expect("failed to reap");
+    assert!(status.success());
+}
+
+#[test]
+fn reaper_with_sigchld_stream() {
+    let mut rt = Runtime::new().expect("failed to create runtime");
+
+    let queue = AtomicOrphanQueue::new();
+    let child = Command::new("sleep").arg("0.1").spawn().expect("failed to spawn");
+    let signal = sigchld_stream(&Handle::default()).expect("failed to register for SIGCHLD");
+
+    let reaper = Reaper::new(child, &queue, signal, Generation::new());
+    let status = rt.block_on(reaper).expect("failed to reap");
+    assert!(status.success());
+}
@@ -1,6 +1,16 @@
+extern crate futures;
+extern crate tokio;
 extern crate tokio_process;
 
-use tokio_process::CommandExt;
+use futures::{Future, Stream};
+use std::process::Stdio;
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+use tokio::reactor::Reactor;
+use tokio_process::{ChildEvent, CommandExt, ProcessExit, SpawnHandle, Supervised};
+#[cfg(unix)]
+use tokio_process::CommandConfigExt;
 
 mod support;
 
@@ -11,13 +21,784 @@ fn simple() {
 
     let mut child = cmd.spawn_async().unwrap();
 
-    let id = child.id();
+    let id = child.current_id().expect("child has not been reaped yet");
     assert!(id > 0);
 
     let status = support::run_with_timeout(&mut child)
         .expect("failed to run future");
     assert_eq!(status.code(), Some(2));
 
-    assert_eq!(child.id(), id);
+    // The id may be reused once the child has been reaped
+    assert_eq!(child.current_id(), None);
+    assert_eq!(ProcessExit::from(status), ProcessExit::Exited(2));
+    assert!(!ProcessExit::from(status).success());
+    assert!(!ProcessExit::from(status).core_dumped());
     drop(child.kill());
 }
+
+#[test]
+fn spawn_outside_runtime() {
+    // Without a runtime the default handle falls back to a reactor running
+    // in the background, which drives the child just as well
+    let child = support::cmd("exit").arg("2").spawn_async().expect("failed to spawn");
+
+    let (tx, rx) = mpsc::channel();
+    thread::spawn(move || tx.send(child.wait()).unwrap());
+    let status = rx.recv_timeout(Duration::from_secs(3))
+        .expect("timed out")
+        .expect("failed to wait");
+    assert_eq!(status.code(), Some(2));
+}
+
+#[test]
+fn exit_notified_after_pending_poll() {
+    let mut child = support::cmd("cat")
+        .stdin(Stdio::piped())
+        .spawn_async()
+        .unwrap();
+
+    let mut rt = support::CurrentThreadRuntime::new().expect("failed to get runtime");
+
+    // Polling a running child registers for exit notifications (a wait on
+    // the process handle on Windows), which must wake us once it exits
+    // rather than relying on being polled again.
+    let pending = rt.block_on(futures::future::lazy(|| child.poll()))
+        .expect("failed to poll child");
+    assert!(pending.is_not_ready());
+
+    drop(child.stdin().take());
+    let status = rt.block_on(support::with_timeout(&mut child))
+        .expect("failed to wait");
+    assert!(status.success());
+}
+
+#[test]
+fn status_stream() {
+    let mut cmd = support::cmd("exit");
+    cmd.arg("4");
+
+    let mut child = cmd.spawn_async().unwrap();
+    let events = support::run_with_timeout(child.status_stream().collect())
+        .expect("failed to run stream");
+
+    assert_eq!(events.len(), 1);
+    match events[0] {
+        ChildEvent::Exited(status) => assert_eq!(status.code(), Some(4)),
+        event => panic!("unexpected event: {:?}", event),
+    }
+}
+
+#[test]
+fn try_status() {
+    let mut cmd = support::cmd("exit");
+    cmd.arg("3");
+
+    let mut child = cmd.spawn_async().unwrap();
+
+    let deadline = Instant::now() + Duration::from_secs(3);
+    let status = loop {
+        match child.try_status().expect("failed to check status") {
+            Some(status) => break status,
+            None => {
+                assert!(Instant::now() < deadline, "child did not exit");
+                thread::sleep(Duration::from_millis(10));
+            },
+        }
+    };
+    assert_eq!(status.code(), Some(3));
+
+    // The child can still be used as a future afterwards
+    let polled = support::run_with_timeout(child)
+        .expect("failed to run future");
+    assert_eq!(polled, status);
+}
+
+#[test]
+fn success() {
+    support::run_with_timeout(support::cmd("exit").arg("0").spawn_async().unwrap().success())
+        .expect("child failed");
+
+    let err = support::run_with_timeout(support::cmd("exit").arg("2").spawn_async().unwrap().success())
+        .expect_err("child succeeded");
+    assert_eq!(err.kind(), std::io::ErrorKind::Other);
+    assert_eq!(err.to_string(), "process exited with code 2");
+}
+
+#[test]
+fn command_ext_implementable_downstream() {
+    use std::io;
+    use std::process::Command;
+    use tokio::reactor::Handle;
+    use tokio_process::{Child, OutputAsync, StatusAsync};
+
+    // Only the methods which were always required need implementing
+    struct Wrapper(Command);
+
+    impl CommandExt for Wrapper {
+        fn spawn_async_with_handle(&mut self, handle: &Handle) -> io::Result<Child> {
+            self.0.spawn_async_with_handle(handle)
+        }
+
+        fn status_async_with_handle(&mut self, handle: &Handle) -> io::Result<StatusAsync> {
+            self.0.status_async_with_handle(handle)
+        }
+
+        fn output_async_with_handle(&mut self, handle: &Handle) -> OutputAsync {
+            self.0.output_async_with_handle(handle)
+        }
+    }
+
+    let mut cmd = support::cmd("exit");
+    cmd.arg("2");
+    let mut wrapper = Wrapper(cmd);
+
+    let child = wrapper.spawn_async_retry(3, Duration::from_millis(1)).unwrap();
+    let status = support::run_with_timeout(child).expect("failed to run future");
+    assert_eq!(status.code(), Some(2));
+
+    let output = support::run_with_timeout(wrapper.output_async_timeout(Duration::from_secs(10)))
+        .expect("failed to run future");
+    assert_eq!(output.status.code(), Some(2));
+}
+
+#[test]
+fn spawn_many() {
+    use std::process::Command;
+
+    let commands = (0..8).map(|code| {
+        let mut cmd = support::cmd("exit");
+        cmd.arg(code.to_string());
+        cmd
+    }).chain(Some(Command::new("nonexistent-program-tokio-process-spawn-many")));
+
+    let children = tokio_process::spawn_many_async(commands.collect(), &tokio::reactor::Handle::default())
+        .expect("event loop has gone away");
+    assert_eq!(children.len(), 9);
+
+    let mut children = children.into_iter();
+    let err = children.next_back().unwrap().expect_err("spawned a nonexistent program");
+    assert_eq!(err.kind(), std::io::ErrorKind::NotFound);
+
+    let children = children.map(|child| child.expect("failed to spawn child"));
+    let statuses = support::run_with_timeout(futures::future::join_all(children))
+        .expect("failed to run future");
+    let codes: Vec<_> = statuses.iter().map(|status| status.code()).collect();
+    assert_eq!(codes, (0..8).map(Some).collect::<Vec<_>>());
+
+    let spawner = SpawnHandle::new(Default::default());
+    let mut cmd = support::cmd("exit");
+    cmd.arg("0");
+    let children = spawner.spawn_many(vec![cmd])
+        .expect("event loop has gone away");
+    for child in children {
+        let status = support::run_with_timeout(child.expect("failed to spawn child"))
+            .expect("failed to run future");
+        assert!(status.success());
+    }
+}
+
+#[test]
+#[cfg(unix)]
+fn success_signaled() {
+    use std::process::Command;
+
+    let mut child = Command::new("sleep").arg("10").spawn_async().unwrap();
+    child.signal(15).unwrap();
+    let err = support::run_with_timeout(child.success()).expect_err("child succeeded");
+    assert_eq!(err.to_string(), "process was terminated by signal 15");
+}
+
+#[test]
+fn poll_after_exit() {
+    let mut cmd = support::cmd("exit");
+    cmd.arg("6");
+
+    let mut child = cmd.spawn_async().unwrap();
+    let mut rt = support::CurrentThreadRuntime::new().expect("failed to get runtime");
+    let status = rt.block_on(support::with_timeout(&mut child)).expect("failed to wait");
+    assert_eq!(status.code(), Some(6));
+
+    // Polling again resolves to the same status instead of panicking or
+    // never resolving
+    let polled = rt.block_on(futures::future::lazy(|| child.poll())).expect("failed to poll");
+    assert_eq!(polled, status.into());
+    let polled = rt.block_on(support::with_timeout(&mut child)).expect("failed to wait");
+    assert_eq!(polled, status);
+}
+
+#[test]
+fn poll_exit() {
+    let mut cmd = support::cmd("exit");
+    cmd.arg("5");
+
+    let mut child = cmd.spawn_async().unwrap();
+    let status = support::run_with_timeout(futures::future::poll_fn(|| child.poll_exit()))
+        .expect("failed to run future");
+    assert_eq!(status.code(), Some(5));
+
+    // Once exited the same status keeps being returned
+    assert_eq!(child.poll_exit().expect("failed to poll child"), status.into());
+}
+
+#[test]
+fn kill_after_exit() {
+    let mut cmd = support::cmd("exit");
+    cmd.arg("2");
+
+    // Already reaped
+    let mut child = cmd.spawn_async().unwrap();
+    let status = support::run_with_timeout(&mut child).expect("failed to run future");
+    child.kill().expect("failed to kill an exited child");
+    assert!(!child.was_killed_by_us());
+
+    // Exited, but never polled
+    let mut child = cmd.spawn_async().unwrap();
+    let deadline = Instant::now() + Duration::from_secs(3);
+    while child.try_status().expect("failed to check status").is_none() {
+        assert!(Instant::now() < deadline, "child did not exit");
+        thread::sleep(Duration::from_millis(10));
+    }
+    child.kill().expect("failed to kill an exited child");
+    assert!(!child.was_killed_by_us());
+    assert_eq!(support::run_with_timeout(child).expect("failed to run future"), status);
+}
+
+#[test]
+fn with_timeout() {
+    let mut child = support::cmd("cat")
+        .stdin(Stdio::piped())
+        .spawn_async()
+        .unwrap();
+
+    let mut rt = support::CurrentThreadRuntime::new().expect("failed to get runtime");
+
+    let status = rt.block_on(support::with_timeout(child.with_timeout(Duration::from_millis(100))))
+        .expect("failed to wait");
+    assert_eq!(status, None);
+
+    // Still running, so it exits once its stdin is closed
+    drop(child.stdin().take());
+    let status = rt.block_on(support::with_timeout(child.with_timeout(Duration::from_secs(2))))
+        .expect("failed to wait");
+    assert!(status.expect("child did not exit").success());
+}
+
+#[test]
+fn spawn_handle() {
+    let spawner = SpawnHandle::new(Default::default());
+
+    let mut cmd = support::cmd("exit");
+    cmd.arg("4");
+
+    let mut forgotten = spawner.spawn(&mut cmd).unwrap();
+    forgotten.kill_on_drop(false);
+    drop(forgotten);
+
+    let child = spawner.spawn(&mut cmd).unwrap();
+    let status = support::run_with_timeout(child)
+        .expect("failed to run future");
+    assert_eq!(status.code(), Some(4));
+}
+
+#[test]
+fn spawn_handle_hooks() {
+    use std::sync::{Arc, Mutex};
+
+    let spawned = Arc::new(Mutex::new(Vec::new()));
+    let reaped = Arc::new(Mutex::new(Vec::new()));
+
+    let mut spawner = SpawnHandle::new(Default::default());
+    {
+        let spawned = spawned.clone();
+        let reaped = reaped.clone();
+        spawner.on_spawn(move |id, cmd| spawned.lock().unwrap().push((id, cmd.get_args().count())))
+            .on_reap(move |id, status| reaped.lock().unwrap().push((id, status.code())));
+    }
+
+    let mut cmd = support::cmd("exit");
+    cmd.arg("6");
+
+    let mut child = spawner.spawn(&mut cmd).unwrap();
+    let id = child.current_id().unwrap();
+    assert_eq!(*spawned.lock().unwrap(), vec![(id, 1)]);
+    assert!(reaped.lock().unwrap().is_empty());
+
+    let status = support::run_with_timeout(&mut child).expect("failed to run future");
+    assert_eq!(status.code(), Some(6));
+    assert_eq!(*reaped.lock().unwrap(), vec![(id, Some(6))]);
+
+    // Only invoked once per child
+    assert_eq!(child.try_status().unwrap(), Some(status));
+    assert_eq!(reaped.lock().unwrap().len(), 1);
+
+    // Children spawned elsewhere are not observed
+    let child = cmd.spawn_async().unwrap();
+    support::run_with_timeout(child).expect("failed to run future");
+    assert_eq!(spawned.lock().unwrap().len(), 1);
+    assert_eq!(reaped.lock().unwrap().len(), 1);
+}
+
+#[test]
+#[cfg(unix)]
+fn process_spec() {
+    use std::env;
+    use tokio_process::{ProcessSpec, StdioSpec};
+
+    let mut spec = ProcessSpec::new("sh");
+    spec.arg("-c")
+        .arg("echo $ADDED $REMOVED; pwd -P")
+        .env("ADDED", "1")
+        .env("REMOVED", "2")
+        .env_remove("REMOVED")
+        .current_dir(env::temp_dir())
+        .stdout(StdioSpec::Piped);
+
+    let spec = spec.clone();
+    for _ in 0..2 {
+        let output = support::run_with_timeout(spec.spawn_async().unwrap().wait_with_output())
+            .expect("failed to run future");
+        assert!(output.status.success());
+
+        let stdout = String::from_utf8(output.stdout).unwrap();
+        let dir = env::temp_dir().canonicalize().unwrap();
+        assert_eq!(stdout, format!("1\n{}\n", dir.display()));
+    }
+
+    let mut supervised = Supervised::spawn(move || spec.command()).unwrap();
+    support::run_with_timeout(&mut supervised).expect("failed to run future");
+    supervised.restart().unwrap();
+    support::run_with_timeout(&mut supervised).expect("failed to run future");
+}
+
+#[test]
+fn supervised_restart() {
+    let mut code = 0;
+    let mut supervised = Supervised::spawn(|| {
+        code += 1;
+        let mut cmd = support::cmd("exit");
+        cmd.arg(code.to_string());
+        cmd
+    }).unwrap();
+
+    let mut rt = support::CurrentThreadRuntime::new().expect("failed to get runtime");
+
+    let status = rt.block_on(support::with_timeout(&mut supervised))
+        .expect("failed to run future");
+    assert_eq!(status.code(), Some(1));
+
+    supervised.restart().expect("failed to restart");
+    assert_eq!(1, supervised.restarts());
+
+    let status = rt.block_on(support::with_timeout(&mut supervised))
+        .expect("failed to run future");
+    assert_eq!(status.code(), Some(2));
+}
+
+#[test]
+fn spawn_with_handle_off_reactor_thread() {
+    // Drive a reactor on a background thread, which this thread knows
+    // nothing about except through its handle.
+    let reactor = Reactor::new().expect("failed to create reactor");
+    let handle = reactor.handle();
+    let _background = reactor.background().expect("failed to run reactor");
+
+    let mut cmd = support::cmd("exit");
+    cmd.arg("5");
+
+    let child = cmd.spawn_async_with_handle(&handle).unwrap();
+    let status = child.wait().expect("failed to wait");
+    assert_eq!(status.code(), Some(5));
+}
+
+#[test]
+#[cfg(unix)]
+fn before_exec_async() {
+    use std::io;
+
+    let mut cmd = support::cmd("exit");
+    cmd.arg("0");
+    unsafe {
+        cmd.before_exec_async(|| Ok(()));
+    }
+
+    let status = support::run_with_timeout(cmd.spawn_async().unwrap())
+        .expect("failed to run future");
+    assert!(status.success());
+
+    unsafe {
+        cmd.before_exec_async(|| Err(io::Error::from_raw_os_error(1)));
+    }
+
+    let err = cmd.spawn_async().unwrap_err();
+    assert_eq!(err.raw_os_error(), Some(1));
+}
+
+#[test]
+#[cfg(unix)]
+fn spawn_async_retry() {
+    extern crate libc;
+
+    use std::env;
+    use std::ffi::CString;
+    use std::fs;
+    use std::io;
+    use std::os::unix::ffi::OsStrExt;
+    use std::time::Instant;
+
+    let marker = env::temp_dir().join(format!("tokio-process-spawn-retry-{}", std::process::id()));
+    drop(fs::remove_file(&marker));
+    let path = CString::new(marker.as_os_str().as_bytes()).unwrap();
+
+    // Only the first attempt to spawn creates the marker, which makes it fail
+    // as if the process limit had been reached
+    let mut cmd = support::cmd("exit");
+    cmd.arg("0");
+    unsafe {
+        cmd.before_exec_async(move || {
+            let fd = libc::open(path.as_ptr(), libc::O_CREAT | libc::O_EXCL | libc::O_WRONLY, 0o600);
+            if fd == -1 {
+                return Ok(());
+            }
+            libc::close(fd);
+            Err(io::Error::from_raw_os_error(libc::EAGAIN))
+        });
+    }
+
+    let err = cmd.spawn_async_retry(1, Duration::from_millis(50)).unwrap_err();
+    assert_eq!(err.raw_os_error(), Some(libc::EAGAIN));
+
+    fs::remove_file(&marker).expect("failed to remove marker");
+    let start = Instant::now();
+    let child = cmd.spawn_async_retry(2, Duration::from_millis(50)).unwrap();
+    assert!(start.elapsed() >= Duration::from_millis(50));
+    fs::remove_file(&marker).expect("failed to remove marker");
+
+    let status = support::run_with_timeout(child).expect("failed to run future");
+    assert!(status.success());
+
+    // Other errors are never retried
+    let mut cmd = support::cmd("exit");
+    cmd.arg("0");
+    unsafe {
+        cmd.before_exec_async(|| Err(io::Error::from_raw_os_error(libc::EPERM)));
+    }
+    let start = Instant::now();
+    let err = cmd.spawn_async_retry(3, Duration::from_secs(10)).unwrap_err();
+    assert_eq!(err.raw_os_error(), Some(libc::EPERM));
+    assert!(start.elapsed() < Duration::from_secs(10));
+}
+
+#[test]
+#[cfg(unix)]
+fn wait_with_output_rusage() {
+    use std::process::Command;
+
+    let mut cmd = Command::new("sh");
+    cmd.arg("-c")
+        .arg("i=0; while [ $i -lt 100000 ]; do i=$((i + 1)); done; echo $i")
+        .stdout(Stdio::piped());
+
+    let child = cmd.spawn_async().unwrap();
+    let (output, usage) = support::run_with_timeout(child.wait_with_output_rusage())
+        .expect("failed to run future");
+    assert!(output.status.success());
+    assert_eq!(output.stdout, b"100000\n");
+
+    let cpu_time = usage.user_time + usage.system_time;
+    assert!(cpu_time > Duration::from_millis(0));
+    assert!(usage.wall_time >= usage.user_time);
+    assert!(usage.max_rss > 0);
+
+    // The resource usage is lost once the child has been reaped
+    let mut child = support::cmd("exit").arg("0").spawn_async().unwrap();
+    support::run_with_timeout(&mut child).expect("failed to run future");
+    assert!(support::run_with_timeout(child.wait_with_output_rusage()).is_err());
+}
+
+#[test]
+#[cfg(unix)]
+fn rlimit() {
+    extern crate libc;
+
+    use std::os::raw::c_int;
+    use std::process::Command;
+
+    let mut cmd = Command::new("sh");
+    cmd.arg("-c").arg("ulimit -n").stdout(Stdio::piped());
+    cmd.rlimit(libc::RLIMIT_NOFILE as c_int, 32, 64);
+    cmd.rlimit(libc::RLIMIT_NOFILE as c_int, 16, 64);
+
+    let output = support::run_with_timeout(cmd.output_async())
+        .expect("failed to run future");
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8_lossy(&output.stdout).trim(), "16");
+
+    // Invalid resources make spawning fail
+    cmd.rlimit(-1, 0, 0);
+    assert!(cmd.spawn_async().is_err());
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn cpu_affinity() {
+    use std::io::ErrorKind;
+    use std::process::Command;
+
+    let mut cmd = Command::new("grep");
+    cmd.arg("Cpus_allowed_list").arg("/proc/self/status").stdout(Stdio::piped());
+    cmd.cpu_affinity(&[0]);
+
+    let output = support::run_with_timeout(cmd.output_async())
+        .expect("failed to run future");
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert_eq!(stdout.split_whitespace().collect::<Vec<_>>(), vec!["Cpus_allowed_list:", "0"]);
+
+    let err = Command::new("true").cpu_affinity(&[]).spawn_async().unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidInput);
+
+    let err = Command::new("true").cpu_affinity(&[0, 1 << 20]).spawn_async().unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidInput);
+}
+
+#[test]
+#[cfg(unix)]
+fn nice() {
+    use std::process::Command;
+
+    let mut cmd = Command::new("nice");
+    cmd.stdout(Stdio::piped());
+    cmd.nice(5);
+    // Out of range, so leaves the priority as is
+    cmd.nice(100);
+
+    let output = support::run_with_timeout(cmd.output_async())
+        .expect("failed to run future");
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8_lossy(&output.stdout).trim(), "5");
+}
+
+#[test]
+#[cfg(unix)]
+fn umask() {
+    use std::process::Command;
+
+    let mut cmd = Command::new("sh");
+    cmd.arg("-c").arg("umask").stdout(Stdio::piped());
+    cmd.umask(0o022);
+    cmd.umask(0o10027);
+
+    let output = support::run_with_timeout(cmd.output_async())
+        .expect("failed to run future");
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8_lossy(&output.stdout).trim(), "0027");
+}
+
+#[test]
+#[cfg(unix)]
+fn sanitized_env() {
+    use std::env;
+    use std::process::Command;
+
+    let path = env::var("PATH").expect("PATH is not set");
+
+    let mut cmd = Command::new("env");
+    cmd.stdout(Stdio::piped())
+        .env("DISCARDED", "1")
+        .sanitized_env(&["PATH", "TOKIO_PROCESS_UNSET_VARIABLE"])
+        .env("ADDED", "1");
+
+    let output = support::run_with_timeout(cmd.output_async())
+        .expect("failed to run future");
+    assert!(output.status.success());
+
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let mut vars = stdout.lines().collect::<Vec<_>>();
+    vars.sort();
+    assert_eq!(vars, vec!["ADDED=1".to_string(), format!("PATH={}", path)]);
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn daemonize() {
+    use std::process::Command;
+
+    let mut cmd = Command::new("sh");
+    cmd.arg("-c")
+        .arg("read -r pid comm state ppid pgrp sid rest < /proc/self/stat; echo $pid $pgrp $sid")
+        .daemonize()
+        .stdout(Stdio::piped());
+
+    let child = cmd.spawn_async().unwrap();
+    let id = child.current_id().expect("child has not been reaped yet").to_string();
+    let output = support::run_with_timeout(child.wait_with_output())
+        .expect("failed to run future");
+    assert!(output.status.success());
+
+    // The child leads both a new session and a new process group
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert_eq!(stdout.split_whitespace().collect::<Vec<_>>(), vec![&*id, &*id, &*id]);
+
+    // A process group leader cannot start a new session
+    let err = Command::new("true").new_process_group().daemonize().spawn_async().unwrap_err();
+    assert_eq!(err.raw_os_error(), Some(1));
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn ppid() {
+    use std::fs;
+    use std::process::Command;
+
+    assert_eq!(tokio_process::current_pid(), std::process::id());
+
+    let mut child = Command::new("sleep").arg("10").spawn_async().unwrap();
+    let id = child.current_id().expect("child has not been reaped yet");
+    assert_eq!(child.ppid(), Some(tokio_process::current_pid()));
+
+    let stat = fs::read_to_string(format!("/proc/{}/stat", id)).unwrap();
+    let ppid = stat.rsplit(')').next().unwrap().split_whitespace().nth(1).unwrap();
+    assert_eq!(ppid, tokio_process::current_pid().to_string());
+
+    child.kill().unwrap();
+    support::run_with_timeout(&mut child).expect("failed to run future");
+    assert_eq!(child.ppid(), None);
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn start_time() {
+    use std::fs;
+    use std::process::Command;
+
+    let mut child = Command::new("sleep").arg("10").spawn_async().unwrap();
+    let id = child.current_id().expect("child has not been reaped yet");
+    let start_time = child.start_time().expect("failed to read start time");
+
+    let stat = fs::read_to_string(format!("/proc/{}/stat", id)).unwrap();
+    let expected = stat.rsplit(')').next().unwrap().split_whitespace().nth(19).unwrap();
+    assert_eq!(start_time.to_string(), expected);
+
+    // Still available once the child is gone
+    child.kill().unwrap();
+    support::run_with_timeout(&mut child).expect("failed to run future");
+    assert_eq!(child.start_time(), Some(start_time));
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn start_time_after_reaped() {
+    use std::process::Command;
+
+    // Adopting a child which was already reaped is a mistake, but mustn't
+    // report the start time of whichever process reused its id
+    let mut child = Command::new("true").spawn().unwrap();
+    child.wait().unwrap();
+
+    let child = tokio_process::Child::from_std(child, &Default::default()).unwrap();
+    assert_eq!(child.start_time(), None);
+}
+
+#[test]
+fn exit_handle() {
+    let mut cmd = support::cmd("exit");
+    cmd.arg("3");
+
+    let child = cmd.spawn_async().unwrap();
+    let first = child.exit_handle();
+    let second = first.clone();
+
+    let (status, first, second) = support::run_with_timeout(child.join3(first, second))
+        .expect("failed to run future");
+    assert_eq!(status.code(), Some(3));
+    assert_eq!(first, status);
+    assert_eq!(second, status);
+
+    // A handle created once the child has exited resolves straight away
+    let mut child = cmd.spawn_async().unwrap();
+    let status = support::run_with_timeout(&mut child).expect("failed to run future");
+    assert_eq!(child.exit_handle().wait().expect("failed to wait"), status);
+
+    // The exit can never be observed once the child has been dropped
+    let handle = cmd.spawn_async().unwrap().exit_handle();
+    assert!(handle.wait().is_err());
+}
+
+#[test]
+fn exit_status_cached() {
+    let mut cmd = support::cmd("exit");
+    cmd.arg("3");
+
+    let mut child = cmd.spawn_async().unwrap();
+    let handle = child.exit_handle();
+    assert_eq!(child.exit_status_cached(), None);
+    assert_eq!(handle.status(), None);
+
+    let status = support::run_with_timeout(&mut child).expect("failed to run future");
+    assert_eq!(child.exit_status_cached(), Some(status));
+    assert_eq!(handle.status(), Some(status));
+
+    // The status outlives the `Child` which observed it
+    drop(child);
+    assert_eq!(handle.status(), Some(status));
+    assert_eq!(status.code(), Some(3));
+}
+
+#[test]
+#[cfg(unix)]
+fn exit_status_cached_after_signal() {
+    let mut cmd = support::cmd("exit");
+    cmd.arg("3");
+
+    // Signalling a child which has exited reaps it instead
+    let mut child = cmd.spawn_async().unwrap();
+    let handle = child.exit_handle();
+    let deadline = Instant::now() + Duration::from_secs(3);
+    while child.signal(0).is_ok() {
+        assert!(Instant::now() < deadline, "child did not exit");
+        thread::sleep(Duration::from_millis(10));
+    }
+
+    let status = child.exit_status_cached().expect("exit was not published");
+    assert_eq!(status.code(), Some(3));
+    assert_eq!(handle.wait().expect("failed to wait"), status);
+}
+
+#[test]
+#[cfg(unix)]
+fn reap_signal() {
+    extern crate libc;
+
+    use tokio::timer::Timeout;
+
+    // Tracking job control relies on signals on every platform, so the exit
+    // can't be noticed any other way
+    let mut spawner = SpawnHandle::new(Default::default());
+    spawner.reap_signal(libc::SIGUSR2).track_job_control(true);
+
+    // Exits only after the first poll, which would otherwise reap it
+    let mut cmd = std::process::Command::new("sleep");
+    cmd.arg("0.1");
+
+    let mut child = spawner.spawn(&mut cmd).unwrap();
+    let mut rt = support::CurrentThreadRuntime::new().expect("failed to get runtime");
+
+    // A SIGCHLD alone doesn't get the child reaped
+    let err = rt.block_on(Timeout::new(&mut child, Duration::from_millis(300)))
+        .expect_err("child was reaped without the reap signal");
+    assert!(err.is_elapsed());
+
+    let deadline = Instant::now() + Duration::from_secs(3);
+    let status = loop {
+        unsafe {
+            libc::kill(libc::getpid(), libc::SIGUSR2);
+        }
+
+        match rt.block_on(Timeout::new(&mut child, Duration::from_millis(50))) {
+            Ok(status) => break status,
+            Err(ref e) if e.is_elapsed() => assert!(Instant::now() < deadline, "child was not reaped"),
+            Err(e) => panic!("failed to wait: {}", e),
+        }
+    };
+    assert!(status.success());
+}
@@ -1,16 +1,24 @@
+extern crate bytes;
 extern crate futures;
 #[macro_use]
 extern crate log;
+extern crate tokio;
 extern crate tokio_io;
 extern crate tokio_process;
 
 use std::io;
 use std::process::{Stdio, ExitStatus, Command};
+use std::time::{Duration, Instant};
 
-use futures::future::Future;
+use bytes::Bytes;
+use futures::future::{self, Future};
 use futures::stream::{self, Stream};
 use tokio_io::io::{read_until, write_all, read_to_end};
-use tokio_process::{CommandExt, Child};
+#[cfg(windows)]
+use tokio_io::io::read_exact;
+use tokio_process::{Child, ChildStderr, ChildStdin, ChildStdout, CommandExt, OutputEvent};
+#[cfg(unix)]
+use tokio_process::CommandConfigExt;
 
 mod support;
 
@@ -21,7 +29,7 @@ fn cat() -> Command {
     cmd
 }
 
-fn feed_cat(mut cat: Child, n: usize) -> Box<Future<Item = ExitStatus, Error = io::Error>> {
+fn feed_cat(mut cat: Child, n: usize) -> Box<dyn Future<Item = ExitStatus, Error = io::Error>> {
     let stdin = cat.stdin().take().unwrap();
     let stdout = cat.stdout().take().unwrap();
 
@@ -48,7 +56,7 @@ fn feed_cat(mut cat: Child, n: usize) -> Box<Future<Item = ExitStatus, Error = i
                     Err(io::Error::new(io::ErrorKind::BrokenPipe, "broken pipe"))
                 },
                 (true, n) if n != 0 => {
-                    Err(io::Error::new(io::ErrorKind::Other, "extraneous data"))
+                    Err(io::Error::other("extraneous data"))
                 },
                 _ => {
                     let s = std::str::from_utf8(&vec).unwrap();
@@ -56,7 +64,7 @@ fn feed_cat(mut cat: Child, n: usize) -> Box<Future<Item = ExitStatus, Error = i
                     if done || s == expected {
                         Ok((reader, i + 1))
                     } else {
-                        Err(io::Error::new(io::ErrorKind::Other, "unexpected data"))
+                        Err(io::Error::other("unexpected data"))
                     }
                 }
             }
@@ -85,6 +93,50 @@ fn feed_a_lot() {
     assert_eq!(status.code(), Some(0));
 }
 
+#[test]
+fn spawn_many_rapidly() {
+    // Children exit (and thus deliver a SIGCHLD) while later ones are still
+    // being spawned
+    let children = (0..100).map(|_| {
+        let mut child = cat().stderr(Stdio::piped()).spawn_async().unwrap();
+        drop(child.stdin().take());
+        child.wait_with_output()
+    }).collect::<Vec<_>>();
+
+    let outputs = support::run_with_timeout(future::join_all(children)).unwrap();
+    for output in outputs {
+        assert!(output.status.success());
+        assert!(output.stdout.is_empty());
+    }
+}
+
+#[test]
+fn splice_output_to() {
+    let mut first = cat().spawn_async().unwrap();
+    let mut second = cat().spawn_async().unwrap();
+
+    let stdin = first.stdin().take().unwrap();
+    let stdout = second.stdout().take().unwrap();
+
+    let data = (0..10_000).map(|i| format!("line {}\n", i)).collect::<String>().into_bytes();
+    let write = write_all(stdin, data.clone()).map(drop);
+    let splice = first.splice_output_to(&mut second);
+    let read = read_to_end(stdout, Vec::new()).map(|(_, read)| read);
+
+    let future = write.join3(splice, read).join(first.join(second));
+    let ((_, spliced, read), (first, second)) = support::run_with_timeout(future).unwrap();
+    assert!(first.success());
+    assert!(second.success());
+    assert_eq!(spliced, data.len() as u64);
+    assert_eq!(read, data);
+
+    // Both handles are required
+    let mut child = cat().spawn_async().unwrap();
+    let mut other = cat().stdin(Stdio::null()).spawn_async().unwrap();
+    let err = support::run_with_timeout(child.splice_output_to(&mut other)).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::NotConnected);
+}
+
 // FIXME: delete this test once we have a resolution for #51
 // This test's setup is flaky, and setting up a consistent test is nearly
 // impossible: right now we invoke `cat` and immediately kill it, expecting
@@ -104,7 +156,8 @@ fn drop_kills() {
     let stdout = child.stdout().take().unwrap();
     drop(child);
 
-    // Ignore all write errors since we expect a broken pipe here
+    // Ignore all write errors since we expect a broken pipe here (see
+    // `write_after_stdin_closed`)
     let writer = write_all(stdin, b"1234").then(|_| Ok(()));
     let reader = read_to_end(stdout, Vec::new());
 
@@ -117,6 +170,204 @@ fn drop_kills() {
     assert_eq!(output.len(), 0);
 }
 
+#[test]
+fn write_after_stdin_closed() {
+    let mut child = support::cmd("exit")
+        .arg("0")
+        .stdin(Stdio::piped())
+        .spawn_async()
+        .unwrap();
+    let stdin = child.stdin().take().unwrap();
+
+    // The child never reads its stdin, which is closed once it exits
+    let status = support::run_with_timeout(&mut child).unwrap();
+    assert!(status.success());
+
+    let err = support::run_with_timeout(write_all(stdin, b"1234")).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::BrokenPipe);
+}
+
+#[test]
+fn pending_write_fails_once_child_exits() {
+    let mut child = support::cmd("exit")
+        .arg("0")
+        .stdin(Stdio::piped())
+        .spawn_async()
+        .unwrap();
+    let stdin = child.stdin().take().unwrap();
+
+    // Far more than fits into the pipe, so the write is still pending when
+    // the child exits, and must be woken up by the pipe being closed
+    let data = vec![0; 16 * 1024 * 1024];
+    let start = Instant::now();
+    let err = support::run_with_timeout(write_all(stdin, data)).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::BrokenPipe);
+    assert!(start.elapsed() < Duration::from_secs(5));
+
+    let status = support::run_with_timeout(&mut child).unwrap();
+    assert!(status.success());
+}
+
+#[cfg(unix)]
+#[test]
+fn stdout_at_eof() {
+    let mut child = Command::new("sh")
+        .arg("-c")
+        .arg("echo hello; exec >&-; read line; true")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn_async()
+        .unwrap();
+
+    assert!(!child.stdout_at_eof());
+    assert!(!child.stderr_at_eof());
+
+    let stdout = child.stdout().take().unwrap();
+    let (_, output) = support::run_with_timeout(read_to_end(stdout, Vec::new())).unwrap();
+    assert_eq!(output, b"hello\n");
+
+    // The child closed its stdout, but is still waiting for input
+    assert!(child.stdout_at_eof());
+    assert!(!child.stderr_at_eof());
+    assert!(child.try_status().unwrap().is_none());
+
+    drop(child.stdin().take());
+    assert!(support::run_with_timeout(child).unwrap().success());
+}
+
+#[test]
+fn stdio_implements_tokio_io_traits() {
+    fn assert_read<T: tokio::io::AsyncRead>() {}
+    fn assert_write<T: tokio::io::AsyncWrite>() {}
+
+    assert_write::<ChildStdin>();
+    assert_read::<ChildStdout>();
+    assert_read::<ChildStderr>();
+
+    let mut child = cat().spawn_async().unwrap();
+    let stdin = child.stdin().take().unwrap();
+    let stdout = child.stdout().take().unwrap();
+
+    let write = tokio::io::write_all(stdin, b"hello\n").map(drop);
+    let read = tokio::io::read_to_end(stdout, Vec::new()).map(|(_, read)| read);
+
+    let (status, _, read) = support::run_with_timeout(child.join3(write, read)).unwrap();
+    assert!(status.success());
+    assert_eq!(read, b"hello\n");
+}
+
+#[test]
+fn split() {
+    let (mut child, stdin, stdout, stderr) = cat().spawn_async().unwrap().split();
+    assert!(child.stdin().is_none());
+    assert!(child.stdout().is_none());
+    assert!(stderr.is_none());
+
+    let writer = write_all(stdin.unwrap(), b"hello").map(|_| ());
+    let reader = read_to_end(stdout.unwrap(), Vec::new()).map(|(_, data)| data);
+
+    let (_, data, status) = support::run_with_timeout(writer.join3(reader, child))
+        .expect("failed to run child");
+    assert!(status.success());
+    assert_eq!(data, b"hello");
+}
+
+#[test]
+fn abandoned_write_leaves_stdin_consistent() {
+    use tokio::timer::Delay;
+
+    let mut child = cat().spawn_async().unwrap();
+    let mut stdin = child.stdin().take().unwrap();
+    let stdout = child.stdout().take().unwrap();
+
+    let mut rt = support::CurrentThreadRuntime::new().expect("failed to get runtime");
+
+    // Nobody reads the child's stdout yet, so the write fills up both pipes
+    // and is still pending when it is dropped
+    let data = b"0123456789abcdef\n".repeat(1 << 19);
+    let timeout = Delay::new(Instant::now() + Duration::from_millis(200));
+    let abandoned = write_all(&mut stdin, &data[..]).select2(timeout);
+    match rt.block_on(abandoned) {
+        Ok(future::Either::B(_)) => {},
+        _ => panic!("write should still have been pending"),
+    }
+
+    let write = write_all(stdin, b"end\n").map(drop);
+    let read = read_to_end(stdout, Vec::new()).map(|(_, read)| read);
+    let (_, read, status) = rt.block_on(support::with_timeout(write.join3(read, child)))
+        .unwrap();
+    assert!(status.success());
+
+    // Some prefix of the abandoned data, directly followed by the next write
+    assert!(read.len() > 4 && read.len() < data.len() + 4);
+    assert!(read.ends_with(b"end\n"));
+    assert_eq!(read[..read.len() - 4], data[..read.len() - 4]);
+}
+
+#[test]
+fn close_stdin() {
+    let mut child = cat().spawn_async().unwrap();
+    let mut rt = support::CurrentThreadRuntime::new().expect("failed to get runtime");
+
+    let stdin = child.stdin().take().unwrap();
+    let stdin = rt.block_on(support::with_timeout(write_all(stdin, b"hello")))
+        .expect("failed to write").0;
+    *child.stdin() = Some(stdin);
+
+    child.close_stdin().expect("failed to close stdin");
+    assert!(child.stdin().is_none());
+    child.close_stdin().expect("closing stdin again failed");
+
+    let output = rt.block_on(support::with_timeout(child.wait_with_output()))
+        .expect("failed to wait");
+    assert!(output.status.success());
+    assert_eq!(output.stdout, b"hello");
+}
+
+#[test]
+fn write_to_child() {
+    let child = cat().spawn_async().unwrap();
+    let mut rt = support::CurrentThreadRuntime::new().expect("failed to get runtime");
+
+    let mut child = rt.block_on(support::with_timeout(write_all(child, b"hello")))
+        .expect("failed to write").0;
+    child.close_stdin().expect("failed to close stdin");
+
+    let err = rt.block_on(write_all(&mut child, b"more")).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::NotConnected);
+
+    let output = rt.block_on(support::with_timeout(child.wait_with_output()))
+        .expect("failed to wait");
+    assert!(output.status.success());
+    assert_eq!(output.stdout, b"hello");
+}
+
+#[test]
+fn feed_stdin() {
+    let mut child = cat().spawn_async().unwrap();
+
+    let chunks = (0..1000).map(|i| format!("line {}\n", i).into_bytes());
+    let expected = chunks.clone().collect::<Vec<_>>().concat();
+
+    let feed = child.feed_stdin(stream::iter_ok(chunks));
+    let (_, output) = support::run_with_timeout(feed.join(child.wait_with_output()))
+        .expect("failed to feed child");
+
+    assert!(output.status.success());
+    assert_eq!(output.stdout, expected);
+}
+
+#[test]
+fn feed_stdin_not_captured() {
+    let mut child = support::cmd("exit").arg("0").spawn_async().unwrap();
+
+    let feed = child.feed_stdin(stream::iter_ok::<_, io::Error>(vec![b"data"]));
+    let err = support::run_with_timeout(feed).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::NotConnected);
+
+    assert!(support::run_with_timeout(child).unwrap().success());
+}
+
 #[test]
 fn wait_with_output_captures() {
     let mut child = cat().spawn_async().unwrap();
@@ -132,6 +383,56 @@ fn wait_with_output_captures() {
     assert_eq!(output.stderr.len(), 0);
 }
 
+#[test]
+#[cfg(unix)]
+fn inherit_stdout_capture_stderr() {
+    let mut child = Command::new("echo")
+        .arg("hello from an inherited stdout")
+        .stdout(Stdio::inherit())
+        .stderr(Stdio::piped())
+        .spawn_async()
+        .unwrap();
+
+    // Only piped streams are captured
+    assert!(child.stdin().is_none());
+    assert!(child.stdout().is_none());
+    let stderr = child.stderr().take().expect("stderr should be captured");
+
+    let read = read_to_end(stderr, Vec::new()).map(|(_, read)| read);
+    let (status, stderr) = support::run_with_timeout(child.join(read)).unwrap();
+    assert!(status.success());
+    assert!(stderr.is_empty());
+}
+
+#[test]
+fn stdout_redirected_to_file() {
+    use std::env;
+    use std::fs::{self, File};
+
+    let path = env::temp_dir().join(format!("tokio-process-stdout-{}", std::process::id()));
+    let file = File::create(&path).expect("failed to create file");
+
+    let mut child = cat()
+        .stdout(file)
+        .spawn_async()
+        .unwrap();
+
+    // The file is handed directly to the child rather than piped through us
+    assert!(child.stdout().is_none());
+    let stdin = child.stdin().take().unwrap();
+
+    let future = write_all(stdin, b"hello file\n").and_then(|(stdin, _)| {
+        drop(stdin);
+        child
+    });
+    let status = support::run_with_timeout(future).unwrap();
+    assert!(status.success());
+
+    let contents = fs::read(&path).expect("failed to read file");
+    drop(fs::remove_file(&path));
+    assert_eq!(contents, b"hello file\n");
+}
+
 #[test]
 fn status_closes_any_pipes() {
     // Cat will open a pipe between the parent and child.
@@ -142,3 +443,625 @@ fn status_closes_any_pipes() {
     support::run_with_timeout(child)
         .expect("time out exceeded! did we get stuck waiting on the child?");
 }
+
+#[test]
+fn close_all_stdio() {
+    // Cat would never exit while we hold its stdin open
+    let mut child = cat().stderr(Stdio::piped()).spawn_async().expect("failed to spawn child");
+    assert!(child.stdin().is_some());
+
+    child.close_all_stdio().expect("failed to close stdio");
+    assert!(child.stdin().is_none());
+    assert!(child.stdout().is_none());
+    assert!(child.stderr().is_none());
+
+    let status = support::run_with_timeout(&mut child)
+        .expect("time out exceeded! did we get stuck waiting on the child?");
+    assert!(status.success());
+
+    // Closing again is a no-op
+    child.close_all_stdio().expect("closing stdio again failed");
+}
+
+#[test]
+fn status_keep_stdio() {
+    let (status, stdio) = cat().status_async_keep_stdio().expect("failed to spawn child");
+
+    let stdin = stdio.stdin.expect("missing stdin");
+    let stdout = stdio.stdout.expect("missing stdout");
+    assert!(stdio.stderr.is_none());
+
+    // Closing stdin once the write completes lets the child exit
+    let write = write_all(stdin, b"1234\n").map(|(_, written)| written);
+    let read = read_to_end(stdout, Vec::new()).map(|(_, read)| read);
+
+    let (status, written, read) = support::run_with_timeout(status.join3(write, read)).unwrap();
+    assert!(status.success());
+    assert_eq!(&written[..], &read[..]);
+}
+
+#[test]
+fn into_std() {
+    use std::io::Write;
+
+    let child = cat().spawn_async().unwrap();
+    let mut child = child.into_std().expect("failed to convert child");
+
+    // The handles must be back in blocking mode, otherwise reading the output
+    // would fail with `WouldBlock` rather than waiting for the child.
+    child.stdin.take().unwrap().write_all(b"1234\n").unwrap();
+    let output = child.wait_with_output().expect("failed to wait on child");
+
+    assert!(output.status.success());
+    assert_eq!(output.stdout, b"1234\n");
+}
+
+#[cfg(windows)]
+#[test]
+fn into_std_after_reading() {
+    use std::io::Write;
+
+    let mut child = cat().spawn_async().unwrap();
+    let stdin = child.stdin().take().unwrap();
+    let stdout = child.stdout().take().unwrap();
+
+    // Once the line has been read another read is left pending on stdout,
+    // which must not swallow any output written after the conversion
+    let (stdin, _) = support::run_with_timeout(write_all(stdin, b"1234\n")).unwrap();
+    let (stdout, line) = support::run_with_timeout(read_exact(stdout, [0; 5])).unwrap();
+    assert_eq!(&line, b"1234\n");
+    *child.stdin() = Some(stdin);
+    *child.stdout() = Some(stdout);
+
+    let mut child = child.into_std().expect("failed to convert child");
+    child.stdin.take().unwrap().write_all(b"5678\n").unwrap();
+    let output = child.wait_with_output().expect("failed to wait on child");
+
+    assert!(output.status.success());
+    assert_eq!(output.stdout, b"5678\n");
+}
+
+#[cfg(unix)]
+#[test]
+fn into_std_nonblocking() {
+    extern crate libc;
+
+    use std::io::Read;
+    use std::os::unix::io::AsRawFd;
+
+    let child = cat().spawn_async().unwrap();
+    let mut child = child.into_std_nonblocking().expect("failed to convert child");
+
+    let mut stdout = child.stdout.take().unwrap();
+    let flags = unsafe { libc::fcntl(stdout.as_raw_fd(), libc::F_GETFL) };
+    assert!(flags != -1);
+    assert!(flags & libc::O_NONBLOCK != 0);
+
+    let mut buf = [0; 8];
+    let err = stdout.read(&mut buf).expect_err("read blocked");
+    assert_eq!(err.kind(), io::ErrorKind::WouldBlock);
+
+    drop(child.stdin.take());
+    assert!(child.wait().expect("failed to wait on child").success());
+}
+
+#[test]
+fn from_std() {
+    let child = cat().spawn().unwrap();
+    let mut child = Child::from_std(child, &Default::default()).expect("failed to adopt child");
+
+    let stdin = child.stdin().take().unwrap();
+    let out = child.wait_with_output();
+
+    let future = write_all(stdin, b"1234\n").map(|p| p.1).join(out);
+    let (written, output) = support::run_with_timeout(future).unwrap();
+
+    assert!(output.status.success());
+    assert_eq!(output.stdout, written);
+}
+
+#[cfg(unix)]
+#[test]
+fn merged_output() {
+    let mut child = Command::new("sh")
+        .arg("-c")
+        .arg("echo out; echo err >&2; echo out; echo err >&2")
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn_async()
+        .unwrap();
+
+    let merged = child.merged_output().expect("missing output");
+    assert!(child.stdout().is_none());
+    assert!(child.stderr().is_none());
+    assert!(child.merged_output().is_none());
+
+    let read = read_to_end(merged, Vec::new()).map(|(_, read)| read);
+    let (status, read) = support::run_with_timeout(child.join(read)).unwrap();
+    assert!(status.success());
+
+    let mut lines = String::from_utf8(read).unwrap()
+        .lines()
+        .map(String::from)
+        .collect::<Vec<_>>();
+    lines.sort();
+    assert_eq!(lines, ["err", "err", "out", "out"]);
+}
+
+#[cfg(unix)]
+#[test]
+fn output_events() {
+    let mut child = Command::new("sh")
+        .arg("-c")
+        .arg("echo out; sleep 0.2; echo err >&2; sleep 0.2; echo last")
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn_async()
+        .unwrap();
+
+    let events = child.output_events().expect("missing output");
+    assert!(child.stdout().is_none());
+    assert!(child.stderr().is_none());
+    assert!(child.output_events().is_none());
+
+    let (status, events) = support::run_with_timeout(child.join(events.collect())).unwrap();
+    assert!(status.success());
+    assert_eq!(events, vec![
+        OutputEvent::Stdout(Bytes::from_static(b"out\n")),
+        OutputEvent::Stderr(Bytes::from_static(b"err\n")),
+        OutputEvent::Stdout(Bytes::from_static(b"last\n")),
+    ]);
+}
+
+#[test]
+fn run() {
+    let (output, exit) = Command::new("sh")
+        .arg("-c")
+        .arg("echo out; sleep 0.2; echo err >&2")
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn_async()
+        .unwrap()
+        .run();
+
+    let (events, status) = support::run_with_timeout(output.collect().join(exit)).unwrap();
+    assert!(status.success());
+    assert_eq!(events, vec![
+        OutputEvent::Stdout(Bytes::from_static(b"out\n")),
+        OutputEvent::Stderr(Bytes::from_static(b"err\n")),
+    ]);
+
+    // The exit status isn't known before all of the output has been read
+    let (output, mut exit) = Command::new("sh")
+        .arg("-c")
+        .arg("echo out")
+        .stdout(Stdio::piped())
+        .spawn_async()
+        .unwrap()
+        .run();
+
+    std::thread::sleep(Duration::from_millis(200));
+    let mut rt = support::CurrentThreadRuntime::new().expect("failed to get runtime");
+    let pending = rt.block_on(future::lazy(|| exit.poll())).expect("failed to poll exit");
+    assert!(pending.is_not_ready());
+
+    // Unless the output is no longer of interest
+    drop(output);
+    let status = rt.block_on(support::with_timeout(exit)).expect("failed to wait");
+    assert!(status.success());
+}
+
+#[cfg(unix)]
+#[test]
+fn stdout_lines() {
+    let mut child = Command::new("sh")
+        .arg("-c")
+        .arg("printf 'first\\nsecond\\r\\n\\nlast'")
+        .stdout(Stdio::piped())
+        .spawn_async()
+        .unwrap();
+
+    let lines = child.stdout_lines().expect("missing stdout").collect();
+    let (status, lines) = support::run_with_timeout(child.join(lines)).unwrap();
+
+    assert!(status.success());
+    assert_eq!(lines, ["first", "second", "", "last"]);
+}
+
+#[test]
+fn null_stdio_is_not_registered() {
+    let mut child = support::cmd("exit")
+        .arg("0")
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn_async()
+        .unwrap();
+
+    // Nothing to register with the event loop, so no handles are created
+    assert!(child.stdin().is_none());
+    assert!(child.stdout().is_none());
+    assert!(child.stderr().is_none());
+
+    let status = support::run_with_timeout(&mut child).unwrap();
+    assert!(status.success());
+    assert!(child.stdin().is_none());
+    assert!(child.stdout().is_none());
+    assert!(child.stderr().is_none());
+}
+
+#[cfg(unix)]
+#[test]
+fn stdio_from_socket() {
+    use std::io::Write;
+    use std::os::fd::OwnedFd;
+    use std::os::unix::net::UnixStream;
+
+    let (ours, theirs) = UnixStream::pair().unwrap();
+    let mut cmd = Command::new("sh");
+    cmd.arg("-c")
+        .arg("read line; echo \"got $line\"")
+        .stdin(Stdio::from(OwnedFd::from(theirs)))
+        .stdout(Stdio::piped());
+    let mut child = cmd.spawn_async().unwrap();
+    // Closes our copy of the child's end
+    drop(cmd);
+
+    // The caller's own descriptor is handed to the child as is
+    assert!(child.stdin().is_none());
+
+    (&ours).write_all(b"hello\n").unwrap();
+    let output = support::run_with_timeout(child.wait_with_output()).unwrap();
+    assert!(output.status.success());
+    assert_eq!(output.stdout, b"got hello\n");
+}
+
+#[test]
+fn stdout_buffered() {
+    let mut child = cat().spawn_async().unwrap();
+    let stdin = child.stdin().take().unwrap();
+    let stdout = child.stdout_buffered(4).expect("missing stdout");
+    assert!(child.stdout_buffered(4).is_none());
+
+    // Lines longer than the buffer are still read in full
+    let write = write_all(stdin, b"first line\nsecond\n").map(drop);
+    let read = read_until(stdout, b'\n', Vec::new())
+        .and_then(|(stdout, first)| read_until(stdout, b'\n', Vec::new()).map(|(_, second)| (first, second)));
+
+    let (status, _, (first, second)) = support::run_with_timeout(child.join3(write, read)).unwrap();
+    assert!(status.success());
+    assert_eq!(first, b"first line\n");
+    assert_eq!(second, b"second\n");
+}
+
+#[test]
+fn stdout_chunks() {
+    let mut child = cat().spawn_async().unwrap();
+    let stdin = child.stdin().take().unwrap();
+
+    let data = (0..10_000).map(|i| format!("line {}\n", i)).collect::<String>().into_bytes();
+    // Drop stdin once everything was written so that cat exits
+    let write = write_all(stdin, data.clone()).map(drop);
+    let chunks = child.stdout_chunks(1000).expect("missing stdout").collect();
+
+    let future = child.join3(write, chunks);
+    let (status, _, chunks) = support::run_with_timeout(future).unwrap();
+    assert!(status.success());
+
+    assert!(chunks.iter().all(|chunk| !chunk.is_empty() && chunk.len() <= 1000));
+    assert_eq!(chunks.concat(), data);
+}
+
+#[cfg(unix)]
+#[test]
+fn wait_with_output_streaming() {
+    use std::cell::RefCell;
+
+    let child = Command::new("sh")
+        .arg("-c")
+        .arg("for i in 1 2 3; do echo out $i; echo err $i >&2; done")
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn_async()
+        .unwrap();
+
+    let stdout = RefCell::new(Vec::new());
+    let stderr = RefCell::new(Vec::new());
+    let future = child.wait_with_output_streaming(
+        |chunk| {
+            stdout.borrow_mut().extend_from_slice(&chunk);
+            Ok(())
+        },
+        |chunk| {
+            stderr.borrow_mut().extend_from_slice(&chunk);
+            Ok(())
+        },
+    );
+
+    let status = support::run_with_timeout(future).unwrap();
+    assert!(status.success());
+    assert_eq!(&*stdout.borrow(), b"out 1\nout 2\nout 3\n");
+    assert_eq!(&*stderr.borrow(), b"err 1\nerr 2\nerr 3\n");
+}
+
+#[cfg(unix)]
+#[test]
+fn wait_with_output_streaming_callback_error() {
+    let child = Command::new("sh")
+        .arg("-c")
+        .arg("echo out; sleep 10")
+        .stdout(Stdio::piped())
+        .spawn_async()
+        .unwrap();
+
+    let future = child.wait_with_output_streaming(
+        |_| Err(io::Error::other("callback failed")),
+        |_| Ok(()),
+    );
+
+    let err = support::run_with_timeout(future).unwrap_err();
+    assert_eq!(err.to_string(), "callback failed");
+}
+
+#[cfg(unix)]
+#[test]
+fn stdout_lines_invalid_utf8() {
+    let mut child = Command::new("sh")
+        .arg("-c")
+        .arg("printf 'ok\\n\\377\\n'")
+        .stdout(Stdio::piped())
+        .spawn_async()
+        .unwrap();
+
+    let lines = child.stdout_lines().expect("missing stdout")
+        .then(Ok::<_, io::Error>)
+        .collect();
+    let lines = support::run_with_timeout(lines).unwrap();
+
+    assert_eq!(lines.len(), 2);
+    assert_eq!(lines[0].as_ref().unwrap(), "ok");
+    assert_eq!(lines[1].as_ref().unwrap_err().kind(), io::ErrorKind::InvalidData);
+}
+
+#[cfg(unix)]
+#[test]
+fn wait_with_output_limited_truncates() {
+    let child = Command::new("yes")
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn_async()
+        .unwrap();
+
+    let limited = support::run_with_timeout(child.wait_with_output_limited(10)).unwrap();
+
+    assert!(limited.truncated);
+    assert!(!limited.output.status.success());
+    assert_eq!(limited.output.stdout, b"y\ny\ny\ny\ny\n");
+    assert!(limited.output.stderr.is_empty());
+}
+
+#[test]
+fn wait_with_output_limited_within_limit() {
+    let mut child = cat().stderr(Stdio::piped()).spawn_async().unwrap();
+    let stdin = child.stdin().take().unwrap();
+    let out = child.wait_with_output_limited(5);
+
+    let future = write_all(stdin, b"1234\n").map(|p| p.1).join(out);
+    let (_, limited) = support::run_with_timeout(future).unwrap();
+
+    assert!(!limited.truncated);
+    assert!(limited.output.status.success());
+    assert_eq!(limited.output.stdout, b"1234\n");
+}
+
+#[cfg(unix)]
+#[test]
+fn wait_with_output_limited_total() {
+    // Neither stream exceeds the limit on its own
+    let child = Command::new("sh")
+        .arg("-c")
+        .arg("printf 123456; sleep 0.2; printf abcdef >&2; sleep 10")
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn_async()
+        .unwrap();
+
+    let limited = support::run_with_timeout(child.wait_with_output_limited_total(10)).unwrap();
+
+    assert!(limited.truncated);
+    assert!(!limited.output.status.success());
+    assert_eq!(limited.output.stdout, b"123456");
+    assert_eq!(limited.output.stderr, b"abcd");
+
+    let child = Command::new("sh")
+        .arg("-c")
+        .arg("printf 12345; printf abcde >&2")
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn_async()
+        .unwrap();
+
+    let limited = support::run_with_timeout(child.wait_with_output_limited_total(10)).unwrap();
+
+    assert!(!limited.truncated);
+    assert!(limited.output.status.success());
+    assert_eq!(limited.output.stdout, b"12345");
+    assert_eq!(limited.output.stderr, b"abcde");
+}
+
+#[cfg(unix)]
+#[test]
+fn wait_with_output_tail() {
+    let child = Command::new("sh")
+        .arg("-c")
+        .arg("seq 1 100000; echo err >&2; echo done >&2")
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn_async()
+        .unwrap();
+
+    let output = support::run_with_timeout(child.wait_with_output_tail(12)).unwrap();
+
+    // The child ran to completion rather than being killed
+    assert!(output.status.success());
+    assert_eq!(output.stdout, b"9999\n100000\n");
+    assert_eq!(output.stderr, b"err\ndone\n");
+}
+
+#[cfg(unix)]
+#[test]
+fn raw_fds() {
+    use std::os::unix::io::AsRawFd;
+
+    let mut child = cat().spawn_async().unwrap();
+
+    let stdin = child.stdin_raw_fd().expect("missing stdin");
+    let stdout = child.stdout_raw_fd().expect("missing stdout");
+    assert_eq!(child.stderr_raw_fd(), None);
+
+    assert_eq!(stdin, child.stdin().as_ref().unwrap().as_raw_fd());
+    assert_eq!(stdout, child.stdout().as_ref().unwrap().as_raw_fd());
+    assert_ne!(stdin, stdout);
+}
+
+#[test]
+fn wait_with_output_timeout_completes() {
+    let mut child = cat().spawn_async().unwrap();
+    let stdin = child.stdin().take().unwrap();
+    let out = child.wait_with_output_timeout(Duration::from_secs(2));
+
+    let future = write_all(stdin, b"1234").map(|p| p.1).join(out);
+    let (written, output) = support::run_with_timeout(future).unwrap();
+
+    let output = output.expect("timed out");
+    assert!(output.status.success());
+    assert_eq!(output.stdout, written);
+}
+
+#[test]
+fn wait_with_output_timeout_returns_partial_output() {
+    let mut child = cat().spawn_async().unwrap();
+    let stdin = child.stdin().take().unwrap();
+    let out = child.wait_with_output_timeout(Duration::from_millis(200));
+
+    // Keep stdin open so the child never exits on its own
+    let future = write_all(stdin, b"hello\n").join(out);
+    let ((_stdin, _), output) = support::run_with_timeout(future).unwrap();
+
+    let partial = output.expect_err("child should not have exited");
+    assert_eq!(partial.status, None);
+    assert_eq!(partial.stdout, b"hello\n");
+    assert!(partial.stderr.is_empty());
+}
+
+#[test]
+fn output_async_timeout_completes() {
+    let mut cmd = support::cmd("cat");
+    cmd.stdin(Stdio::piped());
+
+    let output = support::run_with_timeout(cmd.output_async_timeout(Duration::from_secs(2)))
+        .expect("timed out");
+    assert!(output.status.success());
+    assert!(output.stdout.is_empty());
+}
+
+#[test]
+#[cfg(unix)]
+fn output_async_timeout_kills_child() {
+    use std::os::unix::process::ExitStatusExt;
+    use tokio_process::TimeoutError;
+
+    // The backgrounded `sleep` inherits stdout, so it is never closed
+    let mut cmd = Command::new("sh");
+    cmd.arg("-c").arg("echo hello; sleep 10 & wait");
+
+    let future = cmd.output_async_timeout(Duration::from_millis(500))
+        .grace_period(Duration::from_millis(500));
+    let partial = match support::run_with_timeout(future) {
+        Err(TimeoutError::Elapsed(partial)) => partial,
+        other => panic!("unexpected result: {:?}", other),
+    };
+
+    let status = partial.status.expect("child should have been reaped");
+    assert_eq!(status.signal(), Some(15)); // SIGTERM
+    assert_eq!(partial.stdout, b"hello\n");
+    assert!(partial.stderr.is_empty());
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn pipe_capacity() {
+    extern crate libc;
+
+    use tokio_process::SpawnHandle;
+
+    let mut spawner = SpawnHandle::new(Default::default());
+    spawner.pipe_capacity(1 << 20);
+
+    let mut child = spawner.spawn(&mut cat()).unwrap();
+
+    let fd = child.stdout_raw_fd().unwrap();
+    let capacity = unsafe { libc::fcntl(fd, libc::F_GETPIPE_SZ) };
+    assert!(capacity > 0, "failed to get pipe capacity");
+
+    // Unprivileged processes may be limited to a smaller capacity, in which
+    // case the default is kept
+    let max: i32 = std::fs::read_to_string("/proc/sys/fs/pipe-max-size")
+        .expect("failed to read max pipe size")
+        .trim()
+        .parse()
+        .expect("invalid max pipe size");
+    if max >= 1 << 20 {
+        assert_eq!(capacity, 1 << 20);
+    }
+
+    drop(child.stdin().take());
+    assert!(support::run_with_timeout(child).unwrap().success());
+}
+
+#[test]
+#[cfg(unix)]
+fn spawn_pty() {
+    let mut cmd = Command::new("sh");
+    cmd.arg("-c").arg("test -t 0 && test -t 1 && read line && stty size");
+
+    let (child, pty) = cmd.spawn_pty_async((24, 80)).unwrap();
+    pty.resize((30, 100)).unwrap();
+
+    let output = write_all(pty, b"go\n")
+        .and_then(|(pty, _)| read_to_end(pty, Vec::new()))
+        .map(|(_, output)| output);
+
+    let (status, output) = support::run_with_timeout(child.join(output)).unwrap();
+    assert!(status.success());
+
+    // The terminal echoes our input back before the child's output
+    let output = String::from_utf8(output).unwrap();
+    assert!(output.ends_with("30 100\r\n"), "unexpected output: {:?}", output);
+}
+
+#[test]
+#[cfg(unix)]
+fn spawn_pty_twice() {
+    // The second spawn runs the hooks installed by both
+    let mut cmd = Command::new("sh");
+    cmd.arg("-c").arg("test -t 0");
+
+    for _ in 0..2 {
+        let (child, pty) = cmd.spawn_pty_async((24, 80)).unwrap();
+        assert!(support::run_with_timeout(child).unwrap().success());
+        drop(pty);
+    }
+}
+
+#[test]
+#[cfg(unix)]
+fn spawn_pty_process_group_leader() {
+    // A process group leader can't create a session, so it can't acquire the
+    // pty as its controlling terminal either
+    let err = Command::new("true")
+        .new_process_group()
+        .spawn_pty_async((24, 80))
+        .unwrap_err();
+    assert_eq!(err.raw_os_error(), Some(1 /* EPERM */));
+}
@@ -0,0 +1,77 @@
+#![cfg(target_os = "linux")]
+
+extern crate futures;
+extern crate tokio;
+extern crate tokio_process;
+
+use std::fs;
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+use tokio_process::CommandExt;
+
+mod support;
+
+/// Returns whether `pid` is (still) a child of ours.
+fn is_child(pid: &str) -> bool {
+    match fs::read_to_string(format!("/proc/{}/stat", pid)) {
+        Ok(stat) => {
+            let ppid = stat.rsplit(')').next().unwrap().split_whitespace().nth(1).unwrap();
+            ppid == tokio_process::current_pid().to_string()
+        },
+        Err(_) => false,
+    }
+}
+
+#[test]
+fn reap_reparented_children() {
+    tokio_process::reap_reparented_children().expect("failed to become a subreaper");
+
+    // The grandchild outlives the shell which spawned it
+    let output = support::run_with_timeout(Command::new("sh")
+        .arg("-c")
+        .arg("sleep 0.2 & echo $!")
+        .stdout(Stdio::piped())
+        .spawn_async()
+        .unwrap()
+        .wait_with_output())
+        .expect("failed to run future");
+    assert!(output.status.success());
+
+    let grandchild = String::from_utf8(output.stdout).unwrap();
+    let grandchild = grandchild.trim();
+    assert!(is_child(grandchild));
+
+    // A tracked child which exits meanwhile mustn't be reaped along with it
+    let mut cmd = support::cmd("exit");
+    cmd.arg("3");
+    let child = cmd.spawn_async().unwrap();
+
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while Path::new(&format!("/proc/{}", grandchild)).exists() {
+        assert!(Instant::now() < deadline, "reparented child {} was not reaped", grandchild);
+        tokio_process::reap_orphans_now(Duration::from_millis(0));
+        thread::sleep(Duration::from_millis(10));
+    }
+
+    let status = support::run_with_timeout(child).expect("failed to run future");
+    assert_eq!(status.code(), Some(3));
+}
+
+#[test]
+fn adopted_children_are_not_reaped() {
+    tokio_process::reap_reparented_children().expect("failed to become a subreaper");
+
+    // Only exits once adopted
+    let child = Command::new("sh").arg("-c").arg("sleep 0.1; exit 4").spawn().unwrap();
+    let mut child = tokio_process::Child::from_std(child, &tokio::reactor::Handle::default()).unwrap();
+    let pid = child.current_id().unwrap().to_string();
+
+    thread::sleep(Duration::from_millis(200));
+    tokio_process::reap_orphans_now(Duration::from_millis(0));
+    assert!(Path::new(&format!("/proc/{}", pid)).exists());
+
+    let status = support::run_with_timeout(&mut child).expect("failed to run future");
+    assert_eq!(status.code(), Some(4));
+}